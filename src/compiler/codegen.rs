@@ -0,0 +1,326 @@
+use crate::compiler::syntax::{Ast, BinOp, Cmp, Cond, Expr, Name, Stmt, StmtKind};
+use crate::compiler::Source;
+use crate::parse::diagnostic::Diagnostic;
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+// Код начинается после ячеек прерываний
+const START: u16 = 0x10;
+
+// Имена в .mm: v_ - переменные, a_ - адреса массивов, p_ - процедуры,
+// c_ - константы, t_ - временные ячейки, L - метки переходов
+pub struct Codegen<'a> {
+    source: &'a Source<'a>,
+    // Строка .mm и строка исходника, из которой она получилась
+    output: Vec<(String, u32)>,
+    line: u32,
+    // Последняя строка исходника, которую записали комментарием
+    commented: u32,
+    labels: usize,
+    constants: BTreeSet<u16>,
+    depth: usize,
+    temps: usize,
+    vars: HashMap<&'a str, Option<u16>>,
+    procs: HashSet<&'a str>,
+    current_proc: Option<&'a str>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Codegen<'a> {
+    pub fn new(source: &'a Source<'a>) -> Codegen<'a> {
+        Codegen {
+            source,
+            output: Vec::new(),
+            line: 0,
+            commented: 0,
+            labels: 0,
+            constants: BTreeSet::new(),
+            depth: 0,
+            temps: 0,
+            vars: HashMap::new(),
+            procs: HashSet::new(),
+            current_proc: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn generate(mut self, ast: &'a Ast) -> Result<Vec<(String, u32)>, Vec<Diagnostic>> {
+        for var in &ast.vars {
+            if self.vars.insert(&var.name.text, var.size).is_some() {
+                self.error(
+                    &var.name,
+                    format!("Переменная {} уже объявлена", var.name.text),
+                );
+            }
+        }
+        for proc in &ast.procs {
+            if !self.procs.insert(&proc.name.text) {
+                self.error(
+                    &proc.name,
+                    format!("Процедура {} уже объявлена", proc.name.text),
+                );
+            }
+        }
+
+        self.emit(format!("$pos {:03X}", START));
+        self.stmts(&ast.main);
+        self.line = 0;
+        self.emit("HLT");
+
+        // JSR кладет адрес возврата в первую ячейку процедуры, а выполнение идет со второй
+        for proc in &ast.procs {
+            self.current_proc = Some(&proc.name.text);
+            self.line = proc.name.line;
+            self.emit("");
+            self.comment();
+            self.emit(format!("$word 0 $p_{}", proc.name.text));
+            self.stmts(&proc.body);
+            self.line = proc.name.line;
+            self.emit(format!("BR (%p_{})", proc.name.text));
+        }
+        self.current_proc = None;
+
+        self.line = 0;
+        self.emit("");
+        for var in &ast.vars {
+            self.line = var.name.line;
+            let name = &var.name.text;
+            match var.size {
+                Some(size) => {
+                    self.emit(format!("$res {:X} $v_{}", size, name));
+                    self.emit(format!("$word %v_{} $a_{}", name, name));
+                }
+                None => self.emit(format!("$word {} $v_{}", var.value, name)),
+            }
+        }
+        self.line = 0;
+        for value in std::mem::take(&mut self.constants) {
+            self.emit(format!("$word 0x{:X} $c_{:X}", value, value));
+        }
+        for i in 0..self.temps {
+            self.emit(format!("$word 0 $t_{}", i));
+        }
+
+        if self.diagnostics.is_empty() {
+            Ok(self.output)
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    fn error<S: Into<String>>(&mut self, name: &Name, message: S) {
+        let diagnostic = self.source.error(name.line, name.span.clone(), message);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn emit<S: Into<String>>(&mut self, text: S) {
+        self.output.push((text.into(), self.line));
+    }
+
+    // Перед кодом строки исходника пишем ее саму, чтобы было видно, во что она превратилась
+    fn comment(&mut self) {
+        if self.line == self.commented {
+            return;
+        }
+        self.commented = self.line;
+        let text = self.source.lines[self.line as usize - 1].trim();
+        self.emit(format!("# {}", text));
+    }
+
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("L{}", self.labels)
+    }
+
+    fn constant(&mut self, value: i64) -> String {
+        let value = value as u16;
+        self.constants.insert(value);
+        format!("%c_{:X}", value)
+    }
+
+    fn push_temp(&mut self) -> String {
+        let name = format!("t_{}", self.depth);
+        self.depth += 1;
+        self.temps = self.temps.max(self.depth);
+        name
+    }
+
+    fn pop_temp(&mut self) {
+        self.depth -= 1;
+    }
+
+    fn var(&mut self, name: &Name, indexed: bool) {
+        match (self.vars.get(name.text.as_str()), indexed) {
+            (None, _) => self.error(name, format!("Переменная {} не объявлена", name.text)),
+            (Some(Some(_)), false) => {
+                self.error(name, format!("{} - массив, нужен индекс", name.text))
+            }
+            (Some(None), true) => self.error(name, format!("{} - не массив", name.text)),
+            _ => {}
+        }
+    }
+
+    fn stmts(&mut self, stmts: &'a [Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &'a Stmt) {
+        self.line = stmt.line;
+        self.comment();
+        match &stmt.kind {
+            StmtKind::Assign(name, None, value) => {
+                self.var(name, false);
+                self.expr(value);
+                self.emit(format!("MOV %v_{}", name.text));
+            }
+            StmtKind::Assign(name, Some(index), value) => {
+                let address = self.address(name, index);
+                self.expr(value);
+                self.emit(format!("MOV (%{})", address));
+                self.pop_temp();
+            }
+            StmtKind::If(cond, then, otherwise) => {
+                let else_label = self.label();
+                self.cond(cond, &else_label);
+                self.stmts(then);
+                self.line = stmt.line;
+                if otherwise.is_empty() {
+                    self.emit(format!("$label {}", else_label));
+                } else {
+                    let end = self.label();
+                    self.emit(format!("BR %{}", end));
+                    self.emit(format!("$label {}", else_label));
+                    self.stmts(otherwise);
+                    self.line = stmt.line;
+                    self.emit(format!("$label {}", end));
+                }
+            }
+            StmtKind::While(cond, body) => {
+                let start = self.label();
+                let end = self.label();
+                self.emit(format!("$label {}", start));
+                self.cond(cond, &end);
+                self.stmts(body);
+                self.line = stmt.line;
+                self.emit(format!("BR %{}", start));
+                self.emit(format!("$label {}", end));
+            }
+            StmtKind::Call(name) => {
+                if !self.procs.contains(name.text.as_str()) {
+                    self.error(name, format!("Процедура {} не объявлена", name.text));
+                }
+                self.emit(format!("JSR %p_{}", name.text));
+            }
+            StmtKind::Return => match self.current_proc {
+                Some(proc) => self.emit(format!("BR (%p_{})", proc)),
+                None => self.emit("HLT"),
+            },
+            StmtKind::Halt => self.emit("HLT"),
+        }
+    }
+
+    // Переходит на false_label, если условие ложно. Сравнение - это знак разности.
+    fn cond(&mut self, cond: &'a Cond, false_label: &str) {
+        let (cmp, left, right) = match cond.cmp {
+            Cmp::Gt => (Cmp::Lt, &cond.right, &cond.left),
+            Cmp::Le => (Cmp::Ge, &cond.right, &cond.left),
+            cmp => (cmp, &cond.left, &cond.right),
+        };
+        if let Expr::Number(0) = right {
+            self.expr(left);
+        } else {
+            self.binary(BinOp::Sub, left, right);
+        }
+        match cmp {
+            Cmp::Eq => {
+                let body = self.label();
+                self.emit(format!("BEQ %{}", body));
+                self.emit(format!("BR %{}", false_label));
+                self.emit(format!("$label {}", body));
+            }
+            Cmp::Ne => self.emit(format!("BEQ %{}", false_label)),
+            Cmp::Lt => self.emit(format!("BPL %{}", false_label)),
+            _ => self.emit(format!("BMI %{}", false_label)),
+        }
+    }
+
+    // Адрес элемента массива во временной ячейке. Ее надо освободить через pop_temp
+    fn address(&mut self, name: &Name, index: &'a Expr) -> String {
+        self.var(name, true);
+        self.expr(index);
+        self.emit(format!("ADD %a_{}", name.text));
+        let temp = self.push_temp();
+        self.emit(format!("MOV %{}", temp));
+        temp
+    }
+
+    // Операнд, который можно сразу подставить в команду, без вычислений
+    fn operand(&mut self, e: &Expr) -> Option<String> {
+        match e {
+            Expr::Number(value) => Some(self.constant(*value)),
+            Expr::Var(name) => {
+                self.var(name, false);
+                Some(format!("%v_{}", name.text))
+            }
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, left: &'a Expr, right: &'a Expr) {
+        let mnemonic = match op {
+            BinOp::Add => "ADD",
+            BinOp::Sub => "SUB",
+            BinOp::And => "AND",
+        };
+        if let Some(operand) = self.operand(right) {
+            self.expr(left);
+            self.emit(format!("{} {}", mnemonic, operand));
+            return;
+        }
+        // Правую часть считаем первой и откладываем, левая останется в А
+        self.expr(right);
+        let temp = self.push_temp();
+        self.emit(format!("MOV %{}", temp));
+        self.expr(left);
+        self.emit(format!("{} %{}", mnemonic, temp));
+        self.pop_temp();
+    }
+
+    // Значение выражения остается в регистре А
+    fn expr(&mut self, e: &'a Expr) {
+        match e {
+            Expr::Number(0) => self.emit("CLA"),
+            Expr::Number(_) | Expr::Var(_) => {
+                let operand = self.operand(e).unwrap();
+                self.emit("CLA");
+                self.emit(format!("ADD {}", operand));
+            }
+            Expr::Index(name, index) => {
+                let address = self.address(name, index);
+                self.emit("CLA");
+                self.emit(format!("ADD (%{})", address));
+                self.pop_temp();
+            }
+            Expr::Not(e) => {
+                self.expr(e);
+                self.emit("CMA");
+            }
+            Expr::Neg(e) => {
+                self.expr(e);
+                self.emit("CMA");
+                self.emit("INC");
+            }
+            Expr::Binary(op, left, right) => self.binary(*op, left, right),
+            // ROL и ROR крутят через C, поэтому перед каждым сдвигом C обнуляется
+            Expr::Shift(to_left, e, count) => {
+                self.expr(e);
+                for _ in 0..*count {
+                    self.emit("CLC");
+                    self.emit(if *to_left { "ROL" } else { "ROR" });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::compiler::codegen::Codegen;
+use crate::parse::diagnostic::Diagnostic;
+use crate::parse::file::{parse_file, Program};
+use crate::parse::general::GeneralParser;
+use crate::parse::include::Location;
+use crate::profile::MachineProfile;
+
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+mod codegen;
+mod syntax;
+
+// Исходник программы на языке высокого уровня
+pub struct Source<'a> {
+    name: Option<Rc<str>>,
+    lines: Vec<&'a str>,
+}
+
+impl Source<'_> {
+    fn error<S: Into<String>>(&self, line: u32, span: Range<usize>, message: S) -> Diagnostic {
+        let text = self.lines.get(line as usize - 1).copied().unwrap_or("");
+        let location = Location {
+            file: self.name.clone(),
+            line,
+        };
+        Diagnostic::error(location, text.into(), span, message)
+    }
+}
+
+// Результат компиляции: текст .mm и карта строк
+#[derive(Debug)]
+pub struct Compiled {
+    pub assembly: String,
+    // Для каждой строки assembly - строка исходника, из которой она получилась. 0 - служебная
+    pub lines: Vec<u32>,
+}
+
+// Ячейка собранной программы и строки, из которых она получилась
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mapped {
+    pub pos: u16,
+    pub assembly_line: u32,
+    pub line: u32,
+}
+
+pub fn compile(text: &str, name: Option<Rc<str>>) -> Result<Compiled, Vec<Diagnostic>> {
+    let source = Source {
+        name,
+        lines: text.lines().collect(),
+    };
+    let ast = syntax::parse(&source).map_err(|e| vec![e])?;
+    let output = Codegen::new(&source).generate(&ast)?;
+
+    let mut assembly = String::new();
+    for (line, _) in &output {
+        assembly.push_str(line);
+        assembly.push('\n');
+    }
+    Ok(Compiled {
+        assembly,
+        lines: output.into_iter().map(|(_, line)| line).collect(),
+    })
+}
+
+impl Compiled {
+    // Собирает .mm так же, как загрузка файла
+    pub fn assemble(
+        &self,
+        parser: &GeneralParser,
+        max_size: u16,
+    ) -> Result<Program, Vec<Diagnostic>> {
+        parse_file(&mut self.assembly.as_bytes(), None, parser, max_size)
+    }
+
+    // Карта исходника: какие ячейки получились из какой строки
+    pub fn map(&self, program: &Program) -> Vec<Mapped> {
+        program
+            .cells
+            .iter()
+            .filter(|cell| cell.origin.location.file.is_none())
+            .map(|cell| {
+                let assembly_line = cell.origin.location.line;
+                Mapped {
+                    pos: cell.pos,
+                    assembly_line,
+                    line: self
+                        .lines
+                        .get(assembly_line as usize - 1)
+                        .copied()
+                        .unwrap_or(0),
+                }
+            })
+            .collect()
+    }
+}
+
+// Текстовая карта: адрес ячейки и строка исходника
+pub fn write_map(map: &[Mapped]) -> String {
+    let mut result = String::from("# ячейка строка\n");
+    for mapped in map.iter().filter(|mapped| mapped.line != 0) {
+        result.push_str(&format!("{:03X} {}\n", mapped.pos, mapped.line));
+    }
+    result
+}
+
+// bevm compile файл.bl: рядом появятся файл.mm и файл.map
+pub fn run(args: &[String], profile: MachineProfile) -> Result<(), String> {
+    let [file] = args else {
+        return Err("Использование: bevm compile ФАЙЛ.bl".to_string());
+    };
+    let path = Path::new(file);
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Не удалось прочитать {}: {}", file, e))?;
+    let name = path
+        .file_name()
+        .map(|name| Rc::from(name.to_string_lossy().as_ref()));
+
+    let report = |diagnostics: Vec<Diagnostic>| {
+        for diagnostic in diagnostics {
+            eprintln!("{}\n", diagnostic);
+        }
+    };
+    let compiled = compile(&text, name).map_err(|diagnostics| {
+        report(diagnostics);
+        "Программа не скомпилирована".to_string()
+    })?;
+    let config = profile.default_config();
+    let parser = profile.parser(&config);
+    let program = compiled
+        .assemble(&parser, config.address_mask())
+        .map_err(|diagnostics| {
+            report(diagnostics);
+            "Сгенерированный код не собирается".to_string()
+        })?;
+
+    let write = |extension: &str, text: &str| {
+        let target = path.with_extension(extension);
+        std::fs::write(&target, text)
+            .map_err(|e| format!("Не удалось записать {}: {}", target.display(), e))
+    };
+    write("mm", &compiled.assembly)?;
+    write("map", &write_map(&compiled.map(&program)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::compile;
+    use crate::model::Computer;
+    use crate::parse::general::GeneralParser;
+    use crate::parse::mc::ExecutionResult;
+    use crate::profile::MachineProfile;
+
+    #[test]
+    fn compiles_and_runs() {
+        let source = "var n = 5;
+var sum;
+var i;
+var a[5];
+var flags;
+
+proc fill {
+    i = 0;
+    while i < n {
+        a[i] = (i << 1) + 1; // нечетные числа
+        i = i + 1;
+    }
+}
+
+call fill;
+i = n - 1;
+while i >= 0 {
+    sum = sum + a[i];
+    i = i - 1;
+}
+if sum == 25 {
+    flags = ~0 & 0x0F0;
+} else {
+    flags = -1;
+}
+";
+        let compiled = compile(source, None).unwrap();
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = compiled.assemble(&parser, 0x7FF).unwrap();
+
+        let mut computer = Computer::with_profile(MachineProfile::Classic);
+        program.load_into(&mut computer.general_memory.borrow_mut().data);
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.set_lever(true);
+        let steps = (0..2000)
+            .position(|_| computer.fast_step() == ExecutionResult::Halted)
+            .unwrap();
+        assert!(steps > 50);
+
+        let value = |name: &str| {
+            let label = program.labels.iter().find(|l| l.name == name).unwrap();
+            computer.general_memory.borrow().data[label.pos as usize].get()
+        };
+        assert_eq!(value("v_sum"), 25);
+        assert_eq!(value("v_flags"), 0x0F0);
+
+        // Строка "sum = sum + a[i];" собралась в ячейки, и карта это знает
+        let map = compiled.map(&program);
+        assert!(map.iter().any(|mapped| mapped.line == 18));
+        assert!(map.iter().all(|mapped| mapped.line != 6));
+
+        let errors = compile("var x;\ny = x + z[1];\ncall nowhere;\n", None).unwrap_err();
+        let lines = errors.iter().map(|e| e.location.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![2, 2, 3]);
+        assert!(compile("if x { x = 1;", None).is_err());
+    }
+}
@@ -0,0 +1,451 @@
+use crate::compiler::Source;
+use crate::parse::diagnostic::Diagnostic;
+
+use std::ops::Range;
+
+// Сначала двухсимвольные, чтобы << не разобралось как два <
+const SYMBOLS: [&str; 20] = [
+    "==", "!=", "<=", ">=", "<<", ">>", "{", "}", "(", ")", "[", "]", ";", "=", "<", ">", "+", "-",
+    "&", "~",
+];
+
+const KEYWORDS: [&str; 8] = [
+    "var", "proc", "if", "else", "while", "call", "return", "halt",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(i64),
+    Symbol(&'static str),
+}
+
+struct Spanned {
+    token: Token,
+    line: u32,
+    span: Range<usize>,
+}
+
+// Имя переменной или процедуры и где оно написано
+#[derive(Clone, Debug)]
+pub struct Name {
+    pub text: String,
+    pub line: u32,
+    pub span: Range<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    And,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug)]
+pub enum Expr {
+    Number(i64),
+    Var(Name),
+    Index(Name, Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    // Сдвиг на константу: true - влево
+    Shift(bool, Box<Expr>, u32),
+}
+
+#[derive(Debug)]
+pub struct Cond {
+    pub cmp: Cmp,
+    pub left: Expr,
+    pub right: Expr,
+}
+
+#[derive(Debug)]
+pub enum StmtKind {
+    Assign(Name, Option<Expr>, Expr),
+    If(Cond, Vec<Stmt>, Vec<Stmt>),
+    While(Cond, Vec<Stmt>),
+    Call(Name),
+    Return,
+    Halt,
+}
+
+#[derive(Debug)]
+pub struct Stmt {
+    pub line: u32,
+    pub kind: StmtKind,
+}
+
+#[derive(Debug)]
+pub struct Var {
+    pub name: Name,
+    // Размер массива. У простой переменной его нет
+    pub size: Option<u16>,
+    pub value: i64,
+}
+
+#[derive(Debug)]
+pub struct Proc {
+    pub name: Name,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Default)]
+pub struct Ast {
+    pub vars: Vec<Var>,
+    pub procs: Vec<Proc>,
+    pub main: Vec<Stmt>,
+}
+
+fn tokenize(source: &Source) -> Result<Vec<Spanned>, Diagnostic> {
+    let mut result = Vec::new();
+    for (text, line) in source.lines.iter().zip(1..) {
+        let mut i = 0;
+        while i < text.len() {
+            let rest = &text[i..];
+            let c = rest.chars().next().unwrap();
+            if c.is_whitespace() {
+                i += c.len_utf8();
+                continue;
+            }
+            if rest.starts_with("//") {
+                break;
+            }
+
+            let (token, len) = if c.is_ascii_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (Token::Ident(rest[..len].to_string()), len)
+            } else if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                let value = match word.strip_prefix("0x") {
+                    Some(hex) => i64::from_str_radix(hex, 16),
+                    None => word.parse::<i64>(),
+                };
+                match value {
+                    Ok(value) if (-0x8000..=0xFFFF).contains(&value) => {}
+                    _ => {
+                        return Err(source.error(
+                            line,
+                            i..i + len,
+                            format!("{} - не 16-битное число", word),
+                        ))
+                    }
+                }
+                (Token::Number(value.unwrap()), len)
+            } else {
+                match SYMBOLS.iter().find(|symbol| rest.starts_with(*symbol)) {
+                    Some(symbol) => (Token::Symbol(symbol), symbol.len()),
+                    None => {
+                        return Err(source.error(
+                            line,
+                            i..i + c.len_utf8(),
+                            format!("Непонятный символ {}", c),
+                        ))
+                    }
+                }
+            };
+            result.push(Spanned {
+                token,
+                line,
+                span: i..i + len,
+            });
+            i += len;
+        }
+    }
+    Ok(result)
+}
+
+pub fn parse(source: &Source) -> Result<Ast, Diagnostic> {
+    let tokens = tokenize(source)?;
+    let mut parser = AstParser {
+        source,
+        tokens,
+        pos: 0,
+    };
+    let mut ast = Ast::default();
+    while parser.pos < parser.tokens.len() {
+        if parser.eat("proc") {
+            let name = parser.name()?;
+            let body = parser.block(&mut ast)?;
+            ast.procs.push(Proc { name, body });
+        } else if let Some(stmt) = parser.stmt(&mut ast)? {
+            ast.main.push(stmt);
+        }
+    }
+    Ok(ast)
+}
+
+struct AstParser<'a> {
+    source: &'a Source<'a>,
+    tokens: Vec<Spanned>,
+    pos: usize,
+}
+
+impl AstParser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn is(&self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) => *s == symbol,
+            Some(Token::Ident(word)) => word == symbol,
+            _ => false,
+        }
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = self.is(symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    // Ошибка на текущем токене. В конце файла - на последнем
+    fn error<S: Into<String>>(&self, message: S) -> Diagnostic {
+        let (line, span) = match self.tokens.get(self.pos) {
+            Some(t) => (t.line, t.span.clone()),
+            None => match self.tokens.last() {
+                Some(t) => (t.line, t.span.end..t.span.end),
+                None => (1, 0..0),
+            },
+        };
+        self.source.error(line, span, message)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), Diagnostic> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("Ожидалось {}", symbol)))
+        }
+    }
+
+    fn line(&self) -> u32 {
+        self.tokens.get(self.pos).map_or(0, |t| t.line)
+    }
+
+    fn name(&mut self) -> Result<Name, Diagnostic> {
+        match self.tokens.get(self.pos) {
+            Some(Spanned {
+                token: Token::Ident(text),
+                line,
+                span,
+            }) if !KEYWORDS.contains(&text.as_str()) => {
+                let name = Name {
+                    text: text.clone(),
+                    line: *line,
+                    span: span.clone(),
+                };
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("Ожидалось имя")),
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, Diagnostic> {
+        let negative = self.eat("-");
+        match self.peek() {
+            Some(Token::Number(value)) => {
+                let value = *value;
+                self.pos += 1;
+                Ok(if negative { -value } else { value })
+            }
+            _ => Err(self.error("Ожидалось число")),
+        }
+    }
+
+    fn block(&mut self, ast: &mut Ast) -> Result<Vec<Stmt>, Diagnostic> {
+        self.expect("{")?;
+        let mut result = Vec::new();
+        while !self.eat("}") {
+            if self.pos >= self.tokens.len() {
+                return Err(self.error("Блок не закрыт через }"));
+            }
+            if let Some(stmt) = self.stmt(ast)? {
+                result.push(stmt);
+            }
+        }
+        Ok(result)
+    }
+
+    // Объявления переменных собираются отдельно: все переменные глобальные
+    fn stmt(&mut self, ast: &mut Ast) -> Result<Option<Stmt>, Diagnostic> {
+        let line = self.line();
+        let kind = if self.eat("var") {
+            let name = self.name()?;
+            let size = if self.eat("[") {
+                let size = self.number()?;
+                if !(1..=0x7FF).contains(&size) {
+                    return Err(self.error("Размер массива должен быть от 1 до 7FF"));
+                }
+                self.expect("]")?;
+                Some(size as u16)
+            } else {
+                None
+            };
+            let value = if size.is_none() && self.eat("=") {
+                self.number()?
+            } else {
+                0
+            };
+            self.expect(";")?;
+            ast.vars.push(Var { name, size, value });
+            return Ok(None);
+        } else if self.eat("if") {
+            let cond = self.cond()?;
+            let then = self.block(ast)?;
+            let otherwise = if !self.eat("else") {
+                Vec::new()
+            } else if self.is("if") {
+                // else if - это else с одним if внутри
+                self.stmt(ast)?.into_iter().collect()
+            } else {
+                self.block(ast)?
+            };
+            StmtKind::If(cond, then, otherwise)
+        } else if self.eat("while") {
+            let cond = self.cond()?;
+            StmtKind::While(cond, self.block(ast)?)
+        } else if self.eat("call") {
+            let name = self.name()?;
+            self.expect(";")?;
+            StmtKind::Call(name)
+        } else if self.eat("return") {
+            self.expect(";")?;
+            StmtKind::Return
+        } else if self.eat("halt") {
+            self.expect(";")?;
+            StmtKind::Halt
+        } else {
+            let name = self.name()?;
+            let index = if self.eat("[") {
+                let index = self.expr()?;
+                self.expect("]")?;
+                Some(index)
+            } else {
+                None
+            };
+            self.expect("=")?;
+            let value = self.expr()?;
+            self.expect(";")?;
+            StmtKind::Assign(name, index, value)
+        };
+        Ok(Some(Stmt { line, kind }))
+    }
+
+    fn cond(&mut self) -> Result<Cond, Diagnostic> {
+        let left = self.expr()?;
+        let cmp = [
+            ("==", Cmp::Eq),
+            ("!=", Cmp::Ne),
+            ("<=", Cmp::Le),
+            (">=", Cmp::Ge),
+            ("<", Cmp::Lt),
+            (">", Cmp::Gt),
+        ]
+        .iter()
+        .find(|(symbol, _)| self.is(symbol))
+        .map(|(_, cmp)| *cmp);
+        // Просто выражение - это проверка на неравенство нулю
+        let Some(cmp) = cmp else {
+            return Ok(Cond {
+                cmp: Cmp::Ne,
+                left,
+                right: Expr::Number(0),
+            });
+        };
+        self.pos += 1;
+        let right = self.expr()?;
+        Ok(Cond { cmp, left, right })
+    }
+
+    // Приоритеты как в C: & слабее сдвигов, сдвиги слабее + и -
+    fn expr(&mut self) -> Result<Expr, Diagnostic> {
+        let mut left = self.shift()?;
+        while self.eat("&") {
+            let right = self.shift()?;
+            left = Expr::Binary(BinOp::And, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn shift(&mut self) -> Result<Expr, Diagnostic> {
+        let mut left = self.sum()?;
+        loop {
+            let to_left = if self.eat("<<") {
+                true
+            } else if self.eat(">>") {
+                false
+            } else {
+                return Ok(left);
+            };
+            let count = self.number()?;
+            if !(0..16).contains(&count) {
+                return Err(self.error("Сдвигать можно на число от 0 до 15"));
+            }
+            left = Expr::Shift(to_left, Box::new(left), count as u32);
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, Diagnostic> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat("+") {
+                BinOp::Add
+            } else if self.eat("-") {
+                BinOp::Sub
+            } else {
+                return Ok(left);
+            };
+            let right = self.unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, Diagnostic> {
+        if self.eat("~") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(match self.unary()? {
+                Expr::Number(value) => Expr::Number(-value),
+                e => Expr::Neg(Box::new(e)),
+            });
+        }
+        if self.eat("(") {
+            let e = self.expr()?;
+            self.expect(")")?;
+            return Ok(e);
+        }
+        if let Some(Token::Number(value)) = self.peek() {
+            let value = *value;
+            self.pos += 1;
+            return Ok(Expr::Number(value));
+        }
+        let name = self.name()?;
+        if self.eat("[") {
+            let index = self.expr()?;
+            self.expect("]")?;
+            return Ok(Expr::Index(name, Box::new(index)));
+        }
+        Ok(Expr::Var(name))
+    }
+}
@@ -0,0 +1,60 @@
+use crate::parse::format::format_file;
+use crate::profile::MachineProfile;
+
+use std::io::ErrorKind;
+use std::path::Path;
+
+// bevm fmt [--write] файл.mm ...
+// Без --write результат печатается в stdout, с ним файлы переписываются на месте.
+pub fn run(args: &[String], profile: MachineProfile) -> Result<(), String> {
+    let write = args.iter().any(|arg| arg == "--write" || arg == "-w");
+    let files = args
+        .iter()
+        .filter(|arg| !arg.starts_with('-'))
+        .collect::<Vec<&String>>();
+    if files.is_empty() {
+        return Err("Использование: bevm fmt [--write] ФАЙЛ.mm...".to_string());
+    }
+
+    let config = profile.default_config();
+    let parser = profile.parser(&config);
+    let mut failed = false;
+    for file in files {
+        let path = Path::new(file);
+        // Файл не в UTF-8 (например, CP1251) не трогаем: иначе комментарии превратятся в �
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                eprintln!("{} не в кодировке UTF-8, файл оставлен как есть", file);
+                failed = true;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Не удалось прочитать {}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+        match format_file(&text, Some(path), &parser, config.address_mask()) {
+            Ok(formatted) if write => {
+                if formatted != text {
+                    std::fs::write(path, formatted)
+                        .map_err(|e| format!("Не удалось записать {}: {}", file, e))?;
+                }
+            }
+            Ok(formatted) => print!("{}", formatted),
+            Err(diagnostics) => {
+                for diagnostic in diagnostics {
+                    eprintln!("{}\n", diagnostic);
+                }
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        Err("Не все файлы удалось отформатировать".to_string())
+    } else {
+        Ok(())
+    }
+}
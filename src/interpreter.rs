@@ -0,0 +1,675 @@
+use crate::model::Computer;
+use crate::parse::mc::ExecutionResult;
+use crate::profile::ADDRESS_FIELD_MASK;
+use crate::utils::bit_registers::bit_at;
+use core::ops::{BitAnd, BitOr, BitXor, Shl, Shr};
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum ExecutionMode {
+    Microcode,
+    Fast,
+}
+
+impl ExecutionMode {
+    pub fn title(&self) -> &'static str {
+        match self {
+            ExecutionMode::Microcode => "Точный (по микрокомандам)",
+            ExecutionMode::Fast => "Быстрый (по командам)",
+        }
+    }
+}
+
+// Адреса в стандартной микропрограмме
+pub const FETCH_ADDRESS: u8 = 0x01;
+pub const HALT_ADDRESS: u8 = 0x88;
+pub const INTERRUPT_ADDRESS: u8 = 0x91;
+
+const MAX_MICRO_STEPS: usize = 0x100;
+
+enum Flow {
+    // Дальше проверка прерывания и состояния тумблера
+    Next,
+    // Сразу к выборке следующей команды. Так заканчиваются EI и DI
+    Fetch,
+    Halt,
+}
+
+// Исполняет команды основной памяти напрямую, без микрокоманд.
+// Поведение повторяет стандартную микропрограмму: те же регистры, флаги и ячейки
+// после каждой команды. Все что происходит между командами (пуск, прерывание, останов)
+// по-прежнему исполняется микрокомандами.
+impl Computer {
+    pub fn fast_run(&mut self, limit: usize) -> ExecutionResult {
+        for _ in 0..limit {
+            if self.fast_step() == ExecutionResult::Halted {
+                return ExecutionResult::Halted;
+            }
+        }
+        ExecutionResult::Success
+    }
+
+    pub fn fast_step(&mut self) -> ExecutionResult {
+        if !self.profile.has_microcode() {
+            return self.ng_step();
+        }
+
+        if self.run_micro_until(FETCH_ADDRESS) == ExecutionResult::Halted {
+            return ExecutionResult::Halted;
+        }
+
+        // После EI и DI микропрограмма сразу выбирает следующую команду
+        let mut flow = self.execute_command();
+        while let Flow::Fetch = flow {
+            flow = self.execute_command();
+        }
+
+        match flow {
+            Flow::Halt => self.halt(),
+            _ => {
+                if self.registers.get_interupt() {
+                    self.registers.r_micro_command_counter = INTERRUPT_ADDRESS;
+                    self.run_micro_until(FETCH_ADDRESS)
+                } else if !self.registers.get_lever() {
+                    self.halt()
+                } else {
+                    ExecutionResult::Success
+                }
+            }
+        }
+    }
+
+    // Следующая микрокоманда начнет выборку команды по адресу из СК
+    pub fn at_command_start(&self) -> bool {
+        !self.profile.has_microcode() || self.registers.r_micro_command_counter == FETCH_ADDRESS
+    }
+
+    fn run_micro_until(&mut self, address: u8) -> ExecutionResult {
+        for _ in 0..MAX_MICRO_STEPS {
+            if self.registers.r_micro_command_counter == address {
+                break;
+            }
+            if self.micro_step() == ExecutionResult::Halted {
+                return ExecutionResult::Halted;
+            }
+        }
+        self.registers.r_micro_command_counter = address;
+        ExecutionResult::Success
+    }
+
+    fn halt(&mut self) -> ExecutionResult {
+        self.registers.r_micro_command_counter = HALT_ADDRESS;
+        self.micro_step()
+    }
+
+    fn read(&self, address: u16) -> u16 {
+        self.general_memory
+            .borrow()
+            .data
+            .get(address.bitand(self.config.address_mask()) as usize)
+            .unwrap()
+            .get()
+    }
+
+    fn write(&mut self, address: u16, value: u16) {
+        self.general_memory
+            .borrow_mut()
+            .data
+            .get_mut(address.bitand(self.config.address_mask()) as usize)
+            .unwrap()
+            .set(value);
+    }
+
+    fn execute_command(&mut self) -> Flow {
+        let counter = self.registers.r_command_counter;
+        let command = self.read(counter);
+
+        let mask = self.config.address_mask();
+        self.registers.r_address = counter.bitand(mask);
+        self.registers.r_command_counter = counter.wrapping_add(1).bitand(mask);
+        self.registers.r_data = command;
+        self.registers.r_command = command;
+        self.registers.r_buffer = command as u32;
+
+        match command.shr(12u16) {
+            0xF => self.execute_address_less(command),
+            0xE => {
+                self.registers.set_io(true);
+                self.process_io_command();
+                Flow::Next
+            }
+            _ => self.execute_address(command),
+        }
+    }
+
+    fn execute_address(&mut self, command: u16) -> Flow {
+        let mask = self.config.address_mask();
+        if bit_at(command, 11) {
+            let pointer = command.bitand(mask);
+            self.registers.r_address = pointer;
+            self.registers.r_data = self.read(pointer);
+            self.registers.r_buffer = 0;
+
+            // Косвенная адресация через ячейки 008-00F увеличивает указатель
+            if (0x008..=0x00F).contains(&command.bitand(ADDRESS_FIELD_MASK)) {
+                let incremented = self.registers.r_data.wrapping_add(1);
+                self.write(pointer, incremented);
+                self.registers.r_buffer = 0xFFFF + incremented as u32;
+            }
+        }
+
+        let opcode = command.shr(12u16);
+        if opcode >= 0x8 {
+            return self.execute_branch(opcode);
+        }
+
+        let address = self.registers.r_data.bitand(mask);
+        self.registers.r_buffer = self.registers.r_data as u32;
+        self.registers.r_address = address;
+
+        match opcode {
+            // ISZ
+            0x0 => {
+                self.registers.r_data = self.read(address).wrapping_add(1);
+                self.write(address, self.registers.r_data);
+                self.registers.r_buffer = 0;
+                if !bit_at(self.registers.r_data, 15) {
+                    let counter = self.registers.r_command_counter as u32 + 1;
+                    self.registers.r_buffer = counter;
+                    self.registers.r_command_counter = (counter as u16).bitand(mask);
+                }
+            }
+            // AND
+            0x1 => {
+                self.registers.r_data = self.read(address);
+                let result = self.registers.r_counter.bitand(self.registers.r_data);
+                self.store_result(result as u32, false);
+            }
+            // JSR
+            0x2 => {
+                let target = self.registers.r_data as u32 + 1;
+                self.registers.r_command = target.bitand(0xFFFF) as u16;
+                self.registers.r_data = self.registers.r_command_counter;
+                self.write(address, self.registers.r_data);
+                self.registers.r_buffer = self.registers.r_command as u32;
+                self.registers.r_command_counter = self.registers.r_command.bitand(mask);
+            }
+            // MOV
+            0x3 => {
+                self.registers.r_data = self.registers.r_counter;
+                self.write(address, self.registers.r_data);
+                self.registers.r_buffer = 0;
+            }
+            _ => {
+                self.registers.r_data = self.read(address);
+                self.registers.r_buffer = 0;
+
+                let left = self.registers.r_counter as u32;
+                let right = self.registers.r_data as u32;
+                match opcode {
+                    // ADD
+                    0x4 => self.store_result(left + right, true),
+                    // ADC
+                    0x5 => {
+                        let carry = if self.registers.get_overflow() { 1 } else { 0 };
+                        self.store_result(left + right + carry, true)
+                    }
+                    // SUB
+                    0x6 => self.store_result(left + right.bitxor(0xFFFF) + 1, true),
+                    // HZA7
+                    _ => {}
+                }
+            }
+        }
+
+        Flow::Next
+    }
+
+    fn execute_branch(&mut self, opcode: u16) -> Flow {
+        let jump = match opcode {
+            // BCS
+            0x8 => self.registers.get_overflow(),
+            // BPL
+            0x9 => !self.registers.get_negative(),
+            // BMI
+            0xA => self.registers.get_negative(),
+            // BEQ
+            0xB => self.registers.get_null(),
+            // BR
+            0xC => true,
+            // HZAD
+            _ => {
+                self.registers.r_buffer = 0;
+                false
+            }
+        };
+
+        if jump {
+            self.registers.r_buffer = self.registers.r_data as u32;
+            self.registers.r_command_counter =
+                self.registers.r_data.bitand(self.config.address_mask());
+        }
+
+        Flow::Next
+    }
+
+    fn execute_address_less(&mut self, command: u16) -> Flow {
+        let counter = self.registers.r_counter as u32;
+
+        match command.shr(8u16).bitand(0xF) {
+            // HLT
+            0x0 => return Flow::Halt,
+            // NOP
+            0x1 => {}
+            // CLA
+            0x2 => self.store_result(0, false),
+            // CLC
+            0x3 => self.registers.set_overflow(false),
+            // CMA
+            0x4 => self.store_result(counter.bitxor(0xFFFF), false),
+            // CMC
+            0x5 => self.registers.set_overflow(!self.registers.get_overflow()),
+            // ROL
+            0x6 => {
+                let mut result = counter.shl(1u32);
+                if self.registers.get_overflow() {
+                    result = result.bitor(0x1);
+                }
+                self.store_result(result, true)
+            }
+            // ROR
+            0x7 => {
+                let mut result = counter.shr(1u32);
+                if self.registers.get_overflow() {
+                    result = result.bitor(0x8000);
+                }
+                if bit_at(counter as u16, 0) {
+                    result = result.bitor(0x10000);
+                }
+                self.store_result(result, true)
+            }
+            // INC
+            0x8 => self.store_result(counter + 1, true),
+            // DEC
+            0x9 => self.store_result(counter + 0xFFFF, true),
+            // EI
+            0xA => {
+                self.registers.set_allow_interrupt(true);
+                return Flow::Fetch;
+            }
+            // DI
+            0xB => {
+                self.registers.set_allow_interrupt(false);
+                self.registers.set_interrupt(false);
+                return Flow::Fetch;
+            }
+            // HZC, HZD, HZE, HZF
+            _ => self.registers.r_buffer = 0,
+        }
+
+        Flow::Next
+    }
+
+    fn store_result(&mut self, result: u32, update_carry: bool) {
+        let mut result = result;
+        if update_carry {
+            self.registers.set_overflow(result > 0xFFFF);
+            result = result.bitand(0xFFFF);
+        }
+
+        self.registers.r_buffer = result;
+        self.registers.set_null(result == 0);
+        self.registers.set_negative(bit_at(result as u16, 15));
+        self.registers.r_counter = result as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Computer;
+    use crate::parse::general::GeneralParser;
+    use crate::parse::mc::ExecutionResult;
+    use crate::parse::{CommandInfo, Parser};
+    use crate::profile::MachineProfile;
+
+    // Сумма массива из 5 элементов циклом через ISZ и автоинкремент
+    fn load_sum_program(computer: &mut Computer) {
+        let program = [
+            (0x008, 0x0020),
+            (0x010, 0xF200),
+            (0x011, 0x4808),
+            (0x012, 0x0030),
+            (0x013, 0xC011),
+            (0x014, 0x3031),
+            (0x015, 0xF000),
+            (0x020, 0x0001),
+            (0x021, 0x0002),
+            (0x022, 0x0003),
+            (0x023, 0x0004),
+            (0x024, 0x0005),
+            (0x030, 0xFFFB),
+        ];
+        let mut memory = computer.general_memory.borrow_mut();
+        for (address, value) in program {
+            memory.data[address].set(value);
+        }
+        drop(memory);
+        computer.registers.r_command_counter = 0x010;
+    }
+
+    #[test]
+    fn fast_matches_microcode() {
+        let mut micro = Computer::new();
+        let mut fast = Computer::new();
+        load_sum_program(&mut micro);
+        load_sum_program(&mut fast);
+
+        for _ in 0..0x40 {
+            micro.find(|res| res == &ExecutionResult::Halted);
+            fast.fast_step();
+
+            assert_eq!(micro.registers.r_counter, fast.registers.r_counter);
+            assert_eq!(micro.registers.r_status, fast.registers.r_status);
+            assert_eq!(
+                micro.registers.r_command_counter,
+                fast.registers.r_command_counter
+            );
+            assert_eq!(micro.registers.r_buffer, fast.registers.r_buffer);
+            assert_eq!(micro.registers.r_data, fast.registers.r_data);
+            assert_eq!(
+                micro.registers.r_micro_command_counter,
+                fast.registers.r_micro_command_counter
+            );
+        }
+
+        assert_eq!(fast.general_memory.borrow().data[0x31].get(), 15);
+    }
+
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: u64) -> u16 {
+            (self.next() % max) as u16
+        }
+    }
+
+    const PROGRAM_START: usize = 0x010;
+    const PROGRAM_END: usize = 0x030;
+    const DATA_END: usize = 0x040;
+    const ADDRESS_MASK: u16 = 0x7FF;
+
+    // Команды ссылаются только на первые ячейки памяти, чтобы чаще попадать в данные программы
+    fn random_command(random: &mut Random) -> u16 {
+        let opcode = random.below(0x10);
+        match opcode {
+            0xE => 0xE000 | random.below(0x1000),
+            0xF => 0xF000 | random.below(0x10) << 8,
+            _ => opcode << 12 | random.below(2) << 11 | random.below(DATA_END as u64),
+        }
+    }
+
+    fn random_computer(random: &mut Random) -> Computer {
+        let mut computer = Computer::new();
+        {
+            let mut memory = computer.general_memory.borrow_mut();
+            for cell in memory.data.iter_mut().take(DATA_END) {
+                cell.set(random.next() as u16);
+            }
+            for cell in memory.data[PROGRAM_START..PROGRAM_END].iter_mut() {
+                cell.set(random_command(random));
+            }
+        }
+        for device in computer.io_devices.iter_mut() {
+            device.data = random.next() as u8;
+            device.ready = random.below(2) == 1;
+        }
+        computer.registers.r_command_counter = PROGRAM_START as u16;
+        computer.registers.r_counter = random.next() as u16;
+        computer.registers.set_overflow(random.below(2) == 1);
+        computer.registers.set_negative(random.below(2) == 1);
+        computer.registers.set_null(random.below(2) == 1);
+        computer
+    }
+
+    #[derive(PartialEq, Debug)]
+    enum Step {
+        Next,
+        // EI и DI: следующая команда выполняется сразу, без остановки
+        Chained,
+        Halted,
+    }
+
+    // Эталон, написанный по описанию команд БЭВМ, а не по микропрограмме.
+    // Хранит только то, что видно программисту: память, ВУ, А, флаги и СК.
+    struct Isa {
+        memory: Vec<u16>,
+        devices: Vec<(u8, bool)>,
+        a: u16,
+        c: bool,
+        n: bool,
+        z: bool,
+        pc: u16,
+    }
+
+    impl Isa {
+        fn new(computer: &Computer) -> Isa {
+            let memory = computer.general_memory.borrow();
+            Isa {
+                memory: memory.data.iter().map(|cell| cell.get()).collect(),
+                devices: computer
+                    .io_devices
+                    .iter()
+                    .map(|device| (device.data, device.ready))
+                    .collect(),
+                a: computer.registers.r_counter,
+                c: computer.registers.get_overflow(),
+                n: computer.registers.get_negative(),
+                z: computer.registers.get_null(),
+                pc: computer.registers.r_command_counter,
+            }
+        }
+
+        fn registers(&self) -> (u16, bool, bool, bool, u16) {
+            (self.a, self.c, self.n, self.z, self.pc)
+        }
+
+        fn set_a(&mut self, value: u16) {
+            self.a = value;
+            self.n = value & 0x8000 != 0;
+            self.z = value == 0;
+        }
+
+        fn add(&mut self, left: u16, right: u16, carry: bool) {
+            let sum = left as u32 + right as u32 + carry as u32;
+            self.c = sum > 0xFFFF;
+            self.set_a(sum as u16);
+        }
+
+        fn step(&mut self) -> Step {
+            let command = self.memory[self.pc as usize];
+            self.pc = (self.pc + 1) & ADDRESS_MASK;
+            match command >> 12 {
+                0xE => self.io(command),
+                0xF => return self.address_less(command),
+                opcode => self.address(opcode, command),
+            }
+            Step::Next
+        }
+
+        fn address(&mut self, opcode: u16, command: u16) {
+            let mut address = command & ADDRESS_MASK;
+            if command & 0x800 != 0 {
+                // Косвенная: адрес лежит в ячейке. Ячейки 008-00F после чтения увеличиваются
+                let pointer = address as usize;
+                address = self.memory[pointer] & ADDRESS_MASK;
+                if (0x008..=0x00F).contains(&pointer) {
+                    self.memory[pointer] = self.memory[pointer].wrapping_add(1);
+                }
+            }
+            let cell = address as usize;
+            let value = self.memory[cell];
+            let jump = match opcode {
+                // ISZ: пропускает следующую команду, если результат не отрицательный
+                0x0 => {
+                    self.memory[cell] = value.wrapping_add(1);
+                    if self.memory[cell] & 0x8000 == 0 {
+                        self.pc = (self.pc + 1) & ADDRESS_MASK;
+                    }
+                    false
+                }
+                0x1 => {
+                    self.set_a(self.a & value);
+                    false
+                }
+                // JSR: адрес возврата в ячейку, переход на следующую за ней
+                0x2 => {
+                    self.memory[cell] = self.pc;
+                    self.pc = (address + 1) & ADDRESS_MASK;
+                    false
+                }
+                0x3 => {
+                    self.memory[cell] = self.a;
+                    false
+                }
+                0x4 => {
+                    self.add(self.a, value, false);
+                    false
+                }
+                0x5 => {
+                    self.add(self.a, value, self.c);
+                    false
+                }
+                0x6 => {
+                    self.add(self.a, !value, true);
+                    false
+                }
+                0x8 => self.c,
+                0x9 => !self.n,
+                0xA => self.n,
+                0xB => self.z,
+                0xC => true,
+                // 7 и D не заданы
+                _ => false,
+            };
+            if jump {
+                self.pc = address;
+            }
+        }
+
+        // CLF, TSF, IN, OUT. После любой из них Z показывает, равен ли А нулю
+        fn io(&mut self, command: u16) {
+            let (data, ready) = &mut self.devices[(command & 0xF) as usize];
+            match command >> 8 & 0x3 {
+                0x0 => *ready = false,
+                0x1 => {
+                    if *ready {
+                        self.pc = (self.pc + 1) & ADDRESS_MASK;
+                    }
+                }
+                0x2 => self.a = self.a & 0xFF00 | *data as u16,
+                _ => *data = self.a as u8,
+            }
+            self.z = self.a == 0;
+        }
+
+        fn address_less(&mut self, command: u16) -> Step {
+            let a = self.a;
+            match command >> 8 & 0xF {
+                0x0 => return Step::Halted,
+                0x2 => self.set_a(0),
+                0x3 => self.c = false,
+                0x4 => self.set_a(!a),
+                0x5 => self.c = !self.c,
+                0x6 => {
+                    self.set_a(a << 1 | self.c as u16);
+                    self.c = a & 0x8000 != 0;
+                }
+                0x7 => {
+                    self.set_a(a >> 1 | (self.c as u16) << 15);
+                    self.c = a & 0x1 != 0;
+                }
+                0x8 => self.add(a, 1, false),
+                0x9 => self.add(a, 0xFFFF, false),
+                // Разрешение прерываний здесь не сравнивается, прерывания ставит только интерфейс
+                0xA | 0xB => return Step::Chained,
+                // NOP и незаданные HZC-HZF
+                _ => {}
+            }
+            Step::Next
+        }
+    }
+
+    fn first_memory_difference(computer: &Computer, reference: &Isa) -> Option<usize> {
+        let memory = computer.general_memory.borrow();
+        memory
+            .data
+            .iter()
+            .zip(reference.memory.iter())
+            .position(|(cell, value)| cell.get() != *value)
+    }
+
+    // Сравнивает микропрограмму из mc.txt с эталоном по системе команд на случайных программах.
+    // После HLT программа продолжается, как после кнопки "Продолжение"
+    #[test]
+    fn microcode_matches_reference() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let mut random = Random(0x2545F4914F6CDD1D);
+        let mut halts = 0;
+
+        for program in 0..500 {
+            let mut micro = random_computer(&mut Random(random.next()));
+            let mut reference = Isa::new(&micro);
+
+            for step in 0..0x40 {
+                let counter = micro.registers.r_command_counter;
+                let command = micro.general_memory.borrow().data[counter as usize].get();
+
+                micro.find(|res| res == &ExecutionResult::Halted);
+                let mut result = reference.step();
+                while result == Step::Chained {
+                    result = reference.step();
+                }
+                if result == Step::Halted {
+                    halts += 1;
+                }
+
+                let context = format!(
+                    "программа {}, шаг {}, команда {:0>4X} {} по адресу {:0>3X}",
+                    program,
+                    step,
+                    command,
+                    parser.parse(command).mnemonic(),
+                    counter
+                );
+                assert!(!micro.registers.get_interupt(), "{}", context);
+                let registers = (
+                    micro.registers.r_counter,
+                    micro.registers.get_overflow(),
+                    micro.registers.get_negative(),
+                    micro.registers.get_null(),
+                    micro.registers.r_command_counter,
+                );
+                assert_eq!(registers, reference.registers(), "{}", context);
+                assert_eq!(
+                    first_memory_difference(&micro, &reference),
+                    None,
+                    "{}",
+                    context
+                );
+                let devices = micro
+                    .io_devices
+                    .iter()
+                    .map(|device| (device.data, device.ready))
+                    .collect::<Vec<_>>();
+                assert_eq!(devices, reference.devices, "{}", context);
+            }
+        }
+        assert!(halts > 0);
+    }
+}
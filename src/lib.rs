@@ -0,0 +1,50 @@
+// Без окна от эмулятора нужны только ассемблер и LSP, остальное не используется
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
+
+use crate::profile::MachineProfile;
+
+mod compiler;
+mod fmt;
+mod interpreter;
+pub mod lsp;
+mod model;
+mod ng;
+mod parse;
+mod profile;
+#[cfg(feature = "gui")]
+mod ui;
+mod utils;
+
+pub fn run() {
+    let mut profile = MachineProfile::Classic;
+    let mut rest = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            let name = args.next().unwrap_or_default();
+            match MachineProfile::from_name(&name) {
+                Some(p) => profile = p,
+                None => eprintln!("Неизвестный профиль {name}. Доступны: classic, ng"),
+            }
+        } else {
+            rest.push(arg);
+        }
+    }
+
+    let result = match rest.first().map(String::as_str) {
+        Some("fmt") => fmt::run(&rest[1..], profile),
+        Some("compile") => compiler::run(&rest[1..], profile),
+        #[cfg(feature = "gui")]
+        _ => {
+            let computer = model::Computer::with_profile(profile);
+            ui::gui::Gui::new(computer).run();
+            return;
+        }
+        #[cfg(not(feature = "gui"))]
+        _ => Err("Собрано без окна эмулятора, доступны только fmt и compile".to_string()),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
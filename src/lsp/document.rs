@@ -0,0 +1,470 @@
+use crate::lsp::path_uri;
+use crate::parse::diagnostic::{Diagnostic, Severity};
+use crate::parse::file::{parse_file, Program};
+use crate::parse::general::GeneralParser;
+use crate::parse::lexer::{is_word_char, tokenize, Token};
+use crate::parse::syntax::{highlight, Highlight};
+use crate::parse::{CommandInfo, Parser};
+
+use serde_json::{json, Value};
+use std::ops::Range;
+use std::path::PathBuf;
+
+const DIRECTIVES: [&str; 11] = [
+    "pos", "word", "string", "res", "fill", "label", "equ", "set", "include", "macro", "endm",
+];
+
+// Метка или константа, объявленная в файле
+pub struct Symbol {
+    pub name: String,
+    pub line: usize,
+    // Байты в строке, которые занимает имя
+    pub span: Range<usize>,
+    pub constant: bool,
+}
+
+// Открытый в редакторе файл и результат его сборки
+pub struct Document {
+    text: String,
+    path: Option<PathBuf>,
+    program: Option<Program>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Document {
+    pub fn new(
+        text: String,
+        path: Option<PathBuf>,
+        parser: &GeneralParser,
+        max_size: u16,
+    ) -> Document {
+        let (program, diagnostics) =
+            match parse_file(&mut text.as_bytes(), path.as_deref(), parser, max_size) {
+                Ok(mut program) => {
+                    let warnings = std::mem::take(&mut program.warnings);
+                    (Some(program), warnings)
+                }
+                Err(diagnostics) => (None, diagnostics),
+            };
+        Document {
+            text,
+            path,
+            program,
+            diagnostics,
+        }
+    }
+
+    fn line(&self, line: usize) -> &str {
+        self.text.lines().nth(line).unwrap_or("")
+    }
+
+    // Так ассемблер называет строки этого файла
+    fn file_name(&self) -> Option<String> {
+        let name = self.path.as_ref()?.file_name()?;
+        Some(name.to_string_lossy().to_string())
+    }
+
+    pub fn diagnostics(&self) -> Value {
+        let file = self.file_name();
+        let diagnostics = self
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let severity = match diagnostic.severity {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                };
+                let location = &diagnostic.location;
+                let line = location.line.saturating_sub(1) as usize;
+                let (range, message) = if location.file.as_deref() != file.as_deref() {
+                    // Ошибка в подключенном файле показывается на его $include
+                    let line = self.include_line(location.file.as_deref().unwrap_or(""));
+                    let text = self.line(line);
+                    let message = format!("Строка {}: {}", location, diagnostic.message);
+                    (range(line, text, 0..text.len()), message)
+                } else if diagnostic.source.as_ref() != self.line(line) {
+                    // Строка из подстановки макроса: ее столбцы к строке вызова не относятся,
+                    // поэтому подсвечивается имя макроса
+                    let text = self.line(line);
+                    let span = tokenize(text)
+                        .into_iter()
+                        .find(|token| matches!(token.token, Token::Word(_)))
+                        .map_or(0..text.len(), |token| token.start..token.end);
+                    let message = format!(
+                        "{} (в подстановке: {})",
+                        diagnostic.message,
+                        diagnostic.source.trim()
+                    );
+                    (range(line, text, span), message)
+                } else {
+                    let span = &diagnostic.span;
+                    let start = span.start.min(diagnostic.source.len());
+                    let end = span.end.clamp(start, diagnostic.source.len());
+                    let range = range(line, &diagnostic.source, start..end);
+                    (range, diagnostic.message.clone())
+                };
+                json!({
+                    "range": range,
+                    "severity": severity,
+                    "source": "bevm",
+                    "message": message,
+                })
+            })
+            .collect::<Vec<Value>>();
+        Value::Array(diagnostics)
+    }
+
+    fn include_line(&self, file: &str) -> usize {
+        self.text
+            .lines()
+            .position(|line| line.trim_start().starts_with("$include") && line.contains(file))
+            .unwrap_or(0)
+    }
+
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut result = Vec::new();
+        for (n, line) in self.text.lines().enumerate() {
+            for (range, kind) in highlight(line, |_| false) {
+                match kind {
+                    Highlight::Label => result.push(Symbol {
+                        name: line[range.start + 1..range.end].to_string(),
+                        line: n,
+                        span: range.start + 1..range.end,
+                        constant: false,
+                    }),
+                    // $label name, $equ NAME 1, $set NAME 1: имя идет сразу после директивы
+                    Highlight::Directive => {
+                        let directive = &line[range.start + 1..range.end];
+                        if !matches!(directive, "label" | "equ" | "set") {
+                            continue;
+                        }
+                        let name = tokenize(line)
+                            .into_iter()
+                            .find_map(|token| match token.token {
+                                Token::Word(name) if token.start >= range.end => {
+                                    Some((name, token))
+                                }
+                                _ => None,
+                            });
+                        if let Some((name, token)) = name {
+                            result.push(Symbol {
+                                name: name.to_string(),
+                                line: n,
+                                span: token.start..token.end,
+                                constant: directive != "label",
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        result
+    }
+
+    pub fn document_symbols(&self) -> Value {
+        let symbols = self
+            .symbols()
+            .into_iter()
+            .map(|symbol| {
+                let range = range(symbol.line, self.line(symbol.line), symbol.span);
+                json!({
+                    "name": symbol.name,
+                    "kind": if symbol.constant { 14 } else { 13 },
+                    "range": range,
+                    "selectionRange": range,
+                })
+            })
+            .collect::<Vec<Value>>();
+        Value::Array(symbols)
+    }
+
+    // %метка под курсором, если курсор не в комментарии и не в строке
+    fn reference_at(&self, line: usize, character: usize) -> Option<&str> {
+        let text = self.line(line);
+        let byte = byte_offset(text, character);
+        let quoted = highlight(text, |_| false).into_iter().any(|(range, kind)| {
+            matches!(kind, Highlight::Comment | Highlight::Text) && range.contains(&byte)
+        });
+        if quoted {
+            return None;
+        }
+        tokenize(text)
+            .into_iter()
+            .find_map(|token| match token.token {
+                Token::Label(name) if (token.start..=token.end).contains(&byte) => Some(name),
+                _ => None,
+            })
+    }
+
+    pub fn definition(&self, uri: &str, line: usize, character: usize) -> Value {
+        let Some(name) = self.reference_at(line, character) else {
+            return Value::Null;
+        };
+        match self
+            .symbols()
+            .into_iter()
+            .find(|symbol| symbol.name == name)
+        {
+            Some(symbol) => json!({
+                "uri": uri,
+                "range": range(symbol.line, self.line(symbol.line), symbol.span),
+            }),
+            None => self.included_definition(name).unwrap_or(Value::Null),
+        }
+    }
+
+    // Метку из $include знает только собранная программа. Константы $equ из подключенных
+    // файлов и метки файла с ошибками так не найти
+    fn included_definition(&self, name: &str) -> Option<Value> {
+        let program = self.program.as_ref()?;
+        let label = program.labels.iter().find(|label| label.name == name)?;
+        let origin = &label.origin;
+        let source = program
+            .sources
+            .iter()
+            .find(|source| source.name == origin.location.file)?;
+        let line = origin.location.line.saturating_sub(1) as usize;
+        Some(json!({
+            "uri": path_uri(source.path.as_deref()?),
+            "range": range(line, &origin.source, origin.span.clone()),
+        }))
+    }
+
+    pub fn hover(&self, line: usize, character: usize, parser: &GeneralParser) -> Value {
+        let text = if let Some(name) = self.reference_at(line, character) {
+            self.label_hover(name)
+        } else {
+            self.command_hover(line, character, parser)
+        };
+        match text {
+            Some(text) => json!({ "contents": { "kind": "markdown", "value": text } }),
+            None => Value::Null,
+        }
+    }
+
+    fn label_hover(&self, name: &str) -> Option<String> {
+        let program = self.program.as_ref();
+        if let Some(label) = program.and_then(|p| p.labels.iter().find(|l| l.name == name)) {
+            return Some(format!("`%{}` - метка, адрес `{:03X}`", name, label.pos));
+        }
+        if let Some((_, value)) = program.and_then(|p| p.constants.iter().find(|c| c.0 == name)) {
+            return Some(format!("`%{}` = `{:X}` ($equ)", name, value));
+        }
+        let symbol = self.symbols().into_iter().find(|s| s.name == name)?;
+        Some(format!(
+            "`%{}` объявлена в строке {}",
+            name,
+            symbol.line + 1
+        ))
+    }
+
+    // Описание команды и то, во что ассемблер собрал эту строку
+    fn command_hover(
+        &self,
+        line: usize,
+        character: usize,
+        parser: &GeneralParser,
+    ) -> Option<String> {
+        let text = self.line(line);
+        let byte = byte_offset(text, character);
+        let first = tokenize(text).into_iter().next()?;
+        let Token::Word(word) = first.token else {
+            return None;
+        };
+        if !(first.start..=first.end).contains(&byte) || !parser.is_mnemonic(word) {
+            return None;
+        }
+
+        let mut result = String::new();
+        let file = self.file_name();
+        let cells = self.program.iter().flat_map(|program| {
+            program.cells.iter().filter(|cell| {
+                cell.origin.location.file.as_deref() == file.as_deref()
+                    && cell.origin.location.line as usize == line + 1
+            })
+        });
+        let decoded = cells
+            .map(|cell| {
+                let command = parser.parse(cell.value);
+                format!(
+                    "{:03X}: {:04X}  {}",
+                    cell.pos,
+                    cell.value,
+                    command.mnemonic()
+                )
+            })
+            .collect::<Vec<String>>();
+        if !decoded.is_empty() {
+            result.push_str(&format!("```\n{}\n```\n", decoded.join("\n")));
+        }
+
+        let word = word.to_uppercase();
+        let (_, description) = parser
+            .commands()
+            .into_iter()
+            .find(|(mnemonic, _)| *mnemonic == word)?;
+        result.push_str(description);
+        Some(result)
+    }
+
+    pub fn completion(&self, line: usize, character: usize, parser: &GeneralParser) -> Value {
+        let text = self.line(line);
+        let before = &text[..byte_offset(text, character)];
+        let word_start = before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_word_char(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let prefix = &before[..word_start];
+
+        let items = if prefix.ends_with('%') {
+            self.symbols()
+                .into_iter()
+                .map(|symbol| {
+                    json!({
+                        "label": symbol.name,
+                        "kind": if symbol.constant { 21 } else { 6 },
+                        "detail": format!("строка {}", symbol.line + 1),
+                    })
+                })
+                .collect()
+        } else if prefix.trim() == "$" {
+            DIRECTIVES
+                .iter()
+                .map(|directive| json!({ "label": directive, "kind": 14 }))
+                .collect()
+        } else if prefix.trim().is_empty() {
+            parser
+                .commands()
+                .into_iter()
+                .map(|(mnemonic, description)| {
+                    json!({ "label": mnemonic, "kind": 14, "documentation": description })
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        json!({ "isIncomplete": false, "items": items })
+    }
+}
+
+fn position(text: &str, byte: usize) -> usize {
+    text[..byte].encode_utf16().count()
+}
+
+// Редакторы считают символы в UTF-16, а строки у нас в UTF-8
+fn byte_offset(text: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn range(line: usize, text: &str, span: Range<usize>) -> Value {
+    json!({
+        "start": { "line": line, "character": position(text, span.start) },
+        "end": { "line": line, "character": position(text, span.end) },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::document::Document;
+    use crate::parse::general::GeneralParser;
+    use crate::profile::MachineProfile;
+    use serde_json::json;
+
+    #[test]
+    fn answers_editor_requests() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let text =
+            "$equ DISPLAY 3\n$pos 10\nCLA $start # начало\nOUT %DISPLAY\nBR %start\nADD %nowhere\n";
+        let document = Document::new(text.to_string(), None, &parser, 0x7FF);
+
+        let diagnostics = document.diagnostics();
+        let errors = diagnostics
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|d| d["severity"] == 1)
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0]["range"]["start"],
+            json!({ "line": 5, "character": 4 })
+        );
+
+        let definition = document.definition("file:///a.mm", 4, 5);
+        assert_eq!(
+            definition["range"]["start"],
+            json!({ "line": 2, "character": 5 })
+        );
+        assert_eq!(document.definition("file:///a.mm", 2, 15), json!(null));
+
+        let hover = document.hover(2, 1, &parser);
+        assert!(hover["contents"]["value"]
+            .as_str()
+            .unwrap()
+            .contains("регистра А в 0"));
+
+        let names = document
+            .symbols()
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.constant))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![("DISPLAY".to_string(), true), ("start".to_string(), false)]
+        );
+
+        let completion = document.completion(4, 4, &parser);
+        assert_eq!(completion["items"].as_array().unwrap().len(), 2);
+        let completion = document.completion(5, 1, &parser);
+        assert!(completion["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|i| i["label"] == "ADD"));
+    }
+
+    #[test]
+    fn macros_and_includes() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let dir = std::env::temp_dir().join(format!("bevm-lsp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lib.mm"), "HLT\nCLA $print\n").unwrap();
+
+        let text = "$macro STORE cell\nMOV %cell\n$endm\n$include \"lib.mm\"\n  STORE %nowhere\nBR %print\n";
+        let document = Document::new(text.to_string(), Some(dir.join("main.mm")), &parser, 0x7FF);
+        let diagnostics = document.diagnostics();
+        let error = diagnostics
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|d| d["severity"] == 1)
+            .unwrap();
+        assert_eq!(
+            error["range"],
+            json!({
+                "start": { "line": 4, "character": 2 },
+                "end": { "line": 4, "character": 7 },
+            })
+        );
+
+        let text = "$include \"lib.mm\"\nBR %print\n";
+        let document = Document::new(text.to_string(), Some(dir.join("main.mm")), &parser, 0x7FF);
+        let definition = document.definition("file:///main.mm", 1, 5);
+        assert!(definition["uri"].as_str().unwrap().ends_with("/lib.mm"));
+        assert_eq!(
+            definition["range"]["start"],
+            json!({ "line": 1, "character": 5 })
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
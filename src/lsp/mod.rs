@@ -0,0 +1,203 @@
+use crate::lsp::document::Document;
+use crate::lsp::protocol::{
+    error_response, notification, read_message, response, write_message, Incoming,
+};
+use crate::parse::general::GeneralParser;
+use crate::profile::MachineProfile;
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+mod document;
+mod protocol;
+
+// Языковой сервер для .mm: редактор запускает bevm-lsp и общается с ним через stdin/stdout
+pub fn run() -> Result<(), String> {
+    let stdin = std::io::stdin();
+    let mut reader = stdin.lock();
+    let stdout = std::io::stdout();
+    let mut writer = stdout.lock();
+
+    let mut server = Server::new(MachineProfile::Classic);
+    // Сломанное сообщение не повод останавливать сервер: редактор останется без подсказок
+    while let Some(incoming) = read_message(&mut reader)? {
+        let replies = match incoming {
+            Incoming::Message(message) => server.handle(&message),
+            Incoming::Malformed { id, error } => {
+                eprintln!("bevm-lsp: {}", error);
+                id.map(|id| error_response(&id, -32700, &error))
+                    .into_iter()
+                    .collect()
+            }
+        };
+        for reply in replies {
+            write_message(&mut writer, &reply).map_err(|e| e.to_string())?;
+        }
+        if server.exit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Server {
+    parser: GeneralParser,
+    max_size: u16,
+    documents: HashMap<String, Document>,
+    exit: bool,
+}
+
+impl Server {
+    fn new(profile: MachineProfile) -> Server {
+        let config = profile.default_config();
+        Server {
+            parser: profile.parser(&config),
+            max_size: (config.general_size() - 1) as u16,
+            documents: HashMap::new(),
+            exit: false,
+        }
+    }
+
+    // Ответы и уведомления, которые надо отправить редактору
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let line = params["position"]["line"].as_u64().unwrap_or(0) as usize;
+        let character = params["position"]["character"].as_u64().unwrap_or(0) as usize;
+
+        let Some(id) = message.get("id") else {
+            return self.notify(method, params, uri);
+        };
+        if method.is_empty() {
+            // Ответ редактора на наш запрос, мы их не посылаем
+            return Vec::new();
+        }
+        let document = self.documents.get(uri);
+        let result = match method {
+            "initialize" => {
+                // Профиль можно выбрать в настройках клиента: { "profile": "ng" }
+                let profile = params["initializationOptions"]["profile"]
+                    .as_str()
+                    .and_then(MachineProfile::from_name);
+                if let Some(profile) = profile {
+                    *self = Server::new(profile);
+                }
+                json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "hoverProvider": true,
+                        "definitionProvider": true,
+                        "completionProvider": { "triggerCharacters": ["%", "$"] },
+                        "documentSymbolProvider": true,
+                    },
+                    "serverInfo": { "name": "bevm-lsp" },
+                })
+            }
+            "shutdown" => Value::Null,
+            "textDocument/hover" => document.map_or(Value::Null, |document| {
+                document.hover(line, character, &self.parser)
+            }),
+            "textDocument/definition" => document.map_or(Value::Null, |document| {
+                document.definition(uri, line, character)
+            }),
+            "textDocument/completion" => document.map_or(Value::Null, |document| {
+                document.completion(line, character, &self.parser)
+            }),
+            "textDocument/documentSymbol" => {
+                document.map_or(Value::Null, Document::document_symbols)
+            }
+            _ => {
+                return vec![error_response(
+                    id,
+                    -32601,
+                    &format!("Неизвестный метод {}", method),
+                )]
+            }
+        };
+        vec![response(id, result)]
+    }
+
+    fn notify(&mut self, method: &str, params: &Value, uri: &str) -> Vec<Value> {
+        let text = match method {
+            "exit" => {
+                self.exit = true;
+                return Vec::new();
+            }
+            "textDocument/didOpen" => params["textDocument"]["text"].as_str(),
+            // Синхронизация целиком: в последнем изменении весь текст файла
+            "textDocument/didChange" => params["contentChanges"]
+                .as_array()
+                .and_then(|changes| changes.last())
+                .and_then(|change| change["text"].as_str()),
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish(uri, json!([]))];
+            }
+            _ => None,
+        };
+        let Some(text) = text else {
+            return Vec::new();
+        };
+
+        let document = Document::new(text.to_string(), uri_path(uri), &self.parser, self.max_size);
+        let diagnostics = document.diagnostics();
+        self.documents.insert(uri.to_string(), document);
+        vec![publish(uri, diagnostics)]
+    }
+}
+
+fn publish(uri: &str, diagnostics: Value) -> Value {
+    notification(
+        "textDocument/publishDiagnostics",
+        json!({ "uri": uri, "diagnostics": diagnostics }),
+    )
+}
+
+// Путь нужен ассемблеру, чтобы находить файлы из $include
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(value) if byte == b'%' => {
+                bytes.push(value);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // file:///C:/dir/a.mm на Windows
+    let path = match path.strip_prefix('/') {
+        Some(windows) if windows.get(1..2) == Some(":") => windows.to_string(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+
+// Обратно из пути в file://, чтобы редактор открыл подключенный файл
+fn path_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/:-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
@@ -0,0 +1,110 @@
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+// Что пришло от редактора
+pub enum Incoming {
+    Message(Value),
+    // Заголовок или тело не разобрать. Сервер сообщает об ошибке и читает дальше
+    Malformed { id: Option<Value>, error: String },
+}
+
+// Сообщения JSON-RPC с заголовком Content-Length, как их шлют редакторы.
+// Err только при ошибке чтения, конец ввода - None
+pub fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<Incoming>, String> {
+    let mut length = Ok(None);
+    loop {
+        let mut header = String::new();
+        let read = reader
+            .read_line(&mut header)
+            .map_err(|e| format!("Не могу прочитать заголовок: {}", e))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value
+                    .trim()
+                    .parse::<usize>()
+                    .map(Some)
+                    .map_err(|_| format!("Неверный Content-Length: {}", value.trim()));
+            }
+        }
+    }
+
+    let length = match length {
+        Ok(Some(length)) => length,
+        Ok(None) => {
+            let error = "Сообщение без Content-Length".to_string();
+            return Ok(Some(Incoming::Malformed { id: None, error }));
+        }
+        Err(error) => return Ok(Some(Incoming::Malformed { id: None, error })),
+    };
+    let mut body = vec![0; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|e| format!("Не могу прочитать сообщение: {}", e))?;
+    Ok(Some(match serde_json::from_slice(&body) {
+        Ok(message) => Incoming::Message(message),
+        Err(e) => Incoming::Malformed {
+            id: recover_id(&body),
+            error: format!("Сообщение не JSON: {}", e),
+        },
+    }))
+}
+
+// Тело сломано, но "id" в нем может найтись: тогда редактор получит ответ с ошибкой
+fn recover_id(body: &[u8]) -> Option<Value> {
+    let key = body.windows(4).position(|w| w == b"\"id\"")?;
+    let rest = body[key + 4..].trim_ascii_start().strip_prefix(b":")?;
+    let id = serde_json::Deserializer::from_slice(rest)
+        .into_iter::<Value>()
+        .next()?
+        .ok()?;
+    (id.is_number() || id.is_string()).then_some(id)
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+pub fn response(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+pub fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lsp::protocol::{read_message, Incoming};
+    use serde_json::json;
+
+    #[test]
+    fn survives_malformed_messages() {
+        let input = "Content-Length: 19\r\n\r\n{\"id\": 7, \"method\":\
+                     Content-Length: x\r\n\r\n\
+                     Content-Length: 9\r\n\r\n{\"id\": 8}";
+        let mut reader = input.as_bytes();
+        let mut next = || read_message(&mut reader).unwrap();
+
+        assert!(matches!(next(), Some(Incoming::Malformed { id: Some(id), .. }) if id == 7));
+        assert!(matches!(next(), Some(Incoming::Malformed { id: None, .. })));
+        assert!(matches!(next(), Some(Incoming::Message(m)) if m == json!({ "id": 8 })));
+        assert!(next().is_none());
+    }
+}
@@ -1,14 +1,5 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use crate::model::Computer;
-
-mod model;
-mod parse;
-mod ui;
-mod utils;
-
 fn main() {
-    let computer = Computer::new();
-
-    ui::gui::Gui::new(computer).run();
+    bevm::run();
 }
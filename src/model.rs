@@ -1,6 +1,7 @@
 use crate::parse::general::{GeneralCommandInfo, GeneralParser};
 use crate::parse::mc::{parse, ExecutionResult, McParser, MicroCommandInfo};
 use crate::parse::{CommandInfo, Parser};
+use crate::profile::{MachineConfig, MachineProfile};
 use core::ops::{BitAnd, BitOr, BitXor, Shl};
 use std::cell::RefCell;
 use std::io::{BufRead, BufReader};
@@ -23,6 +24,7 @@ pub enum Register {
     Data,
     CommandCounter,
     Counter,
+    StackPointer,
 }
 
 impl Register {
@@ -45,6 +47,7 @@ impl Register {
             Register::Data => "РД",
             Register::CommandCounter => "СК",
             Register::Counter => "А",
+            Register::StackPointer => "SP",
         }
     }
 
@@ -59,13 +62,23 @@ impl Register {
         match self {
             Register::Status => computer.registers.r_status = data.bitand(0x1FFF),
             Register::MicroCommand => computer.registers.r_micro_command = data,
-            Register::Address => computer.registers.r_address = data.bitand(0x7FF),
+            Register::Address => {
+                computer.registers.r_address = data.bitand(computer.config.address_mask())
+            }
             Register::Command => computer.registers.r_command = data,
             Register::Data => computer.registers.r_data = data,
-            Register::CommandCounter => computer.registers.r_command_counter = data.bitand(0x7FF),
+            Register::CommandCounter => {
+                computer.registers.r_command_counter = data.bitand(computer.config.address_mask())
+            }
             Register::Counter => computer.registers.r_counter = data,
-            Register::McCounter => computer.registers.r_micro_command_counter = data as u8,
+            Register::McCounter => {
+                computer.registers.r_micro_command_counter =
+                    (data as u8).bitand(computer.config.micro_address_mask())
+            }
             Register::Buffer => computer.registers.r_buffer = data as u32,
+            Register::StackPointer => {
+                computer.registers.r_stack_pointer = data.bitand(computer.config.address_mask())
+            }
         }
     }
 
@@ -80,6 +93,7 @@ impl Register {
             Register::Counter => computer.registers.r_counter,
             Register::McCounter => computer.registers.r_micro_command_counter as u16,
             Register::Buffer => computer.registers.r_buffer as u16,
+            Register::StackPointer => computer.registers.r_stack_pointer,
         }
     }
 }
@@ -98,6 +112,8 @@ pub struct Registers {
 
     pub r_command_counter: u16, // СК - счетчик команд. текущая команда эвм
     pub r_counter: u16,         // А
+
+    pub r_stack_pointer: u16, // SP - указатель стека. только в БЭВМ-NG
 }
 
 macro_rules! status_flag {
@@ -131,12 +147,16 @@ impl Registers {
             r_command: 0,
             r_data: 0,
             r_counter: 0,
+
+            r_stack_pointer: 0,
         }
     }
 
     status_flag!(0, set_overflow, get_overflow);
     status_flag!(1, set_null, get_null);
     status_flag!(2, set_negative, get_negative);
+    // В классической БЭВМ этот бит всегда 0, в БЭВМ-NG это флаг переполнения V
+    status_flag!(3, set_signed_overflow, get_signed_overflow);
     status_flag!(4, set_allow_interrupt, get_allow_interupt);
     status_flag!(5, set_interrupt, get_interupt);
     status_flag!(6, set_io_ready, get_io_ready);
@@ -159,6 +179,10 @@ pub struct MemoryCell {
     data: u16,
     pub mnemonic: Option<String>,
     pub name: Option<String>,
+    // Комментарий из исходника, чтобы сохранить его обратно в файл
+    pub comment: Option<String>,
+    // Ячейка записана в исходнике числом или $word, а не командой
+    pub is_data: bool,
 }
 
 impl MemoryCell {
@@ -167,6 +191,8 @@ impl MemoryCell {
             data: 0,
             mnemonic: None,
             name: None,
+            comment: None,
+            is_data: false,
         }
     }
 
@@ -202,10 +228,12 @@ impl IOCell {
 }
 
 pub struct Computer {
+    pub profile: MachineProfile,
+    pub config: MachineConfig,
     pub registers: Registers,
     pub general_memory: Rc<RefCell<Memory<GeneralCommandInfo, GeneralParser>>>,
     pub mc_memory: Rc<RefCell<Memory<MicroCommandInfo, McParser>>>,
-    pub io_devices: [IOCell; 16],
+    pub io_devices: Vec<IOCell>,
     logs: Vec<LogEntry>,
 }
 
@@ -218,7 +246,9 @@ impl Computer {
         let opcode = self.registers.r_data;
 
         let num = opcode.bitand(0xF) as usize;
-        if opcode.bitand(0x0300) == 0x0300 {
+        if num >= self.io_devices.len() {
+            self.log(false, format!("ВУ номер {num} не подключено"));
+        } else if opcode.bitand(0x0300) == 0x0300 {
             let data = self.registers.r_counter.bitand(0xFF) as u8;
             self.log(
                 false,
@@ -279,8 +309,9 @@ impl Computer {
         for x in &mut self.mc_memory.borrow_mut().data {
             x.data = 0;
         }
+        // Вместе с программой стираются и имена ячеек
         for x in &mut self.general_memory.borrow_mut().data {
-            x.data = 0;
+            *x = MemoryCell::new();
         }
         for line in BufReader::new(data)
             .lines()
@@ -290,27 +321,35 @@ impl Computer {
             let address = u16::from_str_radix(splitted.first().unwrap(), 16).unwrap();
             let value = u16::from_str_radix(splitted.get(1).unwrap(), 16).unwrap();
 
-            self.mc_memory
-                .borrow_mut()
-                .data
-                .get_mut(address as usize)
-                .unwrap()
-                .set(value);
+            // В урезанную память МПУ микропрограмма помещается не целиком
+            if let Some(cell) = self.mc_memory.borrow_mut().data.get_mut(address as usize) {
+                cell.set(value);
+            }
         }
     }
 
     pub fn new() -> Computer {
+        Self::with_profile(MachineProfile::Classic)
+    }
+
+    pub fn with_profile(profile: MachineProfile) -> Computer {
+        Self::with_config(profile, profile.default_config())
+    }
+
+    pub fn with_config(profile: MachineProfile, config: MachineConfig) -> Computer {
         let mut result = Computer {
-            io_devices: [IOCell::new(); 16],
+            profile,
+            config,
+            io_devices: vec![IOCell::new(); config.io_devices],
             registers: Registers::new(),
             general_memory: Rc::new(RefCell::new(Memory {
-                data: Self::mem(2048),
-                parser: GeneralParser::new(),
+                data: Self::mem(config.general_size()),
+                parser: profile.parser(&config),
                 name: "general",
                 phantom: PhantomData::default(),
             })),
             mc_memory: Rc::new(RefCell::new(Memory {
-                data: Self::mem(256),
+                data: Self::mem(config.micro_size()),
                 parser: McParser::new(),
                 name: "mpu",
                 phantom: PhantomData::default(),
@@ -322,6 +361,26 @@ impl Computer {
         result
     }
 
+    // Память и регистры сбрасываются, так как программы разных моделей несовместимы
+    pub fn set_profile(&mut self, profile: MachineProfile) {
+        self.profile = profile;
+        self.set_config(profile.default_config());
+    }
+
+    // Память пересоздается на месте, чтобы окна, которые ее показывают, увидели новый размер
+    pub fn set_config(&mut self, config: MachineConfig) {
+        self.config = config;
+        self.io_devices = vec![IOCell::new(); config.io_devices];
+        {
+            let mut general = self.general_memory.borrow_mut();
+            general.parser = self.profile.parser(&config);
+            general.data = Self::mem(config.general_size());
+        }
+        self.mc_memory.borrow_mut().data = Self::mem(config.micro_size());
+        self.reset_memory();
+        self.registers = Registers::new();
+    }
+
     pub fn log(&mut self, micro_command: bool, info: String) {
         if self.logs.len() > 100 {
             self.logs.remove(0);
@@ -347,15 +406,22 @@ impl Computer {
             .mc_memory
             .borrow_mut()
             .data
-            .get(self.registers.r_micro_command_counter as usize)
+            .get(
+                self.registers
+                    .r_micro_command_counter
+                    .bitand(self.config.micro_address_mask()) as usize,
+            )
             .unwrap()
             .get();
         let cmd = parse(opcode);
         self.registers.r_micro_command = opcode;
         let result = cmd.run(self);
         if result != ExecutionResult::Jumped {
-            self.registers.r_micro_command_counter =
-                self.registers.r_micro_command_counter.wrapping_add(1);
+            self.registers.r_micro_command_counter = self
+                .registers
+                .r_micro_command_counter
+                .wrapping_add(1)
+                .bitand(self.config.micro_address_mask());
         }
         result
     }
@@ -0,0 +1,438 @@
+use crate::model::Computer;
+use crate::parse::mc::ExecutionResult;
+use crate::parse::ng::{sign_extend, Addressing};
+use crate::utils::bit_registers::bit_at;
+use core::ops::{BitAnd, BitOr, BitXor, Shl, Shr};
+
+// Откуда берется операнд адресной команды
+enum Operand {
+    Memory(u16),
+    Value(u16),
+    None,
+}
+
+// Исполнение команд БЭВМ-NG. Микропрограммы у этой модели нет,
+// поэтому команды всегда исполняются целиком.
+impl Computer {
+    pub fn ng_step(&mut self) -> ExecutionResult {
+        let mask = self.config.address_mask();
+        let counter = self.registers.r_command_counter.bitand(mask);
+        let command = self.ng_read(counter);
+
+        self.registers.r_address = counter;
+        self.registers.r_data = command;
+        self.registers.r_command = command;
+        self.registers.r_command_counter = counter.wrapping_add(1).bitand(mask);
+
+        let halted = match command.shr(12u16) {
+            0x0 => self.ng_address_less(command),
+            0x1 => {
+                self.ng_io(command);
+                false
+            }
+            0xF => {
+                self.ng_branch(command);
+                false
+            }
+            _ if command.bitand(0xFF00) == 0xCE00 => {
+                self.ng_branch(command);
+                false
+            }
+            _ => {
+                self.ng_address(command);
+                false
+            }
+        };
+
+        if halted || !self.registers.get_lever() {
+            ExecutionResult::Halted
+        } else {
+            ExecutionResult::Success
+        }
+    }
+
+    fn ng_read(&self, address: u16) -> u16 {
+        self.general_memory
+            .borrow()
+            .data
+            .get(address.bitand(self.config.address_mask()) as usize)
+            .unwrap()
+            .get()
+    }
+
+    fn ng_write(&mut self, address: u16, value: u16) {
+        self.general_memory
+            .borrow_mut()
+            .data
+            .get_mut(address.bitand(self.config.address_mask()) as usize)
+            .unwrap()
+            .set(value);
+    }
+
+    fn ng_push(&mut self, value: u16) {
+        self.registers.r_stack_pointer = self
+            .registers
+            .r_stack_pointer
+            .wrapping_sub(1)
+            .bitand(self.config.address_mask());
+        self.ng_write(self.registers.r_stack_pointer, value);
+    }
+
+    fn ng_pop(&mut self) -> u16 {
+        let value = self.ng_read(self.registers.r_stack_pointer);
+        self.registers.r_stack_pointer = self
+            .registers
+            .r_stack_pointer
+            .wrapping_add(1)
+            .bitand(self.config.address_mask());
+        value
+    }
+
+    fn ng_operand(&mut self, addressing: Addressing) -> Operand {
+        let ip = self.registers.r_command_counter;
+        let mask = self.config.address_mask();
+        let relative = |d: u8| ip.wrapping_add(sign_extend(d)).bitand(mask);
+
+        match addressing {
+            Addressing::Absolute(address) => Operand::Memory(address),
+            Addressing::Indirect(d) => Operand::Memory(self.ng_read(relative(d))),
+            Addressing::PostIncrement(d) => {
+                let pointer = relative(d);
+                let address = self.ng_read(pointer);
+                self.ng_write(pointer, address.wrapping_add(1));
+                Operand::Memory(address)
+            }
+            Addressing::PreDecrement(d) => {
+                let pointer = relative(d);
+                let address = self.ng_read(pointer).wrapping_sub(1);
+                self.ng_write(pointer, address);
+                Operand::Memory(address)
+            }
+            Addressing::Stack(d) => Operand::Memory(
+                self.registers
+                    .r_stack_pointer
+                    .wrapping_add(sign_extend(d))
+                    .bitand(self.config.address_mask()),
+            ),
+            Addressing::Relative(d) => Operand::Memory(relative(d)),
+            Addressing::Immediate(d) => Operand::Value(sign_extend(d)),
+            Addressing::Unknown(_) => Operand::None,
+        }
+    }
+
+    fn ng_address(&mut self, command: u16) {
+        let opcode = command.shr(12u16);
+        let address = match self.ng_operand(Addressing::decode(command)) {
+            Operand::Memory(address) => address.bitand(self.config.address_mask()),
+            Operand::Value(value) => {
+                self.registers.r_data = value;
+                return self.ng_apply(opcode, value);
+            }
+            Operand::None => return,
+        };
+        self.registers.r_address = address;
+
+        match opcode {
+            // LOOP
+            0x8 => {
+                let value = self.ng_read(address).wrapping_sub(1);
+                self.registers.r_data = value;
+                self.ng_write(address, value);
+                if value as i16 <= 0 {
+                    self.registers.r_command_counter = self
+                        .registers
+                        .r_command_counter
+                        .wrapping_add(1)
+                        .bitand(self.config.address_mask());
+                }
+            }
+            // SWAM
+            0xB => {
+                let value = self.ng_read(address);
+                self.registers.r_data = value;
+                self.ng_write(address, self.registers.r_counter);
+                self.ng_store_logic(value);
+            }
+            // JUMP
+            0xC => self.registers.r_command_counter = address,
+            // CALL
+            0xD => {
+                self.ng_push(self.registers.r_command_counter);
+                self.registers.r_command_counter = address;
+            }
+            // ST
+            0xE => {
+                self.registers.r_data = self.registers.r_counter;
+                self.ng_write(address, self.registers.r_counter);
+            }
+            _ => {
+                let value = self.ng_read(address);
+                self.registers.r_data = value;
+                self.ng_apply(opcode, value);
+            }
+        }
+    }
+
+    // Команды, которым нужно только значение операнда
+    fn ng_apply(&mut self, opcode: u16, value: u16) {
+        let ac = self.registers.r_counter;
+        let carry = if self.registers.get_overflow() { 1 } else { 0 };
+        match opcode {
+            // AND
+            0x2 => self.ng_store_logic(ac.bitand(value)),
+            // OR
+            0x3 => self.ng_store_logic(ac.bitor(value)),
+            // ADD
+            0x4 => self.ng_store_sum(ac, value, 0),
+            // ADC
+            0x5 => self.ng_store_sum(ac, value, carry),
+            // SUB
+            0x6 => self.ng_store_sum(ac, value.bitxor(0xFFFF), 1),
+            // CMP
+            0x7 => {
+                self.ng_store_sum(ac, value.bitxor(0xFFFF), 1);
+                self.registers.r_counter = ac;
+            }
+            // LD
+            0xA => self.ng_store_logic(value),
+            _ => {}
+        }
+    }
+
+    // Результат логической операции: N и Z по результату, V = 0
+    fn ng_store_logic(&mut self, result: u16) {
+        self.registers.r_counter = result;
+        self.registers.r_buffer = result as u32;
+        self.registers.set_null(result == 0);
+        self.registers.set_negative(bit_at(result, 15));
+        self.registers.set_signed_overflow(false);
+    }
+
+    // Результат сложения: N, Z, V и C
+    fn ng_store_sum(&mut self, left: u16, right: u16, carry: u32) {
+        let full = left as u32 + right as u32 + carry;
+        let result = full.bitand(0xFFFF) as u16;
+        let overflow =
+            bit_at(left, 15) == bit_at(right, 15) && bit_at(left, 15) != bit_at(result, 15);
+
+        self.ng_store_logic(result);
+        self.registers.r_buffer = full;
+        self.registers.set_overflow(full > 0xFFFF);
+        self.registers.set_signed_overflow(overflow);
+    }
+
+    // Результат сдвига: N, Z, C из выдвинутого бита и V = N xor C
+    fn ng_store_shift(&mut self, result: u16, carry: bool) {
+        self.ng_store_logic(result);
+        self.registers.set_overflow(carry);
+        self.registers
+            .set_signed_overflow(bit_at(result, 15) != carry);
+    }
+
+    fn ng_address_less(&mut self, command: u16) -> bool {
+        let ac = self.registers.r_counter;
+        let carry = self.registers.get_overflow();
+
+        match command.bitand(0x0FC0) {
+            // NOP
+            0x0000..=0x00C0 => {}
+            // HLT
+            0x0100..=0x01C0 => return true,
+            // CLA
+            0x0200 | 0x0240 => self.ng_store_logic(0),
+            // NOT
+            0x0280 | 0x02C0 => self.ng_store_logic(ac.bitxor(0xFFFF)),
+            // CLC
+            0x0300 | 0x0340 => self.registers.set_overflow(false),
+            // CMC
+            0x0380 | 0x03C0 => self.registers.set_overflow(!carry),
+            // ROL
+            0x0400 | 0x0440 => {
+                self.ng_store_shift(ac.shl(1u16).bitor(carry as u16), bit_at(ac, 15))
+            }
+            // ROR
+            0x0480 | 0x04C0 => {
+                self.ng_store_shift(ac.shr(1u16).bitor((carry as u16).shl(15u16)), bit_at(ac, 0))
+            }
+            // ASL
+            0x0500 | 0x0540 => self.ng_store_shift(ac.shl(1u16), bit_at(ac, 15)),
+            // ASR
+            0x0580 | 0x05C0 => self.ng_store_shift(((ac as i16) >> 1) as u16, bit_at(ac, 0)),
+            // SXTB
+            0x0600 | 0x0640 => self.ng_store_logic(sign_extend(ac as u8)),
+            // SWAB
+            0x0680 | 0x06C0 => self.ng_store_logic(ac.rotate_left(8)),
+            // INC
+            0x0700 => self.ng_store_sum(ac, 1, 0),
+            // DEC
+            0x0740 => self.ng_store_sum(ac, 0xFFFF, 0),
+            // NEG
+            0x0780 | 0x07C0 => self.ng_store_sum(ac.bitxor(0xFFFF), 0, 1),
+            // POP
+            0x0800..=0x08C0 => {
+                let value = self.ng_pop();
+                self.ng_store_logic(value);
+            }
+            // POPF
+            0x0900..=0x09C0 => self.registers.r_status = self.ng_pop(),
+            // RET
+            0x0A00..=0x0AC0 => {
+                self.registers.r_command_counter = self.ng_pop().bitand(self.config.address_mask())
+            }
+            // IRET
+            0x0B00..=0x0BC0 => {
+                self.registers.r_status = self.ng_pop();
+                self.registers.r_command_counter = self.ng_pop().bitand(self.config.address_mask());
+            }
+            // PUSH
+            0x0C00..=0x0CC0 => self.ng_push(ac),
+            // PUSHF
+            0x0D00..=0x0DC0 => self.ng_push(self.registers.r_status),
+            // SWAP
+            0x0E00..=0x0EC0 => {
+                let value = self.ng_read(self.registers.r_stack_pointer);
+                self.ng_write(self.registers.r_stack_pointer, ac);
+                self.ng_store_logic(value);
+            }
+            _ => {}
+        }
+        false
+    }
+
+    // Четные номера - регистры данных ВУ, нечетные - регистры состояния
+    fn ng_io(&mut self, command: u16) {
+        let argument = command.bitand(0xFF);
+        let device = argument.shr(1u16) as usize;
+        let status = bit_at(argument, 0);
+
+        let io = command.bitand(0xFF00) == 0x1200 || command.bitand(0xFF00) == 0x1300;
+        if io && device >= self.io_devices.len() {
+            self.log(false, format!("ВУ номер {device} не подключено"));
+            return;
+        }
+
+        match command.bitand(0xFF00) {
+            // DI
+            0x1000 => self.registers.set_allow_interrupt(false),
+            // EI
+            0x1100 => self.registers.set_allow_interrupt(true),
+            // IN
+            0x1200 => {
+                let data = if status {
+                    (self.io_devices[device].ready as u16).shl(6u16)
+                } else {
+                    self.io_devices[device].data as u16
+                };
+                let result = self.registers.r_counter.bitand(0xFF00).bitor(data);
+                self.ng_store_logic(result);
+                self.log(
+                    false,
+                    format!("Прочитал {data:0>2X} из регистра {argument:0>2X} ВУ номер {device}"),
+                );
+            }
+            // OUT
+            0x1300 => {
+                if status {
+                    self.io_devices[device].ready = false;
+                    self.log(false, format!("Сбросил флаг готовности ВУ номер {device}"));
+                } else {
+                    let data = self.registers.r_counter.bitand(0xFF) as u8;
+                    self.io_devices[device].data = data;
+                    self.log(false, format!("Записал {data:0>2X} в ВУ номер {device}"));
+                }
+            }
+            // INT
+            0x1800 => {
+                let vector = argument.bitand(0x7).shl(1u16);
+                self.ng_push(self.registers.r_command_counter);
+                self.ng_push(self.registers.r_status);
+                self.registers.r_command_counter =
+                    self.ng_read(vector).bitand(self.config.address_mask());
+                self.registers.r_status = self.ng_read(vector + 1);
+            }
+            _ => {}
+        }
+    }
+
+    fn ng_branch(&mut self, command: u16) {
+        let n = self.registers.get_negative();
+        let z = self.registers.get_null();
+        let c = self.registers.get_overflow();
+        let v = self.registers.get_signed_overflow();
+
+        let jump = match command.shr(8u16) {
+            0xCE => true,
+            0xF0 => z,
+            0xF1 => !z,
+            0xF2 => n,
+            0xF3 => !n,
+            0xF4 => c,
+            0xF5 => !c,
+            0xF6 => v,
+            0xF7 => !v,
+            0xF8 => n != v,
+            0xF9 => n == v,
+            _ => false,
+        };
+
+        if jump {
+            let offset = sign_extend(command.bitand(0xFF) as u8);
+            self.registers.r_command_counter = self
+                .registers
+                .r_command_counter
+                .wrapping_add(offset)
+                .bitand(self.config.address_mask());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Computer;
+    use crate::parse::mc::ExecutionResult;
+    use crate::parse::Parser;
+    use crate::profile::MachineProfile;
+
+    fn load(computer: &mut Computer, start: u16, program: &[&str]) {
+        let parser = MachineProfile::Ng.parser(&computer.config);
+        let mut memory = computer.general_memory.borrow_mut();
+        for (offset, line) in program.iter().enumerate() {
+            let position = start + offset as u16;
+            let opcode = parser.rev_parse_at(line, position).unwrap();
+            memory.data[position as usize].set(opcode);
+        }
+    }
+
+    #[test]
+    fn sum_with_stack_and_call() {
+        let mut computer = Computer::with_profile(MachineProfile::Ng);
+        // Сумма массива из 20-23 через подпрограмму и автоинкремент
+        load(
+            &mut computer,
+            0x10,
+            &["CLA", "CALL 30", "LOOP 1F", "BR 11", "ST 1E", "HLT"],
+        );
+        load(&mut computer, 0x30, &["ADD (IP+01)+", "RET"]);
+        {
+            let mut memory = computer.general_memory.borrow_mut();
+            memory.data[0x32].set(0x20);
+            memory.data[0x1F].set(4);
+            for (i, value) in [1, 2, 3, 0xFFFF].iter().enumerate() {
+                memory.data[0x20 + i].set(*value);
+            }
+        }
+        computer.registers.r_stack_pointer = 0x700;
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.set_lever(true);
+
+        let mut steps = 0;
+        while computer.ng_step() != ExecutionResult::Halted {
+            steps += 1;
+            assert!(steps < 100);
+        }
+
+        assert_eq!(computer.general_memory.borrow().data[0x1E].get(), 5);
+        assert_eq!(computer.registers.r_stack_pointer, 0x700);
+        assert_eq!(computer.registers.r_command_counter, 0x16);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::model::MemoryCell;
+
+use std::io::BufRead;
+
+// Формат BasePC: в каждой строке адрес и значение ячейки в шестнадцатеричной системе.
+// Перед значением ячейки, с которой начинается программа, стоит +.
+pub struct Bpc {
+    pub cells: Vec<(u16, u16)>,
+    pub start: Option<u16>,
+}
+
+pub fn read_bpc<R: BufRead>(reader: R, size: usize) -> Result<Bpc, String> {
+    let mut result = Bpc {
+        cells: Vec::new(),
+        start: None,
+    };
+
+    for (line, line_num) in reader.lines().zip(1..) {
+        let line = line.map_err(|e| format!("Не могу прочитать строчку {}: {}", line_num, e))?;
+        let mut split = line.split_whitespace();
+        let Some(pos) = split.next() else {
+            continue;
+        };
+        // + может стоять отдельно от значения. Кроме значения в строке ничего быть не должно
+        let (value, start) = match split.next() {
+            Some("+") => (split.next().unwrap_or(""), true),
+            Some(value) => match value.strip_prefix('+') {
+                Some(value) => (value, true),
+                None => (value, false),
+            },
+            None => ("", false),
+        };
+        if value.is_empty() || split.next().is_some() {
+            return Err(format!("Неверный формат({}) на строчке {}", line, line_num));
+        }
+
+        let Ok(pos) = u16::from_str_radix(pos, 16) else {
+            return Err(format!(
+                "Не могу распарсить позицию {} на строчке {}",
+                pos, line_num
+            ));
+        };
+        if pos as usize >= size {
+            return Err(format!(
+                "Позиция {:X} на строчке {} не помещается в память",
+                pos, line_num
+            ));
+        }
+        // from_str_radix пропустил бы еще один знак, а это уже ошибка
+        let cmd = match u16::from_str_radix(value, 16) {
+            Ok(cmd) if value.chars().all(|c| c.is_ascii_hexdigit()) => cmd,
+            _ => {
+                return Err(format!(
+                    "Не могу распарсить команду {} на строчке {}",
+                    value, line_num
+                ))
+            }
+        };
+
+        if start {
+            result.start = Some(pos);
+        }
+        result.cells.push((pos, cmd));
+    }
+
+    Ok(result)
+}
+
+// Пустые ячейки не пишутся, кроме стартовой: при загрузке память все равно обнуляется
+pub fn write_bpc(memory: &[MemoryCell], start: u16) -> String {
+    let mut s = String::new();
+    for (pos, cell) in memory.iter().enumerate() {
+        let pos = pos as u16;
+        if cell.get() == 0 && pos != start {
+            continue;
+        }
+        let marker = if pos == start { "+" } else { "" };
+        s.push_str(&format!("{:03X} {}{:04X}\n", pos, marker, cell.get()));
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::MemoryCell;
+    use crate::parse::bpc::{read_bpc, write_bpc};
+
+    #[test]
+    fn round_trip() {
+        let mut memory = vec![MemoryCell::new(); 0x20];
+        memory[0x10].set(0xF200);
+        memory[0x11].set(0x4015);
+        memory[0x15].set(0x0007);
+
+        let text = write_bpc(&memory, 0x10);
+        assert_eq!(text, "010 +F200\n011 4015\n015 0007\n");
+
+        let bpc = read_bpc(text.as_bytes(), memory.len()).unwrap();
+        assert_eq!(bpc.start, Some(0x10));
+        assert_eq!(
+            bpc.cells,
+            vec![(0x10, 0xF200), (0x11, 0x4015), (0x15, 0x0007)]
+        );
+
+        let bpc = read_bpc("00a + 1\n\n00b  f000\n".as_bytes(), memory.len()).unwrap();
+        assert_eq!(bpc.start, Some(0xA));
+        assert_eq!(bpc.cells, vec![(0xA, 0x1), (0xB, 0xF000)]);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for text in [
+            "010",
+            "010 +",
+            "010 ++1",
+            "010 -1",
+            "XYZ 1",
+            "7FF 1",
+            "010 12345",
+            "010 F2 00",
+            "010 12 34 junk",
+            "010 + 1 2",
+            "010 + +1",
+        ] {
+            assert!(read_bpc(text.as_bytes(), 0x20).is_err(), "{}", text);
+        }
+    }
+}
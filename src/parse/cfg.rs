@@ -0,0 +1,332 @@
+use crate::model::MemoryCell;
+use crate::parse::mc::ControlCommand;
+use crate::parse::{CommandInfo, Parser};
+use crate::profile::MachineConfig;
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    // Следующая по порядку команда
+    Next,
+    Jump,
+    // ISZ или TSF перепрыгнули через следующую команду
+    Skip,
+    // JSR. Возврат из подпрограммы идет по ребру Next
+    Call,
+}
+
+// Команды, которые всегда выполняются подряд
+#[derive(Debug)]
+pub struct Block {
+    pub start: u16,
+    pub commands: Vec<(u16, String)>,
+}
+
+impl Block {
+    pub fn contains(&self, pos: u16) -> bool {
+        self.commands.iter().any(|(command, _)| *command == pos)
+    }
+}
+
+// Ребро между блоками, блоки называются по адресу первой команды
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edge {
+    pub from: u16,
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug)]
+pub struct Graph {
+    pub blocks: Vec<Block>,
+    pub edges: Vec<Edge>,
+}
+
+// Команда и куда после нее может пойти выполнение
+struct Node {
+    text: String,
+    successors: Vec<(u16, EdgeKind)>,
+}
+
+// Граф основной памяти. Память - это просто числа, поэтому кодом считается только то,
+// до чего можно дойти от входов. Нулевая ячейка считается концом, а не ISZ 000.
+pub fn program_graph<I: CommandInfo, P: Parser<I>>(
+    memory: &[MemoryCell],
+    parser: &P,
+    config: &MachineConfig,
+    entries: &[u16],
+) -> Graph {
+    build(memory, entries, config.address_mask(), |pos, value| {
+        let command = parser.parse(value);
+        let effect = command.effect();
+        let mut successors = Vec::new();
+        if !effect.stops {
+            successors.push((pos.wrapping_add(1), EdgeKind::Next));
+        }
+        if effect.skips {
+            successors.push((pos.wrapping_add(2), EdgeKind::Skip));
+        }
+        // Косвенный BR (X) никуда не ведет: адрес станет известен только при выполнении
+        if let Some(target) = effect.jumps {
+            let kind = if effect.calls {
+                EdgeKind::Call
+            } else {
+                EdgeKind::Jump
+            };
+            successors.push((target, kind));
+        }
+        Node {
+            text: command.mnemonic(),
+            successors,
+        }
+    })
+}
+
+// Граф микропрограммы: переходы только у управляющих микрокоманд
+pub fn micro_graph<I: CommandInfo, P: Parser<I>>(
+    memory: &[MemoryCell],
+    parser: &P,
+    config: &MachineConfig,
+    entries: &[u16],
+) -> Graph {
+    let mask = config.micro_address_mask() as u16;
+    build(memory, entries, mask, |pos, value| {
+        let mut successors = vec![(pos.wrapping_add(1), EdgeKind::Next)];
+        if let Some(control) = ControlCommand::decode(value) {
+            successors.push((control.jump_address() as u16, EdgeKind::Jump));
+        }
+        Node {
+            text: parser.parse(value).mnemonic(),
+            successors,
+        }
+    })
+}
+
+// Адреса берутся по маске, как в СК: после последней ячейки памяти идет нулевая
+fn build<F: Fn(u16, u16) -> Node>(
+    memory: &[MemoryCell],
+    entries: &[u16],
+    mask: u16,
+    node: F,
+) -> Graph {
+    let entries = entries
+        .iter()
+        .map(|entry| entry & mask)
+        .collect::<Vec<u16>>();
+    let mut nodes = BTreeMap::<u16, Node>::new();
+    let mut queue = entries.iter().copied().collect::<VecDeque<u16>>();
+    while let Some(pos) = queue.pop_front() {
+        let value = memory.get(pos as usize).map_or(0, MemoryCell::get);
+        if value == 0 || nodes.contains_key(&pos) {
+            continue;
+        }
+        let mut node = node(pos, value);
+        for (to, _) in node.successors.iter_mut() {
+            *to &= mask;
+        }
+        queue.extend(node.successors.iter().map(|(to, _)| *to));
+        nodes.insert(pos, node);
+    }
+
+    // Блок начинается там, куда переходят, и после каждого ветвления
+    let mut leaders = entries
+        .iter()
+        .copied()
+        .filter(|entry| nodes.contains_key(entry))
+        .collect::<BTreeSet<u16>>();
+    for node in nodes.values() {
+        let branches = !matches!(node.successors.as_slice(), [(_, EdgeKind::Next)]);
+        for (to, kind) in &node.successors {
+            if branches || *kind != EdgeKind::Next {
+                leaders.insert(*to);
+            }
+        }
+    }
+    leaders.retain(|leader| nodes.contains_key(leader));
+
+    let mut blocks = Vec::new();
+    let mut edges = Vec::new();
+    for &start in &leaders {
+        let mut commands = Vec::new();
+        let mut pos = start;
+        loop {
+            let node = &nodes[&pos];
+            commands.push((pos, node.text.clone()));
+            match node.successors.as_slice() {
+                [(next, EdgeKind::Next)] if nodes.contains_key(next) && !leaders.contains(next) => {
+                    pos = *next;
+                }
+                successors => {
+                    let targets = successors
+                        .iter()
+                        .filter(|(to, _)| nodes.contains_key(to))
+                        .map(|(to, kind)| Edge {
+                            from: start,
+                            to: *to,
+                            kind: *kind,
+                        });
+                    edges.extend(targets);
+                    break;
+                }
+            }
+        }
+        blocks.push(Block { start, commands });
+    }
+    Graph { blocks, edges }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Graphviz: dot -Tsvg graph.dot -o graph.svg
+pub fn write_dot(graph: &Graph, name: &str) -> String {
+    let mut result = format!("digraph {} {{\n", name);
+    result.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+    for block in &graph.blocks {
+        let label = block
+            .commands
+            .iter()
+            .map(|(pos, text)| format!("{:03X}: {}\\l", pos, escape(text)))
+            .collect::<String>();
+        result.push_str(&format!(
+            "    b{:03X} [label=\"{}\"];\n",
+            block.start, label
+        ));
+    }
+    for edge in &graph.edges {
+        let attributes = match edge.kind {
+            EdgeKind::Next => "",
+            EdgeKind::Jump => " [label=\"переход\"]",
+            EdgeKind::Skip => " [label=\"пропуск\", style=dashed]",
+            EdgeKind::Call => " [label=\"вызов\", style=dotted]",
+        };
+        result.push_str(&format!(
+            "    b{:03X} -> b{:03X}{};\n",
+            edge.from, edge.to, attributes
+        ));
+    }
+    result.push_str("}\n");
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::MemoryCell;
+    use crate::parse::cfg::{micro_graph, program_graph, write_dot, Edge, EdgeKind};
+    use crate::parse::general::GeneralParser;
+    use crate::parse::mc::McParser;
+    use crate::profile::{MachineConfig, MachineProfile};
+
+    fn memory(size: usize, cells: &[(usize, u16)]) -> Vec<MemoryCell> {
+        let mut memory = vec![MemoryCell::new(); size];
+        for (pos, value) in cells {
+            memory[*pos].set(*value);
+        }
+        memory
+    }
+
+    #[test]
+    fn splits_into_blocks() {
+        let config = MachineProfile::Classic.default_config();
+        let parser = GeneralParser::new(&config);
+        let memory = memory(
+            0x40,
+            &[
+                (0x10, 0xF200), // CLA
+                (0x11, 0x4020), // ADD 020
+                (0x12, 0xB015), // BEQ 015
+                (0x13, 0x0021), // ISZ 021
+                (0x14, 0xC011), // BR 011
+                (0x15, 0x2030), // JSR 030
+                (0x16, 0xF000), // HLT
+                (0x20, 0x0005),
+                (0x31, 0xC830), // BR (030)
+            ],
+        );
+        let graph = program_graph(&memory, &parser, &config, &[0x10]);
+
+        let starts = graph.blocks.iter().map(|b| b.start).collect::<Vec<_>>();
+        assert_eq!(starts, vec![0x10, 0x11, 0x13, 0x14, 0x15, 0x16, 0x31]);
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(
+            graph.edges,
+            vec![
+                edge(0x10, 0x11, EdgeKind::Next),
+                edge(0x11, 0x13, EdgeKind::Next),
+                edge(0x11, 0x15, EdgeKind::Jump),
+                edge(0x13, 0x14, EdgeKind::Next),
+                edge(0x13, 0x15, EdgeKind::Skip),
+                edge(0x14, 0x11, EdgeKind::Jump),
+                edge(0x15, 0x16, EdgeKind::Next),
+                edge(0x15, 0x31, EdgeKind::Call),
+            ]
+        );
+        // Данные в 020 не попали в граф
+        assert!(!graph.blocks.iter().any(|b| b.contains(0x20)));
+
+        let dot = write_dot(&graph, "program");
+        assert!(dot.contains("    b011 [label=\"011: ADD 020\\l012: BEQ 015\\l\"];\n"));
+        assert!(dot.contains("    b013 -> b015 [label=\"пропуск\", style=dashed];\n"));
+    }
+
+    #[test]
+    fn wraps_around_memory_end() {
+        let config = MachineConfig {
+            address_bits: 16,
+            ..MachineProfile::Ng.default_config()
+        };
+        let parser = GeneralParser::new(&config);
+        let memory = memory(
+            0x10000,
+            &[
+                (0xFFFE, 0x0010), // ISZ 010
+                (0xFFFF, 0xF200), // CLA
+                (0x0000, 0xF000), // HLT
+            ],
+        );
+        let graph = program_graph(&memory, &parser, &config, &[0xFFFE]);
+
+        let starts = graph.blocks.iter().map(|b| b.start).collect::<Vec<_>>();
+        assert_eq!(starts, vec![0x0000, 0xFFFE, 0xFFFF]);
+        assert!(graph.edges.contains(&Edge {
+            from: 0xFFFE,
+            to: 0x0000,
+            kind: EdgeKind::Skip
+        }));
+        assert!(graph.edges.contains(&Edge {
+            from: 0xFFFF,
+            to: 0x0000,
+            kind: EdgeKind::Next
+        }));
+    }
+
+    #[test]
+    fn follows_microprogram_jumps() {
+        let memory = memory(
+            0x100,
+            &[
+                (0x01, 0x0001),
+                (0x02, 0xC304), // if РС[3] == 1 GOTO 04
+                (0x03, 0x0002),
+                (0x04, 0x8001), // if РС[0] == 0 GOTO 01
+            ],
+        );
+        let config = MachineProfile::Classic.default_config();
+        let graph = micro_graph(&memory, &McParser::new(), &config, &[0x01]);
+
+        let starts = graph.blocks.iter().map(|b| b.start).collect::<Vec<_>>();
+        assert_eq!(starts, vec![0x01, 0x03, 0x04]);
+        assert!(graph.edges.contains(&Edge {
+            from: 0x01,
+            to: 0x04,
+            kind: EdgeKind::Jump
+        }));
+        assert!(graph.edges.contains(&Edge {
+            from: 0x04,
+            to: 0x01,
+            kind: EdgeKind::Jump
+        }));
+    }
+}
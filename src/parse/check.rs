@@ -0,0 +1,88 @@
+use crate::parse::diagnostic::Diagnostic;
+use crate::parse::file::{LabelDefinition, Placed};
+use crate::parse::{CommandInfo, Effect, Parser};
+
+use std::collections::{HashMap, HashSet};
+
+// Проверки собранной программы. Такое ассемблер собирает без ошибок,
+// но почти всегда это ошибка в программе.
+pub(super) fn check_program<I: CommandInfo, P: Parser<I>>(
+    cells: &[Placed],
+    labels: &[LabelDefinition],
+    used: &HashSet<String>,
+    parser: &P,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let by_pos = cells
+        .iter()
+        .map(|cell| (cell.pos, cell))
+        .collect::<HashMap<u16, &Placed>>();
+    let commands = cells
+        .iter()
+        .filter(|cell| cell.code)
+        .map(|cell| (cell.pos, parser.parse(cell.value)))
+        .collect::<HashMap<u16, I>>();
+    let effect = |pos: u16| commands.get(&pos).map(CommandInfo::effect);
+
+    // Сюда можно попасть не только по порядку
+    let targets = labels
+        .iter()
+        .map(|label| label.pos)
+        .chain(commands.values().filter_map(|c| c.effect().jumps))
+        .collect::<HashSet<u16>>();
+
+    for cell in cells.iter().filter(|cell| cell.code) {
+        let command = &commands[&cell.pos];
+        let Effect {
+            writes,
+            jumps,
+            stops,
+            undefined,
+            ..
+        } = command.effect();
+
+        if undefined {
+            diagnostics.push(cell.origin.warning(format!(
+                "Поведение команды {} не задано",
+                command.mnemonic()
+            )));
+        }
+        if let Some(target) = writes.filter(|target| effect(*target).is_some()) {
+            diagnostics.push(cell.origin.warning(format!(
+                "Команда пишет в ячейку {target:X}, в которой записана команда"
+            )));
+        }
+        if let Some(target) = jumps.filter(|target| by_pos.get(target).is_some_and(|t| !t.code)) {
+            diagnostics.push(cell.origin.warning(format!(
+                "Переход на ячейку {target:X}, в которой записаны данные"
+            )));
+        }
+
+        if !stops {
+            continue;
+        }
+        // ISZ или TSF перед BR могут через него перепрыгнуть
+        let skipped = cell
+            .pos
+            .checked_sub(1)
+            .and_then(effect)
+            .is_some_and(|e| e.skips);
+        let next = cell.pos.checked_add(1).and_then(|pos| by_pos.get(&pos));
+        if let Some(next) = next {
+            if next.code && !skipped && !targets.contains(&next.pos) {
+                diagnostics.push(next.origin.warning(format!(
+                    "Команда никогда не выполнится: перед ней {}",
+                    command.mnemonic()
+                )));
+            }
+        }
+    }
+
+    for label in labels.iter().filter(|label| !used.contains(&label.name)) {
+        diagnostics.push(
+            label
+                .origin
+                .warning(format!("Метка {} нигде не используется", label.name)),
+        );
+    }
+}
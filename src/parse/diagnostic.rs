@@ -0,0 +1,112 @@
+use crate::parse::include::Location;
+
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Severity::Error => "ошибка",
+            Severity::Warning => "предупреждение",
+        }
+    }
+}
+
+// Сообщение ассемблера с точным местом в исходнике
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: Location,
+    // Строка исходника целиком
+    pub source: Rc<str>,
+    // Байты строки, к которым относится сообщение
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error<S: Into<String>>(
+        location: Location,
+        source: Rc<str>,
+        span: Range<usize>,
+        message: S,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            location,
+            source,
+            span,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning<S: Into<String>>(
+        location: Location,
+        source: Rc<str>,
+        span: Range<usize>,
+        message: S,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error(location, source, span, message)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    // Номера символов, а не байт: в строке может быть кириллица
+    pub fn columns(&self) -> Range<usize> {
+        let start = self.span.start.min(self.source.len());
+        let end = self.span.end.clamp(start, self.source.len());
+        let column = |byte: usize| self.source[..byte].chars().count();
+        column(start)..column(end)
+    }
+
+    // Строка с ^^^ под ошибочным местом. Табуляции повторяются, чтобы не съехать.
+    pub fn caret(&self) -> String {
+        let columns = self.columns();
+        let mut result = self
+            .source
+            .chars()
+            .take(columns.start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        result.push_str(&"^".repeat((columns.end - columns.start).max(1)));
+        result
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Строка {}, символ {}: {}: {}",
+            self.location,
+            self.columns().start + 1,
+            self.severity.title(),
+            self.message
+        )?;
+        writeln!(f, "{}", self.source)?;
+        write!(f, "{}", self.caret())
+    }
+}
+
+// Где кусок part лежит внутри строки line. part должен быть срезом line,
+// иначе сообщение относится ко всей строке.
+pub fn span_in(line: &str, part: &str) -> Range<usize> {
+    let start = (part.as_ptr() as usize).wrapping_sub(line.as_ptr() as usize);
+    if start <= line.len() && start + part.len() <= line.len() {
+        start..start + part.len()
+    } else {
+        0..line.len()
+    }
+}
@@ -0,0 +1,140 @@
+use crate::model::MemoryCell;
+use crate::parse::file::replace_address;
+use crate::parse::{CommandInfo, Parser};
+
+use std::collections::{HashMap, HashSet};
+
+// Превращает память обратно в исходник. Ячейки, на которые переходят, получают метки L_,
+// а ячейки, с которыми работают команды, - DATA_. Собирается в ту же самую память.
+pub fn disassemble<I: CommandInfo, P: Parser<I>>(memory: &[MemoryCell], parser: &P) -> String {
+    // Данные тоже разбираются как команды и ссылаются непонятно куда. Поэтому ссылки
+    // собираются второй раз, уже без ячеек, которые оказались данными.
+    // Ячейки, загруженные из .mm как данные, известны заранее.
+    let known = memory
+        .iter()
+        .enumerate()
+        .filter(|(_, cell)| cell.is_data)
+        .map(|(pos, _)| pos as u16)
+        .collect::<HashSet<u16>>();
+    let (jumps, data) = references(memory, parser, &known);
+    let mut data_cells = data
+        .keys()
+        .filter(|pos| !jumps.contains_key(pos))
+        .copied()
+        .collect::<HashSet<u16>>();
+    data_cells.extend(known);
+    let (jumps, data) = references(memory, parser, &data_cells);
+
+    // На ячейку могут и переходить, и ссылаться как на данные. Тогда это код
+    let mut names = data;
+    names.extend(jumps.iter().map(|(pos, name)| (*pos, name.clone())));
+
+    let mut s = String::new();
+    let mut prev_empty = true;
+
+    for (pos, cell) in memory.iter().enumerate() {
+        let pos = pos as u16;
+        let v = cell.get();
+        let name = names.get(&pos);
+        if v == 0 && name.is_none() {
+            prev_empty = true;
+            continue;
+        }
+        if prev_empty {
+            s.push_str(&format!("$pos {:X}\n", pos));
+        }
+        prev_empty = false;
+
+        let is_data = cell.is_data || name.is_some() && !jumps.contains_key(&pos);
+        let mut line = if is_data {
+            format!("$word 0x{v:04X}")
+        } else {
+            let command = parser.parse(v);
+            let text = command.file_string();
+            match command
+                .effect()
+                .address
+                .and_then(|address| Some((address, names.get(&address)?)))
+            {
+                Some((address, target)) => replace_address(&text, address, target),
+                None => text,
+            }
+        };
+        if let Some(name) = name {
+            line.push_str(&format!(" ${name}"));
+        }
+        s.push_str(&line);
+        s.push('\n');
+    }
+
+    s
+}
+
+// Метки для переходов и для данных
+fn references<I: CommandInfo, P: Parser<I>>(
+    memory: &[MemoryCell],
+    parser: &P,
+    skip: &HashSet<u16>,
+) -> (HashMap<u16, String>, HashMap<u16, String>) {
+    let mut jumps = HashMap::new();
+    let mut data = HashMap::new();
+    let inside = |address: &u16| (*address as usize) < memory.len();
+    for (pos, cell) in memory.iter().enumerate() {
+        if cell.get() == 0 || skip.contains(&(pos as u16)) {
+            continue;
+        }
+        let effect = parser.parse(cell.get()).effect();
+        if let Some(target) = effect.jumps.filter(inside) {
+            jumps.insert(target, format!("L_{target:03X}"));
+        }
+        if let Some(address) = effect.address.filter(inside) {
+            data.insert(address, format!("DATA_{address:03X}"));
+        }
+    }
+    (jumps, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::MemoryCell;
+    use crate::parse::disasm::disassemble;
+    use crate::parse::file::parse_file;
+    use crate::parse::general::GeneralParser;
+    use crate::profile::MachineProfile;
+
+    #[test]
+    fn reassembles_to_same_memory() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let mut memory = vec![MemoryCell::new(); 0x40];
+        let dump = [
+            (0x10, 0xF200), // CLA
+            (0x11, 0x4020), // ADD 020
+            (0x12, 0x3821), // MOV (021)
+            (0x13, 0x0022), // ISZ 022
+            (0x14, 0xC011), // BR 011
+            (0x15, 0x2030), // JSR 030
+            (0x16, 0xF000), // HLT
+            (0x20, 0x0005),
+            (0x21, 0x0023),
+            (0x31, 0xC830), // BR (030)
+        ];
+        for (pos, value) in dump {
+            memory[pos].set(value);
+        }
+
+        let source = disassemble(&memory, &parser);
+        assert!(source.contains("ADD %DATA_020 $L_011\n"));
+        assert!(source.contains("MOV (%DATA_021)\n"));
+        assert!(source.contains("JSR %DATA_030\n"));
+        assert!(source.contains("$word 0x0000 $DATA_022\n"));
+        assert!(source.contains("BR (%DATA_030) $L_031\n"));
+        assert!(!source.contains("DATA_005"));
+
+        let program = parse_file(&mut source.as_bytes(), None, &parser, 0x3F).unwrap();
+        let mut reloaded = vec![MemoryCell::new(); 0x40];
+        program.load_into(&mut reloaded);
+        for (pos, (before, after)) in memory.iter().zip(&reloaded).enumerate() {
+            assert_eq!(before.get(), after.get(), "ячейка {pos:X}");
+        }
+    }
+}
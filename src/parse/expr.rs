@@ -0,0 +1,221 @@
+use crate::parse::lexer::{parse_number, tokenize, Spanned, Token};
+use crate::utils::koi8r;
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+// Целочисленное выражение над числами и метками: %end-%start, (%array+3)*2
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    // Имя и место в строке, чтобы указать на неизвестную метку
+    Label(String, Range<usize>),
+    Neg(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+// Откуда брать значения меток и констант
+pub trait Symbols {
+    fn value(&self, name: &str) -> Option<i64>;
+}
+
+impl Symbols for HashMap<String, u16> {
+    fn value(&self, name: &str) -> Option<i64> {
+        self.get(name).map(|&value| value as i64)
+    }
+}
+
+// span - байты разобранной строки с ошибкой. Если его нет, ошибка во всем выражении
+#[derive(Debug, PartialEq)]
+pub struct ExprError {
+    pub message: String,
+    pub span: Option<Range<usize>>,
+}
+
+impl ExprError {
+    pub fn new<S: Into<String>>(message: S) -> ExprError {
+        ExprError {
+            message: message.into(),
+            span: None,
+        }
+    }
+
+    pub fn at<S: Into<String>>(message: S, span: Range<usize>) -> ExprError {
+        ExprError {
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+}
+
+impl Expr {
+    pub fn eval<S: Symbols>(&self, symbols: &S) -> Result<i64, ExprError> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Label(name, span) => symbols.value(name).ok_or_else(|| {
+                ExprError::at(format!("Не могу найти переменную {name}."), span.clone())
+            }),
+            Expr::Neg(inner) => inner
+                .eval(symbols)?
+                .checked_neg()
+                .ok_or_else(|| ExprError::new("Переполнение при вычислении выражения")),
+            Expr::Binary(op, left, right) => {
+                let left = left.eval(symbols)?;
+                let right = right.eval(symbols)?;
+                let result = match op {
+                    '+' => left.checked_add(right),
+                    '-' => left.checked_sub(right),
+                    '*' => left.checked_mul(right),
+                    '/' => {
+                        if right == 0 {
+                            return Err(ExprError::new("Деление на ноль"));
+                        }
+                        left.checked_div(right)
+                    }
+                    _ => unreachable!(),
+                };
+                result.ok_or_else(|| ExprError::new("Переполнение при вычислении выражения"))
+            }
+        }
+    }
+}
+
+// Разбирает выражение, начиная с токена start. Числа без префикса записаны в системе radix.
+// Возвращает выражение и номер первого токена после него.
+pub fn parse_expr(
+    tokens: &[Spanned],
+    start: usize,
+    radix: u32,
+) -> Result<(Expr, usize), ExprError> {
+    let mut parser = ExprParser {
+        tokens,
+        pos: start,
+        radix,
+    };
+    let expr = parser.sum()?;
+    Ok((expr, parser.pos))
+}
+
+// Выражение должно занимать всю строку целиком
+pub fn evaluate<S: Symbols>(s: &str, symbols: &S, radix: u32) -> Result<i64, ExprError> {
+    let tokens = tokenize(s);
+    let (expr, end) = parse_expr(&tokens, 0, radix)?;
+    if end != tokens.len() {
+        return Err(ExprError::at(
+            format!(
+                "Неожиданные штуки после выражения: {}",
+                &s[tokens[end].start..]
+            ),
+            tokens[end].start..s.len(),
+        ));
+    }
+    expr.eval(symbols)
+}
+
+struct ExprParser<'t, 'a> {
+    tokens: &'t [Spanned<'a>],
+    pos: usize,
+    radix: u32,
+}
+
+impl<'a> ExprParser<'_, 'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|t| t.token)
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[char],
+        next: fn(&mut Self) -> Result<Expr, ExprError>,
+    ) -> Result<Expr, ExprError> {
+        let mut left = next(self)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            if !ops.contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let right = next(self)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&['+', '-'], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, ExprError> {
+        self.binary(&['*', '/'], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        if self.peek() == Some(Token::Op('-')) {
+            self.pos += 1;
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        let Some(&Spanned { token, start, end }) = self.tokens.get(self.pos) else {
+            return Err(ExprError::new(
+                "Выражение оборвалось, ожидалось число или метка",
+            ));
+        };
+        self.pos += 1;
+        match token {
+            Token::Word(word) => {
+                parse_number(word, self.radix)
+                    .map(Expr::Number)
+                    .ok_or_else(|| {
+                        ExprError::at(format!("Не могу распарсить число {word}"), start..end)
+                    })
+            }
+            Token::Char(ch) => koi8r::encode(ch)
+                .map(|code| Expr::Number(code as i64))
+                .ok_or_else(|| {
+                    ExprError::at(format!("Символ {ch} нельзя записать в КОИ-8Р"), start..end)
+                }),
+            Token::Label(name) => Ok(Expr::Label(name.to_string(), start..end)),
+            Token::LParen => {
+                let inner = self.sum()?;
+                if self.peek() != Some(Token::RParen) {
+                    return Err(ExprError::at("Не закрытая скобка", start..end));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            _ => Err(ExprError::at("Ожидалось число или метка", start..end)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::expr::evaluate;
+    use std::collections::HashMap;
+
+    #[test]
+    fn precedence_and_labels() {
+        let mut symbols = HashMap::new();
+        symbols.insert("start".to_string(), 0x10);
+        symbols.insert("end".to_string(), 0x18);
+
+        assert_eq!(evaluate("1+2*3", &symbols, 16), Ok(7));
+        assert_eq!(evaluate("(1+2)*3", &symbols, 16), Ok(9));
+        assert_eq!(evaluate("%end-%start", &symbols, 16), Ok(8));
+        assert_eq!(evaluate("-%start+0x20/2", &symbols, 16), Ok(0));
+        assert_eq!(
+            evaluate("%nope+1", &symbols, 16).unwrap_err().span,
+            Some(0..5)
+        );
+        assert!(evaluate("(1+2", &symbols, 16).is_err());
+        assert!(evaluate("1/0", &symbols, 16).is_err());
+        assert!(evaluate("-(0-7FFFFFFFFFFFFFFF-1)", &symbols, 16).is_err());
+
+        assert_eq!(evaluate("10", &symbols, 10), Ok(10));
+        assert_eq!(evaluate("0b101+'A'", &symbols, 10), Ok(0x46));
+        assert_eq!(evaluate("'Я'", &symbols, 10), Ok(0xF1));
+        assert_eq!(evaluate("0b1", &symbols, 16), Ok(0xB1));
+    }
+}
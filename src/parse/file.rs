@@ -1,146 +1,783 @@
+use crate::model::MemoryCell;
+use crate::parse::check::check_program;
+use crate::parse::diagnostic::{span_in, Diagnostic};
+use crate::parse::expr::{evaluate, parse_expr, ExprError, Symbols};
+use crate::parse::include::{expand_includes, read_lines, Location, SourceFile};
+use crate::parse::lexer::{is_word_char, parse_number, parse_string, tokenize, Spanned, Token};
+use crate::parse::macros::expand_macros;
 use crate::parse::{CommandInfo, Parser};
+use crate::utils::koi8r;
 
-use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
 
+// Что положить в ячейку. Команды и выражения разбираются на втором проходе,
+// когда известны все метки.
+enum Pending {
+    Command,
+    // Значение из $word или $fill. Числа в нем десятичные
+    Data,
+    Value(u16),
+}
+
+// Строка исходника, из которой получилась ячейка или метка
+#[derive(Clone, Debug)]
+pub struct Origin {
+    pub location: Location,
+    pub source: Rc<str>,
+    // Где в строке лежит команда, значение или имя
+    pub span: Range<usize>,
+}
+
+impl Origin {
+    pub fn warning<S: Into<String>>(&self, message: S) -> Diagnostic {
+        Diagnostic::warning(
+            self.location.clone(),
+            self.source.clone(),
+            self.span.clone(),
+            message,
+        )
+    }
+
+    // $word и $string дают несколько ячеек из одной строки
+    pub fn same_line(&self, other: &Origin) -> bool {
+        Rc::ptr_eq(&self.source, &other.source) && self.location == other.location
+    }
+}
+
+struct PendingCell {
+    pos: u16,
+    pending: Pending,
+    origin: Origin,
+    sets: Rc<HashMap<String, i64>>,
+}
+
+// Ячейка собранной программы
+#[derive(Debug)]
+pub struct Placed {
+    pub pos: u16,
+    pub value: u16,
+    // Записана мнемоникой. Числа считаются данными
+    pub code: bool,
+    pub origin: Origin,
+}
+
+#[derive(Debug)]
+pub struct LabelDefinition {
+    pub name: String,
+    pub pos: u16,
+    pub origin: Origin,
+}
+
+// Собранная программа. Предупреждения не мешают ее загрузить.
+#[derive(Debug)]
+pub struct Program {
+    pub cells: Vec<Placed>,
+    pub labels: Vec<LabelDefinition>,
+    // Константы $equ по алфавиту
+    pub constants: Vec<(String, i64)>,
+    // Сначала сам файл, потом подключенные через $include
+    pub sources: Vec<SourceFile>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Program {
+    // Номер файла из sources и строка, из которой получилась ячейка pos
+    pub fn line_of(&self, pos: u16) -> Option<(usize, u32)> {
+        let cell = self.cells.iter().find(|cell| cell.pos == pos)?;
+        let location = &cell.origin.location;
+        let file = self.sources.iter().position(|s| s.name == location.file)?;
+        Some((file, location.line))
+    }
+
+    // Кладет программу в память вместе с именами ячеек и комментариями
+    pub fn load_into(&self, memory: &mut [MemoryCell]) {
+        for cell in memory.iter_mut() {
+            *cell = MemoryCell::new();
+        }
+        self.overlay(memory);
+    }
+
+    // То же самое, но остальная память не трогается
+    pub fn overlay(&self, memory: &mut [MemoryCell]) {
+        let mut previous: Option<&Origin> = None;
+        for cell in &self.cells {
+            let Some(target) = memory.get_mut(cell.pos as usize) else {
+                continue;
+            };
+            target.set(cell.value);
+            target.name = None;
+            target.comment = None;
+            target.is_data = !cell.code;
+            if !previous.is_some_and(|previous| previous.same_line(&cell.origin)) {
+                target.comment = comment(&cell.origin.source).map(str::to_string);
+            }
+            previous = Some(&cell.origin);
+        }
+
+        let mut named = HashSet::new();
+        for label in &self.labels {
+            if let Some(target) = memory.get_mut(label.pos as usize) {
+                if named.insert(label.pos) {
+                    target.name = Some(label.name.clone());
+                }
+            }
+        }
+    }
+}
+
+// Обратное преобразование: память в текст .mm. Имена ячеек становятся метками,
+// а адреса в командах - ссылками на эти метки. Данные пишутся через $word.
+pub fn write_file<I: CommandInfo, P: Parser<I>>(memory: &[MemoryCell], parser: &P) -> String {
+    let names = memory
+        .iter()
+        .enumerate()
+        .filter_map(|(pos, cell)| Some((pos as u16, cell.name.as_deref()?)))
+        .collect::<HashMap<u16, &str>>();
+
+    let mut s = String::new();
+    let mut prev_empty = true;
+
+    for (pos, cell) in memory.iter().enumerate() {
+        let v = cell.get();
+        if v == 0 && cell.name.is_none() && cell.comment.is_none() {
+            prev_empty = true;
+            continue;
+        }
+        if prev_empty {
+            s.push_str(&format!("$pos {:X}\n", pos));
+        }
+        prev_empty = false;
+
+        let command = parser.parse(v);
+        let mut line = command.file_string();
+        if cell.is_data {
+            line = format!("$word 0x{:0>4X}", v);
+        } else if let Some(address) = command.effect().address {
+            if let Some(name) = names.get(&address) {
+                line = replace_address(&line, address, name);
+            }
+        }
+        if let Some(name) = &cell.name {
+            line.push_str(&format!(" ${name}"));
+        }
+        if let Some(comment) = &cell.comment {
+            line.push_str(&format!(" # {comment}"));
+        }
+        s.push_str(&line);
+        s.push('\n');
+    }
+
+    s
+}
+
+// MOV 024 -> MOV %name, MOV (024) -> MOV (%name)
+pub(super) fn replace_address(command: &str, address: u16, name: &str) -> String {
+    substitute_address(command, address, &format!("%{}", name))
+}
+
+// BR 010 -> BR start: так команды показываются в таблице ячеек
+pub fn label_address(command: &str, address: u16, name: &str) -> String {
+    substitute_address(command, address, name)
+}
+
+fn substitute_address(command: &str, address: u16, text: &str) -> String {
+    let hex = format!("{:0>3X}", address);
+    match command.rfind(&hex) {
+        Some(i) if command[..i].ends_with([' ', '(']) => {
+            format!("{}{}{}", &command[..i], text, &command[i + hex.len()..])
+        }
+        _ => command.to_string(),
+    }
+}
+
+// Обратная замена для введенной команды: BR start и BR %start -> BR 010.
+// Мнемонику не трогаем, даже если есть метка с таким же именем.
+pub fn resolve_labels(command: &str, labels: &HashMap<String, u16>) -> String {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_';
+    let Some(space) = command.find(char::is_whitespace) else {
+        return command.to_string();
+    };
+    let (mut result, mut rest) = (command[..space].to_string(), &command[space..]);
+    while let Some(start) = rest.find(is_name) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c| !is_name(c)).unwrap_or(rest.len());
+        match labels.get(&rest[..end]) {
+            Some(address) => {
+                if result.ends_with('%') {
+                    result.pop();
+                }
+                result.push_str(&format!("{:0>3X}", address));
+            }
+            None => result.push_str(&rest[..end]),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+// Комментарий после # вне кавычек
+pub fn comment(line: &str) -> Option<&str> {
+    let i = find_unquoted(line, '#')?;
+    Some(line[i + 1..].trim()).filter(|comment| !comment.is_empty())
+}
+
+// Имена, видные в выражениях: метки, константы $equ и значения $set.
+// $set можно переопределять, поэтому каждая ячейка помнит значения на момент своей строки.
+struct Scope<'a> {
+    labels: &'a HashMap<String, u16>,
+    equs: &'a HashMap<String, i64>,
+    sets: &'a HashMap<String, i64>,
+    // Метки, на которые кто-то сослался
+    used: &'a RefCell<HashSet<String>>,
+}
+
+impl Symbols for Scope<'_> {
+    fn value(&self, name: &str) -> Option<i64> {
+        self.sets
+            .get(name)
+            .or_else(|| self.equs.get(name))
+            .copied()
+            .or_else(|| {
+                let value = self.labels.value(name);
+                if value.is_some() {
+                    self.used.borrow_mut().insert(name.to_string());
+                }
+                value
+            })
+    }
+}
+
+// path - откуда прочитаны данные. От него считаются пути в $include,
+// без него файлы ищутся в текущей папке.
+// Ошибки не останавливают разбор: возвращаются все найденные сразу вместе с предупреждениями.
 pub fn parse_file<T: Read, I: CommandInfo, P: Parser<I>>(
     data: &mut T,
+    path: Option<&Path>,
     parser: &P,
     max_size: u16,
-) -> Result<Vec<(u16, u16)>, String> {
-    let reader = BufReader::new(data);
-    let mut cursor = 0;
+) -> Result<Program, Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let name = path.map(|path| {
+        path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().to_string(),
+        )
+    });
+    let name: Option<Rc<str>> = name.map(Into::into);
+    let lines = read_lines(BufReader::new(data), name.clone(), &mut diagnostics);
+    let canonical = path.map(|path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf()));
+    let mut sources = vec![SourceFile::new(name, canonical.clone(), &lines)];
 
-    let mut variables = HashMap::<String, u16>::new();
-    let mut pre_result = Vec::<(u16, String, u16)>::new();
+    let mut chain = canonical.into_iter().collect();
+    let lines = expand_includes(
+        lines,
+        path.and_then(Path::parent),
+        &mut chain,
+        &mut sources,
+        &mut diagnostics,
+    );
+    let lines = expand_macros(lines, &mut diagnostics);
+
+    let program = assemble(lines, parser, max_size, &mut diagnostics);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        Err(diagnostics)
+    } else {
+        for cell in &program.cells {
+            let location = &cell.origin.location;
+            if let Some(source) = sources.iter_mut().find(|s| s.name == location.file) {
+                source.addresses.entry(location.line).or_insert(cell.pos);
+            }
+        }
+        Ok(Program {
+            sources,
+            warnings: diagnostics,
+            ..program
+        })
+    }
+}
+
+// Ошибка выражения указывает внутрь куска строки, в котором это выражение записано
+fn expr_span(outer: Range<usize>, error: &ExprError) -> Range<usize> {
+    match &error.span {
+        Some(inner) => outer.start + inner.start..outer.start + inner.end,
+        None => outer,
+    }
+}
+
+fn assemble<I: CommandInfo, P: Parser<I>>(
+    lines: Vec<(String, Location)>,
+    parser: &P,
+    max_size: u16,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Program {
+    let mut cursor = 0u32;
 
-    for (line, line_num) in reader.lines().zip(1..).take(u16::MAX.into()) {
-        let line = line.map_err(|x| x.to_string())?;
+    let mut variables = HashMap::<String, u16>::new();
+    let mut equs = HashMap::<String, i64>::new();
+    let mut sets = Rc::new(HashMap::<String, i64>::new());
+    let mut pre_result = Vec::<PendingCell>::new();
+    let mut labels = Vec::<LabelDefinition>::new();
+    let used = RefCell::new(HashSet::<String>::new());
+    let vectors = parser.interrupt_vectors();
+    // Откуда начался текущий кусок программы. None - с начала памяти, без $pos
+    let mut region: Option<Origin> = None;
+    let mut region_warned = false;
+    // Куски программы после $pos могут идти в любом порядке, но не должны пересекаться
+    let mut occupied = HashMap::<u16, Location>::new();
 
-        let Some(parsed) = parse_line(line.as_str()) else {
+    'lines: for (line, location) in lines {
+        let source: Rc<str> = line.into();
+        let Some(parsed) = parse_line(&source) else {
             continue;
         };
 
-        macro_rules! err {
-            ($msg:expr) => {
-                format!("Ошибка в строке {}. Номер строки: {}. Сообщение: {}", line, line_num, $msg)
+        macro_rules! fail {
+            ($part:expr, $msg:expr) => {{
+                diagnostics.push(Diagnostic::error(
+                    location.clone(),
+                    source.clone(),
+                    span_in(&source, $part),
+                    $msg,
+                ));
+                continue 'lines;
+            }};
+        }
+        macro_rules! origin {
+            ($part:expr) => {
+                Origin {
+                    location: location.clone(),
+                    source: source.clone(),
+                    span: span_in(&source, $part),
+                }
+            };
+        }
+        macro_rules! fail_expr {
+            ($part:expr, $error:expr) => {{
+                let error: ExprError = $error;
+                diagnostics.push(Diagnostic::error(
+                    location.clone(),
+                    source.clone(),
+                    expr_span(span_in(&source, $part), &error),
+                    error.message,
+                ));
+                continue 'lines;
+            }};
+        }
+        macro_rules! scope {
+            () => {
+                Scope {
+                    labels: &variables,
+                    equs: &equs,
+                    sets: &sets,
+                    used: &used,
+                }
+            };
+        }
+        macro_rules! define_label {
+            ($name:expr) => {{
+                if equs.contains_key($name) || sets.contains_key($name) {
+                    fail!($name, format!("Имя {} уже занято константой", $name));
+                }
+                // Строку все равно собираем, чтобы адреса после нее не съехали
+                match labels.iter().find(|label| label.name == $name) {
+                    Some(first) => diagnostics.push(Diagnostic::error(
+                        location.clone(),
+                        source.clone(),
+                        span_in(&source, $name),
+                        format!(
+                            "Метка {} уже объявлена в строке {}",
+                            $name, first.origin.location
+                        ),
+                    )),
+                    None => {
+                        variables.insert($name.to_string(), cursor as u16);
+                        labels.push(LabelDefinition {
+                            name: $name.to_string(),
+                            pos: cursor as u16,
+                            origin: origin!($name),
+                        });
+                    }
+                }
+            }};
+        }
+        macro_rules! place {
+            ($part:expr, $item:expr) => {
+                if cursor > max_size as u32 {
+                    fail!(
+                        $part,
+                        format!("Превышена максимальная позиция. Максимальная {max_size:X}.")
+                    );
+                }
+                if let Some(previous) = occupied.get(&(cursor as u16)) {
+                    fail!(
+                        $part,
+                        format!("Ячейка {cursor:X} уже занята строкой {previous}")
+                    );
+                }
+                occupied.insert(cursor as u16, location.clone());
+                if vectors.contains(&(cursor as u16)) && !region_warned {
+                    region_warned = true;
+                    let origin = region.clone().unwrap_or_else(|| origin!($part));
+                    diagnostics.push(origin.warning(format!(
+                        "Программа занимает ячейки {:X}-{:X}, которые нужны для прерываний",
+                        vectors.start,
+                        vectors.end - 1
+                    )));
+                }
+                pre_result.push(PendingCell {
+                    pos: cursor as u16,
+                    pending: $item,
+                    origin: origin!($part),
+                    sets: sets.clone(),
+                });
+                cursor += 1;
             };
         }
         match parsed {
             DataLine::Operator(name, arg) => {
-                if name != "pos" {
-                    return Err(err!(format!("Неизвестный оператор {name}")));
-                }
+                let (arg, label) = split_label(arg);
+                if name == "pos" {
+                    let pos = match evaluate(arg, &scope!(), 16) {
+                        Ok(pos) if (0..=u16::MAX as i64).contains(&pos) => pos as u16,
+                        Ok(pos) => fail!(arg, format!("Позиция {pos} не помещается в 16 бит")),
+                        Err(e) => fail_expr!(arg, e),
+                    };
 
-                let Ok(pos) = u16::from_str_radix(arg, 16) else {
-                    return Err(err!(format!("Не могу распарсить число {arg}")))
-                };
+                    if pos > max_size {
+                        fail!(arg, format!("Явно указанная позиция курсора больше максимально допустимой. Максимальная {max_size:X}. Укзаная {pos:X}."));
+                    }
 
-                if pos < cursor {
-                    return Err(err!(format!("Явно указанная позиция курсора меньше текущей позиции курсора. Текущая {cursor:X}. Укзаная {pos:X}.")));
+                    cursor = pos as u32;
+                    region = Some(origin!(arg));
+                    region_warned = false;
                 }
 
-                if pos > max_size {
-                    return Err(err!(format!("Явно указанная позиция курсора больше максимально допустимой. Максимальная {max_size:X}. Укзаная {pos:X}." )));
+                if let Some(label) = label {
+                    define_label!(label);
                 }
 
-                cursor = pos;
+                match name {
+                    "pos" => {}
+                    // Метка отдельной строкой, указывает на следующую ячейку
+                    "label" => define_label!(arg),
+                    "equ" | "set" => {
+                        let Some((symbol, value)) = arg.split_once(char::is_whitespace) else {
+                            fail!(arg, "Ожидалось имя и значение");
+                        };
+                        let value = value.trim();
+                        let value = match evaluate(value, &scope!(), 16) {
+                            Ok(value) => value,
+                            Err(e) => fail_expr!(value, e),
+                        };
+                        if symbol.is_empty() || !symbol.chars().all(is_word_char) {
+                            fail!(symbol, format!("Недопустимое имя {}", symbol));
+                        }
+                        // Переопределять можно только то, что задано через $set
+                        let redefined = name == "set" && sets.contains_key(symbol);
+                        if !redefined
+                            && (variables.contains_key(symbol)
+                                || equs.contains_key(symbol)
+                                || sets.contains_key(symbol))
+                        {
+                            fail!(symbol, format!("Имя {} уже занято", symbol));
+                        }
+                        if name == "equ" {
+                            equs.insert(symbol.to_string(), value);
+                        } else {
+                            Rc::make_mut(&mut sets).insert(symbol.to_string(), value);
+                        }
+                    }
+                    "word" => {
+                        for value in split_arguments(arg) {
+                            if value.is_empty() {
+                                fail!(arg, "Пустое значение в списке");
+                            }
+                            place!(value, Pending::Data);
+                        }
+                    }
+                    "string" => {
+                        let text = match parse_string(arg) {
+                            Ok(text) => text,
+                            Err(e) => fail!(arg, e),
+                        };
+                        let mut bytes = Vec::new();
+                        for ch in text.chars() {
+                            let Some(code) = koi8r::encode(ch) else {
+                                fail!(arg, format!("Символ {ch} нельзя записать в КОИ-8Р"));
+                            };
+                            bytes.push(code);
+                        }
+                        // Строка заканчивается нулевым байтом, по два символа в ячейке
+                        bytes.push(0);
+                        for pair in bytes.chunks(2) {
+                            let low = pair.get(1).copied().unwrap_or(0);
+                            place!(arg, Pending::Value((pair[0] as u16) << 8 | low as u16));
+                        }
+                    }
+                    "res" | "fill" => {
+                        let (count, value) = if name == "res" {
+                            (arg, "0")
+                        } else {
+                            let Some((count, value)) = arg.split_once(char::is_whitespace) else {
+                                fail!(arg, "Ожидалось количество ячеек и значение");
+                            };
+                            (count, value.trim())
+                        };
+                        // Количество ячеек - как расстояние между адресами, поэтому hex, как в $pos
+                        let cells = match evaluate(count, &scope!(), 16) {
+                            Ok(cells) if cells >= 0 => cells,
+                            Ok(cells) => {
+                                fail!(count, format!("Отрицательное количество ячеек {cells}"))
+                            }
+                            Err(e) => fail_expr!(count, e),
+                        };
+                        for _ in 0..cells {
+                            if name == "res" {
+                                place!(arg, Pending::Value(0));
+                            } else {
+                                place!(value, Pending::Data);
+                            }
+                        }
+                    }
+                    _ => fail!(name, format!("Неизвестный оператор {name}")),
+                }
             }
             DataLine::Command(command, name) => {
-                pre_result.push((cursor, command.to_string(), line_num as u16));
-
                 if let Some(name) = name {
-                    variables.insert(name.to_string(), cursor);
+                    define_label!(name);
                 }
 
-                cursor += 1;
-
-                if cursor > max_size {
-                    return Err(err!(format!(
-                        "Превышена максимальная позиция. Максимальная {max_size:X}."
-                    )));
-                }
+                place!(command, Pending::Command);
             }
         }
     }
 
-    let mut result = Vec::<(u16, u16)>::new();
+    let mut placed = Vec::<Placed>::new();
 
-    for (pos, cmd, line) in pre_result {
-        let mut builder = String::new();
-        let mut name = String::new();
-
-        let mut var = false;
+    for cell in pre_result {
+        let scope = Scope {
+            labels: &variables,
+            equs: &equs,
+            sets: &cell.sets,
+            used: &used,
+        };
+        let origin = cell.origin;
+        let text = &origin.source[origin.span.clone()];
+        let mut fail = |span: Range<usize>, message: String| {
+            diagnostics.push(Diagnostic::error(
+                origin.location.clone(),
+                origin.source.clone(),
+                span,
+                message,
+            ))
+        };
 
-        for x in cmd.chars() {
-            if !is_variable_name_char(x) && var {
-                if !variables.contains_key(name.as_str()) {
-                    return Err(format!(
-                        "Ошибка в строке {line}. Не могу найти переменную {name}."
-                    ));
+        let (value, code) = match cell.pending {
+            Pending::Value(v) => (v, false),
+            Pending::Data => match evaluate(text, &scope, 10) {
+                Ok(v) if (-0x8000..=0xFFFF).contains(&v) => (v as u16, false),
+                Ok(v) => {
+                    fail(
+                        origin.span.clone(),
+                        format!("Значение {v} не помещается в 16 бит"),
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    fail(expr_span(origin.span.clone(), &e), e.message);
+                    continue;
                 }
-                builder.push_str(format!("{:X}", variables.get(name.as_str()).unwrap()).as_str());
-                if x != '%' {
-                    builder.push(x);
-                    var = false;
+            },
+            Pending::Command => {
+                let resolved = match resolve_operands(text, &scope) {
+                    Ok(resolved) => resolved,
+                    Err(e) => {
+                        fail(expr_span(origin.span.clone(), &e), e.message);
+                        continue;
+                    }
+                };
+                let str = resolved.as_str();
+
+                let mnemonic = if parser.supports_rev_parse() {
+                    parser.rev_parse_at(str, cell.pos)
+                } else {
+                    Err("Не могу распарсить число.".to_string())
+                };
+                match (mnemonic, u16::from_str_radix(str, 16)) {
+                    (Ok(v), _) => (v, true),
+                    (Err(_), Ok(v)) => (v, false),
+                    // Если метки заменились на числа, показываем, что в итоге разбиралось
+                    (Err(e), _) if str != text => {
+                        fail(origin.span.clone(), format!("{e} ({str})"));
+                        continue;
+                    }
+                    (Err(e), _) => {
+                        fail(origin.span.clone(), e);
+                        continue;
+                    }
                 }
-                name = String::new();
-            } else if x == '%' {
-                var = true;
-            } else if var {
-                name.push(x);
-            } else {
-                builder.push(x);
             }
+        };
+        placed.push(Placed {
+            pos: cell.pos,
+            value,
+            code,
+            origin,
+        });
+    }
+
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        check_program(&placed, &labels, &used.borrow(), parser, diagnostics);
+    }
+
+    let mut constants = equs.into_iter().collect::<Vec<_>>();
+    constants.sort();
+    Program {
+        cells: placed,
+        labels,
+        constants,
+        sources: Vec::new(),
+        warnings: Vec::new(),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(super) enum DataLine<'a> {
+    Operator(&'a str, &'a str),
+    Command(&'a str, Option<&'a str>),
+}
+
+// Подставляет значения выражений в операнды. Остальной текст не трогается,
+// его разбирает парсер команд: скобки косвенной адресации, IP+05, #05 и так далее.
+fn resolve_operands<S: Symbols>(cmd: &str, symbols: &S) -> Result<String, ExprError> {
+    let tokens = tokenize(cmd);
+    let mut result = String::new();
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        if !starts_expression(&tokens, i) {
+            i += 1;
+            continue;
         }
 
-        if var {
-            if !variables.contains_key(name.as_str()) {
-                return Err(format!(
-                    "Ошибка в строке {line}. Не могу найти переменную {name}."
-                ));
+        let (expr, end) = match parse_expr(&tokens, i, 16) {
+            Ok(parsed) => parsed,
+            Err(e) if matches!(tokens[i].token, Token::Label(_)) => return Err(e),
+            // Например (IP+05)+ - это не выражение, а режим адресации
+            Err(_) => {
+                i += 1;
+                continue;
             }
-            builder.push_str(format!("{:X}", variables.get(name.as_str()).unwrap()).as_str());
+        };
+
+        let value = expr.eval(symbols)?;
+        let span = tokens[i].start..tokens[end - 1].end;
+        if !(0..=u16::MAX as i64).contains(&value) {
+            return Err(ExprError::at(
+                format!(
+                    "Значение выражения {} равно {value} и не помещается в 16 бит",
+                    &cmd[span.clone()]
+                ),
+                span,
+            ));
         }
 
-        let str = builder.as_str();
+        result.push_str(&cmd[copied..tokens[i].start]);
+        result.push_str(&format!("{:X}", value));
+        copied = tokens[end - 1].end;
+        i = end;
+    }
 
-        if parser.supports_rev_parse() {
-            match parser.rev_parse(str) {
-                Ok(v) => result.push((pos, v)),
-                Err(e) => match u16::from_str_radix(str, 16) {
-                    Ok(v) => result.push((pos, v)),
-                    Err(_) => return Err(format!("Ошибка в строке {}({}): {}", line, str, e)),
-                },
-            }
-        } else {
-            match u16::from_str_radix(str, 16) {
-                Ok(v) => result.push((pos, v)),
-                Err(_) => {
-                    return Err(format!(
-                        "Ошибка в строке {line}({str}): Не могу распарсить число."
-                    ))
+    result.push_str(&cmd[copied..]);
+    Ok(result)
+}
+
+// Выражение начинается с метки, либо с числа или скобки, за которыми идет операция.
+// Число должно начинаться с цифры, иначе его не отличить от мнемоники вроде ADD.
+fn starts_expression(tokens: &[Spanned], i: usize) -> bool {
+    let operator_at = |j: usize| matches!(tokens.get(j).map(|t| t.token), Some(Token::Op(_)));
+
+    match tokens[i].token {
+        Token::Label(_) | Token::Char(_) => true,
+        Token::Word(word) => {
+            word.starts_with(|c: char| c.is_ascii_digit())
+                && parse_number(word, 16).is_some()
+                && operator_at(i + 1)
+        }
+        Token::LParen => matching_paren(tokens, i).is_some_and(|close| operator_at(close + 1)),
+        _ => false,
+    }
+}
+
+fn matching_paren(tokens: &[Spanned], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        match t.token {
+            Token::LParen => depth += 1,
+            Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
                 }
             }
+            _ => {}
         }
     }
+    None
+}
 
-    Ok(result)
+// Позиция символа ch вне кавычек
+fn find_unquoted(s: &str, ch: char) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match quote {
+            _ if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == ch => return Some(i),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => {}
+        }
+    }
+    None
 }
 
-#[derive(Debug, PartialEq)]
-enum DataLine<'a> {
-    Operator(&'a str, &'a str),
-    Command(&'a str, Option<&'a str>),
+// $word 1, 2, 3 $array - метка стоит в конце, как у команд
+pub(super) fn split_label(arg: &str) -> (&str, Option<&str>) {
+    match find_unquoted(arg, '$') {
+        Some(i) => (arg[..i].trim(), Some(arg[i + 1..].trim())),
+        None => (arg, None),
+    }
 }
 
-fn is_variable_name_char(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch.is_ascii_digit()
+pub(super) fn split_arguments(arg: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut rest = arg;
+    while let Some(i) = find_unquoted(rest, ',') {
+        result.push(rest[..i].trim());
+        rest = &rest[i + 1..];
+    }
+    result.push(rest.trim());
+    result
 }
 
-fn parse_line(line: &str) -> Option<DataLine> {
+pub(super) fn parse_line(line: &str) -> Option<DataLine<'_>> {
     // remove comments
-    let line = line.split_terminator('#').next().unwrap_or(line).trim();
+    let line = match find_unquoted(line, '#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+    .trim();
 
     if line.is_empty() {
         return None;
@@ -148,22 +785,41 @@ fn parse_line(line: &str) -> Option<DataLine> {
 
     // if starts with $
     if let Some(stripped) = line.strip_prefix('$') {
-        let mut iter = (stripped).split_ascii_whitespace();
-        let first = iter.next();
-        let second = iter.next();
-        return Some(DataLine::Operator(first?.trim(), second?.trim()));
+        let (first, second) = stripped
+            .split_once(char::is_whitespace)
+            .unwrap_or((stripped, ""));
+        return Some(DataLine::Operator(first.trim(), second.trim()));
     }
 
     // if not
-    let mut iter = line.split('$');
-    let first = iter.next();
-    let second = iter.next();
-    return Some(DataLine::Command(first?.trim(), second.map(str::trim)));
+    let (first, second) = match find_unquoted(line, '$') {
+        Some(i) => (&line[..i], Some(&line[i + 1..])),
+        None => (line, None),
+    };
+    Some(DataLine::Command(first.trim(), second.map(str::trim)))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::parse::file::{parse_line, DataLine};
+    use crate::model::MemoryCell;
+    use crate::parse::diagnostic::Diagnostic;
+    use crate::parse::file::{
+        label_address, parse_file, parse_line, resolve_labels, write_file, DataLine, Program,
+    };
+    use crate::parse::general::GeneralParser;
+    use crate::parse::include::Location;
+    use crate::parse::{CommandInfo, Parser};
+    use crate::profile::MachineProfile;
+    use std::collections::HashMap;
+
+    fn values(result: Result<Program, Vec<Diagnostic>>) -> Vec<(u16, u16)> {
+        let program = result.unwrap();
+        program
+            .cells
+            .iter()
+            .map(|cell| (cell.pos, cell.value))
+            .collect()
+    }
 
     #[test]
     fn parse() {
@@ -190,4 +846,446 @@ mod tests {
             assert_eq!(parse_line(input), expected);
         }
     }
+
+    #[test]
+    fn expressions_in_operands() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$pos 10
+CLA $start
+ADD %array+1
+MOV (%ptr+1)
+BR %start
+%ptr-%start $ptr
+$pos %ptr+1
+1 $array
+(2+1)*3
+";
+        let result = values(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF));
+        assert_eq!(
+            result,
+            vec![
+                (0x10, 0xF200),
+                (0x11, 0x4016),
+                (0x12, 0x3815),
+                (0x13, 0xC010),
+                (0x14, 0x0004),
+                (0x15, 0x0001),
+                (0x16, 0x0009),
+            ]
+        );
+
+        let errors = [
+            "BR %far*1000\nHLT $far",
+            "OUT %dev+0F\nHLT $dev",
+            "ADD %nope",
+        ];
+        for program in errors {
+            assert!(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF).is_err());
+        }
+    }
+
+    #[test]
+    fn data_directives() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = r#"$pos 10
+$word 1, -2, 0x1F, 0b101, 'A', %text $array
+$string "Пи#" $text # комментарий
+$res 2
+$fill 3 %array+1
+HLT
+"#;
+        let result = values(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF));
+        assert_eq!(
+            result,
+            vec![
+                (0x10, 0x0001),
+                (0x11, 0xFFFE),
+                (0x12, 0x001F),
+                (0x13, 0x0005),
+                (0x14, 0x0041),
+                (0x15, 0x0016),
+                (0x16, 0xF0C9),
+                (0x17, 0x2300),
+                (0x18, 0x0000),
+                (0x19, 0x0000),
+                (0x1A, 0x0011),
+                (0x1B, 0x0011),
+                (0x1C, 0x0011),
+                (0x1D, 0xF000),
+            ]
+        );
+
+        let program = "$pos 10\n$res 10\n$fill 0x2 10\nHLT\n";
+        let result = values(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF));
+        assert_eq!(result.len(), 0x13);
+        assert_eq!(result[0x10..], [(0x20, 10), (0x21, 10), (0x22, 0xF000)]);
+    }
+
+    #[test]
+    fn equates() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$equ KEYBOARD 3
+$set STEP 1
+IN %KEYBOARD
+AND %MASK
+ADD %STEP
+$set STEP %STEP*2
+ADD %STEP
+$word %STEP, %MASK
+$equ MASK 0F
+";
+        let result = values(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF));
+        assert_eq!(
+            result,
+            vec![
+                (0x0, 0xE203),
+                (0x1, 0x100F),
+                (0x2, 0x4001),
+                (0x3, 0x4002),
+                (0x4, 0x0002),
+                (0x5, 0x000F),
+            ]
+        );
+
+        let errors = [
+            "$equ A 1\n$equ A 2",
+            "$equ A 1\n$set A 2",
+            "$equ A 1\nHLT $A",
+        ];
+        for program in errors {
+            assert!(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF).is_err());
+        }
+    }
+
+    #[test]
+    fn macros() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$macro STORE value, cell
+CLA
+ADD %value
+MOV %cell
+$endm
+$macro COUNTDOWN cell
+CLA $again # своя метка в каждой подстановке
+ISZ %cell
+BR %again
+$endm
+$pos 10
+STORE 5, %a $first
+COUNTDOWN %a
+COUNTDOWN %a
+BR %first
+0 $a
+";
+        let result = values(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF));
+        assert_eq!(
+            result,
+            vec![
+                (0x10, 0xF200),
+                (0x11, 0x4005),
+                (0x12, 0x301A),
+                (0x13, 0xF200),
+                (0x14, 0x001A),
+                (0x15, 0xC013),
+                (0x16, 0xF200),
+                (0x17, 0x001A),
+                (0x18, 0xC016),
+                (0x19, 0xC010),
+                (0x1A, 0x0000),
+            ]
+        );
+
+        let errors = [
+            "$macro A x\nCLA",
+            "$macro A x\nCLA\n$endm\nA",
+            "$macro A\nA\n$endm\nA",
+        ];
+        for program in errors {
+            assert!(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF).is_err());
+        }
+    }
+
+    #[test]
+    fn macro_label_directive() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$macro WAIT cell
+$label again
+ISZ %cell
+BR %again
+$endm
+$pos 10
+WAIT %a
+WAIT %a
+0 $a
+";
+        let result = values(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF));
+        assert_eq!(
+            result,
+            vec![
+                (0x10, 0x0014),
+                (0x11, 0xC010),
+                (0x12, 0x0014),
+                (0x13, 0xC012),
+                (0x14, 0x0000),
+            ]
+        );
+    }
+
+    #[test]
+    fn includes() {
+        let dir = std::env::temp_dir().join(format!("bevm-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let write = |name: &str, text: &str| std::fs::write(dir.join(name), text).unwrap();
+        write("main.mm", "$include \"lib/io.mm\"\nBR %print\n");
+        write("lib/io.mm", "$include \"consts.mm\"\nOUT %DISPLAY $print\n");
+        write("lib/consts.mm", "$equ DISPLAY 3\n");
+        write("cycle.mm", "$include \"loop.mm\"\n");
+        write("loop.mm", "HLT\n$include \"cycle.mm\"\n");
+        write("broken.mm", "$include \"lib/bad.mm\"\n");
+        write("lib/bad.mm", "CLA\nBR %nowhere\n");
+        std::fs::create_dir_all(dir.join("a")).unwrap();
+        std::fs::create_dir_all(dir.join("b")).unwrap();
+        write("two.mm", "$include \"a/x.mm\"\n$include \"b/y.mm\"\n");
+        write("a/x.mm", "$include \"util.mm\"\n");
+        write("b/y.mm", "$include \"util.mm\"\n");
+        write("a/util.mm", "CLA\n");
+        write("b/util.mm", "CLC\nCMA\n");
+
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let parse = |name: &str| {
+            let path = dir.join(name);
+            let mut file = std::fs::File::open(&path).unwrap();
+            parse_file(&mut file, Some(&path), &parser, 0x7FF)
+        };
+        // Без предупреждений
+        let errors = |name: &str| {
+            let mut diagnostics = parse(name).unwrap_err();
+            diagnostics.retain(Diagnostic::is_error);
+            diagnostics
+        };
+
+        let main = parse("main.mm").unwrap();
+        let sources = main
+            .sources
+            .iter()
+            .map(|source| (source.name.as_deref().unwrap(), source.lines.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sources,
+            vec![("main.mm", 2), ("lib/io.mm", 2), ("lib/consts.mm", 1)]
+        );
+        assert_eq!(main.sources[0].addresses.get(&2), Some(&0x1));
+        assert_eq!(main.line_of(0x0), Some((1, 2)));
+        assert_eq!(values(Ok(main)), vec![(0x0, 0xE303), (0x1, 0xC000)]);
+        assert!(errors("cycle.mm")[0].message.contains("Циклическое"));
+        // Одинаковые имена из разных папок - разные файлы
+        let two = parse("two.mm").unwrap();
+        let names = two
+            .sources
+            .iter()
+            .map(|source| source.name.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["two.mm", "a/x.mm", "a/util.mm", "b/y.mm", "b/util.mm"]
+        );
+        assert_eq!(
+            two.cells[1].origin.location.file.as_deref(),
+            Some("b/util.mm")
+        );
+        assert_eq!(
+            errors("broken.mm")[0].location,
+            Location {
+                file: Some("lib/bad.mm".into()),
+                line: 2
+            }
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn collects_all_errors() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "CLA
+ADD %nope+1 # неизвестная метка
+$word 1, 70000
+$pos 0
+HLT
+$фуу
+";
+        let mut diagnostics =
+            parse_file(&mut program.as_bytes(), None, &parser, 0x7FF).unwrap_err();
+        diagnostics.retain(Diagnostic::is_error);
+        let found = diagnostics
+            .iter()
+            .map(|d| (d.location.line, d.columns()))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![(5, 0..3), (6, 1..4), (2, 4..9), (3, 9..14)]);
+        assert_eq!(diagnostics[2].caret(), "    ^^^^^");
+    }
+
+    #[test]
+    fn duplicate_labels() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$word 1 $x
+$word 2 $x
+$label start
+CLA $start
+BR %x
+";
+        let mut diagnostics =
+            parse_file(&mut program.as_bytes(), None, &parser, 0x7FF).unwrap_err();
+        diagnostics.retain(Diagnostic::is_error);
+        let found = diagnostics
+            .iter()
+            .map(|d| (d.location.line, d.columns(), d.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (2, 9..10, "Метка x уже объявлена в строке 1"),
+                (4, 5..10, "Метка start уже объявлена в строке 3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$pos 0
+BR %start
+$pos 10
+CLA $start
+MOV %start
+BR %value
+HLT
+ISZ %value $loop
+BR %loop
+HZF
+0 $value
+$label unused
+";
+        let program = parse_file(&mut program.as_bytes(), None, &parser, 0x7FF).unwrap();
+        let warnings = program
+            .warnings
+            .iter()
+            .map(|w| (w.location.line, w.columns().start, w.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (
+                    1,
+                    5,
+                    "Программа занимает ячейки 0-1, которые нужны для прерываний"
+                ),
+                (
+                    5,
+                    0,
+                    "Команда пишет в ячейку 10, в которой записана команда"
+                ),
+                (6, 0, "Переход на ячейку 17, в которой записаны данные"),
+                (7, 0, "Команда никогда не выполнится: перед ней BR 017"),
+                (10, 0, "Поведение команды HZF не задано"),
+                (12, 7, "Метка unused нигде не используется"),
+            ]
+        );
+    }
+
+    #[test]
+    fn save_keeps_labels_and_comments() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$pos 10
+CLA $start # начало
+ISZ %counter
+MOV (%counter)
+BR %start
+$pos 18
+0 $counter # счетчик
+";
+        let mut memory = vec![MemoryCell::new(); 0x20];
+        parse_file(&mut program.as_bytes(), None, &parser, 0x1F)
+            .unwrap()
+            .load_into(&mut memory);
+        let saved = write_file(&memory, &parser);
+        assert_eq!(
+            saved,
+            "$pos 10
+CLA $start # начало
+ISZ %counter
+MOV (%counter)
+BR %start
+$pos 18
+$word 0x0000 $counter # счетчик
+"
+        );
+
+        let mut reloaded = vec![MemoryCell::new(); 0x20];
+        parse_file(&mut saved.as_bytes(), None, &parser, 0x1F)
+            .unwrap()
+            .load_into(&mut reloaded);
+        for (before, after) in memory.iter().zip(&reloaded) {
+            assert_eq!(before.get(), after.get());
+            assert_eq!(before.name, after.name);
+            assert_eq!(before.comment, after.comment);
+            assert_eq!(before.is_data, after.is_data);
+        }
+    }
+
+    #[test]
+    fn labels_in_mnemonics() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let shown = |value: u16, address: u16, name: &str| {
+            label_address(&parser.parse(value).mnemonic(), address, name)
+        };
+        assert_eq!(shown(0xC010, 0x10, "start"), "BR start");
+        assert_eq!(shown(0x3818, 0x18, "counter"), "MOV (counter)");
+        assert_eq!(shown(0x4011, 0x10, "start"), "ADD 011");
+
+        let labels = HashMap::from([("start".to_string(), 0x10), ("ADD".to_string(), 0x11)]);
+        assert_eq!(resolve_labels("BR start", &labels), "BR 010");
+        assert_eq!(resolve_labels("MOV (%start)", &labels), "MOV (010)");
+        assert_eq!(resolve_labels("ADD ADD", &labels), "ADD 011");
+        assert_eq!(resolve_labels("BR starter", &labels), "BR starter");
+        assert_eq!(resolve_labels("CLA", &labels), "CLA");
+    }
+
+    #[test]
+    fn segments() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$pos 400
+$word 7 $value
+$pos 10
+ADD %value
+BR %handler
+$pos 7F0
+HLT $handler
+";
+        let result = values(parse_file(&mut program.as_bytes(), None, &parser, 0x7FF));
+        assert_eq!(
+            result,
+            vec![
+                (0x400, 0x0007),
+                (0x10, 0x4400),
+                (0x11, 0xC7F0),
+                (0x7F0, 0xF000)
+            ]
+        );
+
+        let overlapping = "$pos 10\nCLA\nCLA\n$pos 11\nHLT";
+        let errors = parse_file(&mut overlapping.as_bytes(), None, &parser, 0x7FF).unwrap_err();
+        assert_eq!(errors[0].location.line, 5);
+
+        // Поверх памяти: остальные ячейки остаются как были
+        let mut memory = vec![MemoryCell::new(); 0x800];
+        memory[0x20].set(0x1234);
+        memory[0x10].name = Some("old".to_string());
+        parse_file(&mut program.as_bytes(), None, &parser, 0x7FF)
+            .unwrap()
+            .overlay(&mut memory);
+        assert_eq!(memory[0x20].get(), 0x1234);
+        assert_eq!(memory[0x7F0].name.as_deref(), Some("handler"));
+        assert_eq!(memory[0x10].name, None);
+    }
 }
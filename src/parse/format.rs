@@ -0,0 +1,298 @@
+use crate::parse::diagnostic::Diagnostic;
+use crate::parse::file::{comment, parse_file, parse_line, split_label, DataLine, Placed};
+use crate::parse::general::GeneralParser;
+use crate::parse::include::Location;
+use crate::parse::lexer::parse_number;
+use crate::parse::{CommandInfo, Parser};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+// Директивы, которые занимают ячейки. Они выравниваются вместе с командами.
+const DATA_DIRECTIVES: [&str; 4] = ["word", "string", "res", "fill"];
+
+// Слишком длинные операнды (например, строки) не раздвигают колонки всего файла
+const MAX_OPERAND_WIDTH: usize = 24;
+
+// Строка исходника, разобранная на колонки
+enum Row {
+    Empty,
+    Comment(String),
+    // $pos, $equ, $include и прочее пишется с начала строки как есть
+    Directive(String, Option<String>),
+    Code {
+        mnemonic: String,
+        operand: String,
+        label: Option<String>,
+        comment: Option<String>,
+    },
+}
+
+// Единый стиль для .mm: колонки мнемоники, операнда, метки и комментария,
+// мнемоники заглавными буквами, адреса в три цифры. Собранная программа не меняется.
+pub fn format_file(
+    text: &str,
+    path: Option<&Path>,
+    parser: &GeneralParser,
+    max_size: u16,
+) -> Result<String, Vec<Diagnostic>> {
+    let program = parse_file(&mut text.as_bytes(), path, parser, max_size)?;
+    let main = program
+        .sources
+        .first()
+        .and_then(|source| source.name.clone());
+    let mut cells = HashMap::<u32, Vec<&Placed>>::new();
+    for cell in &program.cells {
+        if cell.origin.location.file == main {
+            cells
+                .entry(cell.origin.location.line)
+                .or_default()
+                .push(cell);
+        }
+    }
+
+    let mut in_macro = false;
+    let rows = text
+        .lines()
+        .zip(1..)
+        .map(|(line, line_num)| {
+            let row = format_row(line, parser, cells.get(&line_num), in_macro);
+            match parse_line(line) {
+                Some(DataLine::Operator("macro", _)) => in_macro = true,
+                Some(DataLine::Operator("endm", _)) => in_macro = false,
+                _ => {}
+            }
+            row
+        })
+        .collect::<Vec<Row>>();
+    let result = layout(&rows);
+
+    // Форматирование не должно менять программу. Если поменяло, файл лучше не трогать.
+    let before = program
+        .cells
+        .iter()
+        .map(|cell| (cell.pos, cell.value))
+        .collect::<Vec<_>>();
+    let after = parse_file(&mut result.as_bytes(), path, parser, max_size).map(|program| {
+        program
+            .cells
+            .iter()
+            .map(|cell| (cell.pos, cell.value))
+            .collect::<Vec<_>>()
+    });
+    if after.as_ref() != Ok(&before) {
+        let location = Location {
+            file: main,
+            line: 1,
+        };
+        let source = text.lines().next().unwrap_or("");
+        return Err(vec![Diagnostic::error(
+            location,
+            source.into(),
+            0..source.len(),
+            "Форматирование изменило бы программу, файл оставлен как есть",
+        )]);
+    }
+
+    Ok(result)
+}
+
+fn format_row(
+    line: &str,
+    parser: &GeneralParser,
+    cells: Option<&Vec<&Placed>>,
+    in_macro: bool,
+) -> Row {
+    let comment = comment(line).map(|comment| format!("# {}", comment));
+    let Some(parsed) = parse_line(line) else {
+        return match comment {
+            Some(comment) => Row::Comment(comment),
+            None if line.contains('#') => Row::Comment("#".to_string()),
+            None => Row::Empty,
+        };
+    };
+
+    match parsed {
+        DataLine::Operator(name, arg) if DATA_DIRECTIVES.contains(&name) => {
+            let (arg, label) = split_label(arg);
+            Row::Code {
+                mnemonic: format!("${}", name),
+                operand: arg.to_string(),
+                label: label.map(|label| format!("${}", label)),
+                comment,
+            }
+        }
+        DataLine::Operator(name, arg) => {
+            let arg = match (name, parse_number(arg, 16)) {
+                ("pos", Some(pos)) if !in_macro => format!("{:03X}", pos),
+                _ => arg.to_string(),
+            };
+            let text = if arg.is_empty() {
+                format!("${}", name)
+            } else {
+                format!("${} {}", name, arg)
+            };
+            Row::Directive(text, comment)
+        }
+        DataLine::Command(command, label) => {
+            let (mnemonic, operand) = command
+                .split_once(char::is_whitespace)
+                .map_or((command, ""), |(m, o)| (m, o.trim()));
+            let mut mnemonic = mnemonic.to_string();
+            let mut operand = operand.to_string();
+            if parser.is_mnemonic(&mnemonic) {
+                mnemonic = mnemonic.to_uppercase();
+            }
+            // Адрес числом записываем так же, как его показывает сама БЭВМ.
+            // В теле макроса операнды зависят от параметров, их не трогаем.
+            if let (Some([cell]), false) = (cells.map(Vec::as_slice), in_macro) {
+                if let Some(canonical) = canonical(parser, cell, &mnemonic, &operand) {
+                    operand = canonical;
+                }
+            }
+            Row::Code {
+                mnemonic,
+                operand,
+                label: label.map(|label| format!("${}", label)),
+                comment,
+            }
+        }
+    }
+}
+
+fn canonical(
+    parser: &GeneralParser,
+    cell: &Placed,
+    mnemonic: &str,
+    operand: &str,
+) -> Option<String> {
+    // Метки, символы и выражения остаются как написаны
+    let plain = |c: char| c.is_ascii_alphanumeric() || "()#+- ".contains(c);
+    if !cell.code || operand.is_empty() || !operand.chars().all(plain) {
+        return None;
+    }
+    let text = parser.parse(cell.value).file_string();
+    let (name, canonical) = text.split_once(' ')?;
+    // 5+3 тоже выражение, а (IP+05)+ - режим адресации NG
+    if ['+', '-']
+        .iter()
+        .any(|c| operand.contains(*c) && !canonical.contains(*c))
+    {
+        return None;
+    }
+    let same = name == mnemonic && parser.rev_parse_at(&text, cell.pos) == Ok(cell.value);
+    same.then(|| canonical.to_string())
+}
+
+fn layout(rows: &[Row]) -> String {
+    let code = || {
+        rows.iter().filter_map(|row| match row {
+            Row::Code {
+                mnemonic,
+                operand,
+                label,
+                ..
+            } => Some((mnemonic, operand, label)),
+            _ => None,
+        })
+    };
+    let width = |s: &str| s.chars().count();
+    let mnemonic_width = code().map(|(m, _, _)| width(m)).max().unwrap_or(0);
+    let operand_width = code()
+        .map(|(_, o, _)| width(o))
+        .filter(|w| *w <= MAX_OPERAND_WIDTH)
+        .max()
+        .unwrap_or(0);
+    let label_width = code()
+        .filter_map(|(_, _, l)| l.as_ref().map(|l| width(l)))
+        .max()
+        .unwrap_or(0);
+    let comment_column = mnemonic_width + operand_width + label_width + 3;
+
+    let mut result = String::new();
+    let mut previous_empty = true;
+    for row in rows {
+        let line = match row {
+            Row::Empty => {
+                if !previous_empty {
+                    result.push('\n');
+                }
+                previous_empty = true;
+                continue;
+            }
+            Row::Comment(comment) => comment.clone(),
+            Row::Directive(text, comment) => {
+                // Каждый $pos начинает новый кусок программы
+                if text.starts_with("$pos") && !previous_empty {
+                    result.push('\n');
+                }
+                match comment {
+                    Some(comment) => format!("{:<comment_column$} {}", text, comment),
+                    None => text.clone(),
+                }
+            }
+            Row::Code {
+                mnemonic,
+                operand,
+                label,
+                comment,
+            } => format!(
+                "{:<mnemonic_width$} {:<operand_width$} {:<label_width$} {}",
+                mnemonic,
+                operand,
+                label.as_deref().unwrap_or(""),
+                comment.as_deref().unwrap_or("")
+            ),
+        };
+        result.push_str(line.trim_end());
+        result.push('\n');
+        previous_empty = false;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::format::format_file;
+    use crate::parse::general::GeneralParser;
+    use crate::profile::MachineProfile;
+
+    #[test]
+    fn formats_program() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let source = "$equ   DISPLAY 3
+$pos 10
+cla $start   #начало
+  add 5
+out %DISPLAY
+
+
+mov (21) # сохранить
+br %start $loop
+add 2+3
+$pos 20
+$word 7 $x
+";
+        let expected = "$equ DISPLAY 3
+
+$pos 010
+CLA            $start # начало
+ADD   005
+OUT   %DISPLAY
+
+MOV   (021)           # сохранить
+BR    %start   $loop
+ADD   2+3
+
+$pos 020
+$word 7        $x
+";
+        let formatted = format_file(source, None, &parser, 0x7FF).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            format_file(&formatted, None, &parser, 0x7FF).unwrap(),
+            expected
+        );
+        assert!(format_file("ADD %nowhere", None, &parser, 0x7FF).is_err());
+    }
+}
@@ -1,17 +1,30 @@
-use crate::parse::{CommandInfo, Parser};
+use crate::parse::{CommandInfo, Effect, Parser};
+use crate::profile::MachineConfig;
 
 use core::ops::{BitAnd, BitOr, BitXor};
+#[cfg(feature = "gui")]
 use imgui::Ui;
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::ops::Range;
 use std::rc::Rc;
 
 pub struct GeneralParser {
     sorted: Vec<Rc<dyn GeneralCommand>>,
     mnemonic_map: HashMap<String, Rc<dyn GeneralCommand>>,
+    pub(super) interrupt_vectors: Range<u16>,
 }
 
 impl GeneralParser {
-    fn register<T>(&mut self, command: T)
+    pub(super) fn empty() -> GeneralParser {
+        GeneralParser {
+            sorted: vec![],
+            mnemonic_map: HashMap::new(),
+            interrupt_vectors: 0..0,
+        }
+    }
+
+    pub(super) fn register<T>(&mut self, command: T)
     where
         T: 'static,
         T: GeneralCommand,
@@ -22,11 +35,8 @@ impl GeneralParser {
         self.mnemonic_map.insert(rc2.mnemonic().to_string(), rc2);
     }
 
-    pub fn new() -> GeneralParser {
-        let mut parser = GeneralParser {
-            sorted: vec![],
-            mnemonic_map: HashMap::new(),
-        };
+    pub fn new(config: &MachineConfig) -> GeneralParser {
+        let mut parser = GeneralParser::empty();
 
         parser.register(SimpleCommand::new(
             0xFF00,
@@ -78,96 +88,131 @@ impl GeneralParser {
         ));
         parser.register(SimpleCommand::new(0xF100, "NOP", "Команда, которая не делает ничего. Удобно применять вместе с ISZ для инкремента какой-либо ячейки памяти."));
         parser.register(AddressCommand::new_io(
+            config,
             0xE300,
             "OUT",
             "Присваивает указаному ВУ значение из регистра А",
         ));
         parser.register(SimpleCommand::new(0xF000, "HLT", "Выключает ЭВМ."));
-        parser.register(AddressCommand::new_io(0xE100, "TSF", "Присваивает 6 биту регистра РС статус готовности указанного ВУ. Затем, если 6 бит РС равен единице, регистр СК увеличивается на единицу."));
+        parser.register(AddressCommand::new_io(config, 0xE100, "TSF", "Присваивает 6 биту регистра РС статус готовности указанного ВУ. Затем, если 6 бит РС равен единице, регистр СК увеличивается на единицу."));
         parser.register(AddressCommand::new_io(
+            config,
             0xE200,
             "IN",
             "Берет значение из данного ВУ и кладет его в 8 младших бит регистра А",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0xB000,
             "BEQ",
             "Присваивает регистру СК значение X, если регистр А равен 0.",
         ));
         parser.register(AddressCommand::new_io(
+            config,
             0xE000,
             "CLF",
             "Устанавливает флаг готовности данного ВУ в 0.",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0x3000,
             "MOV",
             "Присваивает ячейке по адресу X значение из регистра А",
         ));
-        parser.register(AddressCommand::new_address(0x5000, "ADC", "Складывает значение из ячейки по адресу X с регистром А и добавляет 1, если С равен 1."));
+        parser.register(AddressCommand::new_address(config, 0x5000, "ADC", "Складывает значение из ячейки по адресу X с регистром А и добавляет 1, если С равен 1."));
         parser.register(AddressCommand::new_address(
+            config,
             0x6000,
             "SUB",
             "Вычитает значение ячейки по адресу X из регистра А.",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0x9000,
             "BPL",
             "Присваивает регистру СК значение X, если значение в регистре А больше или равно 0.",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0xA000,
             "BMI",
             "Присваивает регистру СК значение X, если значение в регистре А строго меньше 0.",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0xC000,
             "BR",
             "Присваивает регистру СК значение X",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0x1000,
             "AND",
             "Присваивает регистру А результат бинарного И между регистром А и значением в ячейке X",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0x4000,
             "ADD",
             "Присваивает регистру А результат сложения регистром А и значением в ячейке X",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0x8000,
             "BCS",
             "Присваивает регистру СК значение X, если С равно 1",
         ));
-        parser.register(AddressCommand::new_address(0x2000, "JSR", "Команда для организации логики подпрограмм. Значение регистра СК будет положено в ячейку по адресу X после чего регистру СК будет присвоенное значение X + 1"));
-        parser.register(AddressCommand::new_address(0x0000, "ISZ", "Увеличивает значение в ячейке по адресу X на 1. После чего, если значение в этой ячейке больше или равно 0, увеличивает СК на 1 тем самым \"перепрыгивает\" следующую команду."));
+        parser.register(AddressCommand::new_address(config, 0x2000, "JSR", "Команда для организации логики подпрограмм. Значение регистра СК будет положено в ячейку по адресу X после чего регистру СК будет присвоенное значение X + 1"));
+        parser.register(AddressCommand::new_address(config, 0x0000, "ISZ", "Увеличивает значение в ячейке по адресу X на 1. После чего, если значение в этой ячейке больше или равно 0, увеличивает СК на 1 тем самым \"перепрыгивает\" следующую команду."));
 
         parser.register(AddressCommand::new_address(
+            config,
             0x7000,
             "HZA7",
             "Команда для которой не задано поведение. То есть она ничего не делает.",
         ));
         parser.register(AddressCommand::new_address(
+            config,
             0xD000,
             "HZAD",
             "Команда для которой не задано поведение. То есть она ничего не делает.",
         ));
 
-        parser.sorted.sort_by(|a, b| {
-            let l = format!("{:b}", a.mask())
-                .chars()
-                .filter(|c| *c == '1')
-                .count();
-            let r = format!("{:b}", b.mask())
-                .chars()
-                .filter(|c| *c == '1')
-                .count();
-            r.cmp(&l)
-        });
+        // При прерывании СК сохраняется в ячейку 0, а выполнение продолжается с ячейки 1
+        parser.interrupt_vectors = 0..2;
+        parser.sort();
 
         parser
     }
+
+    // Более специфичные команды должны проверяться раньше
+    pub(super) fn sort(&mut self) {
+        self.sorted.sort_by_key(|c| Reverse(c.specificity()));
+    }
+
+    // Мнемоники с описаниями, для подсказок в редакторах
+    pub fn commands(&self) -> Vec<(&str, &str)> {
+        self.sorted
+            .iter()
+            .map(|command| (command.mnemonic(), command.description()))
+            .collect()
+    }
+
+    pub fn is_mnemonic(&self, word: &str) -> bool {
+        self.mnemonic_map.contains_key(word.to_uppercase().as_str())
+    }
+
+    fn find(&self, str: &str) -> Result<&Rc<dyn GeneralCommand>, String> {
+        let mnemonic = str.split_whitespace().next();
+        if mnemonic.is_none() {
+            return Err("Пустая строка получается".to_string());
+        }
+
+        let mnemonic = mnemonic.unwrap().to_uppercase();
+        self.mnemonic_map
+            .get(mnemonic.as_str())
+            .ok_or(format!("Неизвестная мнемоника {mnemonic}"))
+    }
 }
 
 impl Parser<GeneralCommandInfo> for GeneralParser {
@@ -185,37 +230,50 @@ impl Parser<GeneralCommandInfo> for GeneralParser {
     }
 
     fn rev_parse(&self, str: &str) -> Result<u16, String> {
-        let mnemonic = str.split(' ').next();
-        if mnemonic.is_none() {
-            return Err("Пустая строка получается".to_string());
-        }
+        self.find(str)?.rev_parse(str)
+    }
 
-        let mnemonic = mnemonic.unwrap().to_uppercase();
-        let command = self.mnemonic_map.get(mnemonic.as_str());
-        if command.is_none() {
-            return Err(format!("Неизвестная мнемоника {mnemonic}"));
-        }
+    fn rev_parse_at(&self, str: &str, position: u16) -> Result<u16, String> {
+        self.find(str)?.rev_parse_at(str, position)
+    }
 
-        command.unwrap().rev_parse(str)
+    fn interrupt_vectors(&self) -> Range<u16> {
+        self.interrupt_vectors.clone()
     }
 }
 
-trait GeneralCommand {
+pub(super) trait GeneralCommand {
     fn matching(&self, cmd: u16) -> bool {
         self.mask().bitand(cmd).bitand(self.mask()) == self.mask()
     }
 
+    fn specificity(&self) -> u32 {
+        self.mask().count_ones()
+    }
+
     fn file_string(&self, cmd: u16) -> String;
 
     fn mnemonic(&self) -> &str;
 
+    fn description(&self) -> &str;
+
     fn mask(&self) -> u16;
 
     fn parse(&self, data: u16) -> String;
 
     fn rev_parse(&self, s: &str) -> Result<u16, String>;
 
+    // Для команд, смысл которых зависит от адреса ячейки (например, относительные переходы)
+    fn rev_parse_at(&self, s: &str, _position: u16) -> Result<u16, String> {
+        self.rev_parse(s)
+    }
+
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui, opcode: u16);
+
+    fn effect(&self, _opcode: u16) -> Effect {
+        Effect::default()
+    }
 }
 
 struct SimpleCommand {
@@ -249,6 +307,10 @@ impl GeneralCommand for SimpleCommand {
         self.name
     }
 
+    fn description(&self) -> &str {
+        self.description
+    }
+
     fn mask(&self) -> u16 {
         self.mask
     }
@@ -271,6 +333,15 @@ impl GeneralCommand for SimpleCommand {
         Ok(self.mask)
     }
 
+    fn effect(&self, _opcode: u16) -> Effect {
+        Effect {
+            stops: self.name == "HLT",
+            undefined: self.name.starts_with("HZ"),
+            ..Effect::default()
+        }
+    }
+
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui, opcode: u16) {
         ui.text("Тип: Безадресная команда");
         ui.text(format!("Мнемоника: {}", self.name));
@@ -289,23 +360,37 @@ struct AddressCommand {
     description: &'static str,
     mask: u16,
     io: bool,
+    // Наибольший адрес или номер ВУ, который допускает текущая конфигурация
+    max: u16,
 }
 
 impl AddressCommand {
-    fn new_address(mask: u16, name: &'static str, description: &'static str) -> AddressCommand {
+    fn new_address(
+        config: &MachineConfig,
+        mask: u16,
+        name: &'static str,
+        description: &'static str,
+    ) -> AddressCommand {
         AddressCommand {
             name,
             description,
             mask,
             io: false,
+            max: config.max_direct_address(),
         }
     }
-    fn new_io(mask: u16, name: &'static str, description: &'static str) -> AddressCommand {
+    fn new_io(
+        config: &MachineConfig,
+        mask: u16,
+        name: &'static str,
+        description: &'static str,
+    ) -> AddressCommand {
         AddressCommand {
             name,
             description,
             mask,
             io: true,
+            max: config.max_io_device(),
         }
     }
 }
@@ -319,6 +404,10 @@ impl GeneralCommand for AddressCommand {
         self.name
     }
 
+    fn description(&self) -> &str {
+        self.description
+    }
+
     fn mask(&self) -> u16 {
         self.mask
     }
@@ -341,7 +430,7 @@ impl GeneralCommand for AddressCommand {
     }
 
     fn rev_parse(&self, s: &str) -> Result<u16, String> {
-        let splited = s.trim().split(' ').collect::<Vec<&str>>();
+        let splited = s.split_whitespace().collect::<Vec<&str>>();
 
         if splited.len() > 2 {
             return Err(format!(
@@ -379,12 +468,11 @@ impl GeneralCommand for AddressCommand {
         };
 
         if let Ok(parsed) = u16::from_str_radix(address, 16) {
-            let max = if self.io { 0xF } else { 0x7FF };
-            if parsed > max {
+            if parsed > self.max {
                 if self.io {
-                    Err("Максимально адресуемое ВУ 0xF".to_string())
+                    Err(format!("Максимально адресуемое ВУ 0x{:X}", self.max))
                 } else {
-                    Err("Максимально адресуема память 0x7FF".to_string())
+                    Err(format!("Максимально адресуема память 0x{:X}", self.max))
                 }
             } else if indirect {
                 Ok(self.mask.bitor(parsed).bitor(0x0800))
@@ -399,6 +487,56 @@ impl GeneralCommand for AddressCommand {
         }
     }
 
+    fn effect(&self, opcode: u16) -> Effect {
+        if self.io {
+            return Effect {
+                skips: self.name == "TSF",
+                ..Effect::default()
+            };
+        }
+        let address = if opcode.bitand(0x0800) == 0 {
+            Some(opcode.bitand(0x7FF))
+        } else {
+            None
+        };
+        let effect = match self.name {
+            "MOV" => Effect {
+                writes: address,
+                ..Effect::default()
+            },
+            "ISZ" => Effect {
+                writes: address,
+                skips: true,
+                ..Effect::default()
+            },
+            // Адрес возврата кладется в X, а подпрограмма начинается с X + 1
+            "JSR" => Effect {
+                writes: address,
+                jumps: address.map(|address| address + 1),
+                calls: true,
+                ..Effect::default()
+            },
+            "BR" => Effect {
+                jumps: address,
+                stops: true,
+                ..Effect::default()
+            },
+            "BEQ" | "BPL" | "BMI" | "BCS" => Effect {
+                jumps: address,
+                ..Effect::default()
+            },
+            _ => Effect {
+                undefined: self.name.starts_with("HZ"),
+                ..Effect::default()
+            },
+        };
+        Effect {
+            address: Some(opcode.bitand(0x7FF)),
+            ..effect
+        }
+    }
+
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui, opcode: u16) {
         if self.io {
             ui.text("Тип: Команда ввода-вывода");
@@ -440,21 +578,40 @@ impl CommandInfo for GeneralCommandInfo {
         self.info.parse(self.opcode)
     }
 
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui) {
         self.info.draw_highlight(ui, self.opcode)
     }
+
+    fn effect(&self) -> Effect {
+        self.info.effect(self.opcode)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::parse::general::GeneralParser;
     use crate::parse::{CommandInfo, Parser};
+    use crate::profile::MachineProfile;
 
     #[test]
     fn test() {
-        let parser = GeneralParser::new();
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
 
         assert_eq!(parser.parse(0xF700).mnemonic(), "ROR");
         assert_eq!(parser.parse(0x3024).mnemonic(), "MOV 024");
     }
+
+    #[test]
+    fn small_memory_limits() {
+        let mut config = MachineProfile::Classic.default_config();
+        config.address_bits = 8;
+        config.io_devices = 4;
+        let parser = GeneralParser::new(&config);
+
+        assert_eq!(parser.rev_parse("MOV FF"), Ok(0x30FF));
+        assert!(parser.rev_parse("MOV 100").is_err());
+        assert_eq!(parser.rev_parse("OUT 3"), Ok(0xE303));
+        assert!(parser.rev_parse("OUT 4").is_err());
+    }
 }
@@ -0,0 +1,295 @@
+use crate::model::MemoryCell;
+
+// Образы памяти для других программ, например для модели БЭВМ на Verilog.
+// Адреса везде считаются в словах, а не в байтах.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    // Как у Quartus: адрес записи - номер слова, слово пишется старшим байтом вперед
+    IntelHex,
+    BinaryLittle,
+    BinaryBig,
+    // Текст для $readmemh: по слову в строке, @адрес задает позицию
+    ReadMemH,
+}
+
+impl ImageFormat {
+    pub const ALL: [ImageFormat; 4] = [
+        ImageFormat::IntelHex,
+        ImageFormat::BinaryLittle,
+        ImageFormat::BinaryBig,
+        ImageFormat::ReadMemH,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            ImageFormat::IntelHex => "Intel HEX",
+            ImageFormat::BinaryLittle => "Двоичный, младший байт первый",
+            ImageFormat::BinaryBig => "Двоичный, старший байт первый",
+            ImageFormat::ReadMemH => "$readmemh",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::IntelHex => "hex",
+            ImageFormat::BinaryLittle | ImageFormat::BinaryBig => "bin",
+            ImageFormat::ReadMemH => "mem",
+        }
+    }
+}
+
+const HEX_WORDS_PER_RECORD: usize = 8;
+
+pub fn write_image(memory: &[MemoryCell], format: ImageFormat) -> Vec<u8> {
+    let words = memory.iter().map(MemoryCell::get);
+    match format {
+        ImageFormat::BinaryLittle => words.flat_map(u16::to_le_bytes).collect(),
+        ImageFormat::BinaryBig => words.flat_map(u16::to_be_bytes).collect(),
+        ImageFormat::ReadMemH => {
+            let mut s = String::new();
+            for word in words {
+                s.push_str(&format!("{:04x}\n", word));
+            }
+            s.into_bytes()
+        }
+        ImageFormat::IntelHex => {
+            let words = words.collect::<Vec<u16>>();
+            let mut s = String::new();
+            for (i, chunk) in words.chunks(HEX_WORDS_PER_RECORD).enumerate() {
+                let address = (i * HEX_WORDS_PER_RECORD) as u16;
+                let data = chunk
+                    .iter()
+                    .flat_map(|word| word.to_be_bytes())
+                    .collect::<Vec<u8>>();
+                s.push_str(&hex_record(address, 0, &data));
+            }
+            s.push_str(&hex_record(0, 1, &[]));
+            s.into_bytes()
+        }
+    }
+}
+
+fn hex_record(address: u16, kind: u8, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend(address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend(data);
+    let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    bytes.push(0u8.wrapping_sub(sum));
+
+    let mut s = String::from(":");
+    for byte in bytes {
+        s.push_str(&format!("{:02X}", byte));
+    }
+    s.push('\n');
+    s
+}
+
+// Ячейки, которых нет в образе, остаются нулями
+pub fn read_image(data: &[u8], size: usize, format: ImageFormat) -> Result<Vec<u16>, String> {
+    let mut memory = vec![0u16; size];
+    match format {
+        ImageFormat::BinaryLittle | ImageFormat::BinaryBig => {
+            if data.len() % 2 == 1 {
+                return Err(format!(
+                    "В файле нечетное число байт ({}), а слова двухбайтные",
+                    data.len()
+                ));
+            }
+            if data.len() / 2 > size {
+                return Err(format!(
+                    "Образ на {:X} слов не помещается в память на {:X} слов",
+                    data.len() / 2,
+                    size
+                ));
+            }
+            for (cell, pair) in memory.iter_mut().zip(data.chunks(2)) {
+                let pair = [pair[0], pair[1]];
+                *cell = if format == ImageFormat::BinaryLittle {
+                    u16::from_le_bytes(pair)
+                } else {
+                    u16::from_be_bytes(pair)
+                };
+            }
+        }
+        ImageFormat::ReadMemH => read_memh(&text(data)?, &mut memory)?,
+        ImageFormat::IntelHex => read_intel_hex(&text(data)?, &mut memory)?,
+    }
+    Ok(memory)
+}
+
+fn text(data: &[u8]) -> Result<String, String> {
+    String::from_utf8(data.to_vec()).map_err(|_| "Файл не текстовый".to_string())
+}
+
+fn put(memory: &mut [u16], address: usize, word: u16, line_num: usize) -> Result<(), String> {
+    let Some(cell) = memory.get_mut(address) else {
+        return Err(format!(
+            "Адрес {:X} на строчке {} не помещается в память",
+            address, line_num
+        ));
+    };
+    *cell = word;
+    Ok(())
+}
+
+fn read_memh(text: &str, memory: &mut [u16]) -> Result<(), String> {
+    let mut address = 0;
+    let mut in_comment = false;
+    for (line, line_num) in text.lines().zip(1..) {
+        let mut rest = line;
+        let mut code = String::new();
+        // Комментарии как в Verilog: // до конца строки и /* */
+        loop {
+            if in_comment {
+                match rest.find("*/") {
+                    Some(i) => {
+                        rest = &rest[i + 2..];
+                        in_comment = false;
+                    }
+                    None => break,
+                }
+            }
+            let block = rest.find("/*");
+            let line_comment = rest.find("//");
+            match (block, line_comment) {
+                (Some(b), Some(l)) if l < b => {
+                    code.push_str(&rest[..l]);
+                    break;
+                }
+                (Some(b), _) => {
+                    code.push_str(&rest[..b]);
+                    code.push(' ');
+                    rest = &rest[b + 2..];
+                    in_comment = true;
+                }
+                (_, Some(l)) => {
+                    code.push_str(&rest[..l]);
+                    break;
+                }
+                _ => {
+                    code.push_str(rest);
+                    break;
+                }
+            }
+        }
+
+        for word in code.split_whitespace() {
+            let word = word.replace('_', "");
+            if let Some(position) = word.strip_prefix('@') {
+                address = usize::from_str_radix(position, 16).map_err(|_| {
+                    format!(
+                        "Не могу распарсить адрес {} на строчке {}",
+                        position, line_num
+                    )
+                })?;
+                continue;
+            }
+            let value = u16::from_str_radix(&word, 16).map_err(|_| {
+                format!("Не могу распарсить слово {} на строчке {}", word, line_num)
+            })?;
+            put(memory, address, value, line_num)?;
+            address += 1;
+        }
+    }
+    Ok(())
+}
+
+fn read_intel_hex(text: &str, memory: &mut [u16]) -> Result<(), String> {
+    // Базовый адрес из записей 02 и 04. Как и адреса записей, он в словах
+    let mut base = 0usize;
+    for (line, line_num) in text.lines().zip(1..) {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let bad = |what: &str| format!("{} на строчке {}", what, line_num);
+
+        let Some(digits) = line.strip_prefix(':') else {
+            return Err(bad("Запись должна начинаться с :"));
+        };
+        if digits.len() % 2 == 1 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(bad("Неверные шестнадцатеричные цифры"));
+        }
+        let bytes = (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect::<Vec<u8>>();
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(bad("Длина записи не совпадает с указанной"));
+        }
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(bad("Не сходится контрольная сумма"));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0 => {
+                if data.len() % 2 == 1 {
+                    return Err(bad("Нечетное число байт в записи"));
+                }
+                for (i, pair) in data.chunks(2).enumerate() {
+                    let word = u16::from_be_bytes([pair[0], pair[1]]);
+                    put(memory, base + address + i, word, line_num)?;
+                }
+            }
+            1 => return Ok(()),
+            2 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as usize) << 4,
+            4 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as usize) << 16,
+            3 | 5 => {}
+            kind => return Err(bad(&format!("Неизвестный тип записи {:02X}", kind))),
+        }
+    }
+    Err("Нет записи конца файла :00000001FF".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::MemoryCell;
+    use crate::parse::image::{read_image, write_image, ImageFormat};
+
+    #[test]
+    fn round_trip() {
+        let mut memory = vec![MemoryCell::new(); 0x12];
+        memory[0x0].set(0xF200);
+        memory[0x9].set(0x4011);
+        memory[0x11].set(0x00AB);
+        let words = memory.iter().map(MemoryCell::get).collect::<Vec<u16>>();
+
+        for format in ImageFormat::ALL {
+            let image = write_image(&memory, format);
+            assert_eq!(read_image(&image, memory.len(), format).unwrap(), words);
+        }
+
+        let hex = String::from_utf8(write_image(&memory, ImageFormat::IntelHex)).unwrap();
+        assert_eq!(
+            hex.lines().next().unwrap(),
+            ":10000000F2000000000000000000000000000000FE"
+        );
+        assert_eq!(hex.lines().last().unwrap(), ":00000001FF");
+        assert_eq!(
+            &write_image(&memory, ImageFormat::BinaryLittle)[..2],
+            &[0x00, 0xF2]
+        );
+    }
+
+    #[test]
+    fn reads_foreign_images() {
+        let memh = "// программа\n@10 f200 /* CLA */ 40_11\n@2\n0001";
+        let memory = read_image(memh.as_bytes(), 0x20, ImageFormat::ReadMemH).unwrap();
+        assert_eq!(
+            (memory[0x10], memory[0x11], memory[0x2]),
+            (0xF200, 0x4011, 1)
+        );
+
+        let hex = ":020000040000FA\n:02000A00F20002\n:00000001FF\n";
+        let memory = read_image(hex.as_bytes(), 0x20, ImageFormat::IntelHex).unwrap();
+        assert_eq!(memory[0xA], 0xF200);
+
+        let broken = ":02000A00F20003\n:00000001FF\n";
+        assert!(read_image(broken.as_bytes(), 0x20, ImageFormat::IntelHex).is_err());
+        assert!(read_image(&[0; 3], 0x20, ImageFormat::BinaryBig).is_err());
+        assert!(read_image(b"@20 1", 0x20, ImageFormat::ReadMemH).is_err());
+    }
+}
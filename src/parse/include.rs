@@ -0,0 +1,164 @@
+use crate::parse::diagnostic::{span_in, Diagnostic};
+use crate::parse::file::{parse_line, DataLine};
+use crate::parse::lexer::parse_string;
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Откуда взялась строка программы
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub file: Option<Rc<str>>,
+    pub line: u32,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{} в файле {}", self.line, file),
+            None => write!(f, "{}", self.line),
+        }
+    }
+}
+
+// Текст файла программы, чтобы показать его в окне исходника
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    pub name: Option<Rc<str>>,
+    // Полный путь: по нему одинаковые имена из разных папок не путаются
+    pub path: Option<PathBuf>,
+    pub lines: Vec<String>,
+    // Номер строки -> адрес ее первой ячейки. Заполняется после сборки
+    pub addresses: HashMap<u32, u16>,
+}
+
+impl SourceFile {
+    pub fn new(
+        name: Option<Rc<str>>,
+        path: Option<PathBuf>,
+        lines: &[(String, Location)],
+    ) -> SourceFile {
+        SourceFile {
+            name,
+            path,
+            lines: lines.iter().map(|(line, _)| line.clone()).collect(),
+            addresses: HashMap::new(),
+        }
+    }
+}
+
+// Имя подключенного файла для сообщений - путь от папки главного файла: lib/util.mm
+fn display_name(canonical: &Path, path: &Path, chain: &[PathBuf]) -> Rc<str> {
+    let root = chain.first().and_then(|main| main.parent());
+    let relative = root.and_then(|root| canonical.strip_prefix(root).ok());
+    relative.unwrap_or(path).display().to_string().into()
+}
+
+// Читает строки, пока они читаются. Ошибка чтения, например не UTF-8, попадает в diagnostics.
+pub(super) fn read_lines<R: BufRead>(
+    reader: R,
+    file: Option<Rc<str>>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<(String, Location)> {
+    let mut result = Vec::new();
+    for (line, line_num) in reader.lines().zip(1..).take(u16::MAX.into()) {
+        let location = Location {
+            file: file.clone(),
+            line: line_num,
+        };
+        match line {
+            Ok(line) => result.push((line, location)),
+            Err(e) => {
+                diagnostics.push(Diagnostic::error(location, "".into(), 0..0, e.to_string()));
+                break;
+            }
+        }
+    }
+    result
+}
+
+// Подставляет содержимое $include "lib.mm". Путь считается от папки файла, в котором
+// стоит $include. Цепочка открытых файлов нужна, чтобы найти циклы.
+// Текст каждого подключенного файла попадает в files.
+pub(super) fn expand_includes(
+    lines: Vec<(String, Location)>,
+    dir: Option<&Path>,
+    chain: &mut Vec<PathBuf>,
+    files: &mut Vec<SourceFile>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<(String, Location)> {
+    let mut result = Vec::new();
+
+    for (line, location) in lines {
+        let Some(DataLine::Operator("include", arg)) = parse_line(&line) else {
+            result.push((line, location));
+            continue;
+        };
+
+        macro_rules! fail {
+            ($msg:expr) => {{
+                let span = span_in(&line, arg);
+                diagnostics.push(Diagnostic::error(
+                    location,
+                    line.as_str().into(),
+                    span,
+                    $msg,
+                ));
+                continue;
+            }};
+        }
+
+        let name = match parse_string(arg) {
+            Ok(name) => name,
+            Err(e) => fail!(e),
+        };
+        let path = match dir {
+            Some(dir) => dir.join(&name),
+            None => PathBuf::from(&name),
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => fail!(format!("Не могу открыть файл {}: {}", path.display(), e)),
+        };
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+
+        if chain.contains(&canonical) {
+            let cycle = chain
+                .iter()
+                .skip_while(|p| **p != canonical)
+                .chain(Some(&canonical))
+                .map(|p| p.display().to_string())
+                .collect::<Vec<String>>();
+            fail!(format!(
+                "Циклическое подключение файлов: {}",
+                cycle.join(" -> ")
+            ));
+        }
+
+        let known = files
+            .iter()
+            .find(|file| file.path.as_ref() == Some(&canonical))
+            .and_then(|file| file.name.clone());
+        let name = known
+            .clone()
+            .unwrap_or_else(|| display_name(&canonical, &path, chain));
+        let included = read_lines(BufReader::new(file), Some(name.clone()), diagnostics);
+        if known.is_none() {
+            files.push(SourceFile::new(
+                Some(name),
+                Some(canonical.clone()),
+                &included,
+            ));
+        }
+        chain.push(canonical);
+        let included = expand_includes(included, path.parent(), chain, files, diagnostics);
+        chain.pop();
+        result.extend(included);
+    }
+
+    result
+}
@@ -0,0 +1,150 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+// Разбивает строку ассемблера на токены. Позиции токенов сохраняются,
+// чтобы можно было подменить кусок строки, не трогая остальное.
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Token<'a> {
+    // Мнемоника, имя регистра или шестнадцатеричное число
+    Word(&'a str),
+    // Ссылка на метку: %name
+    Label(&'a str),
+    // Символ в кавычках: 'A'
+    Char(char),
+    Op(char),
+    LParen,
+    RParen,
+    Other(char),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Spanned<'a> {
+    pub token: Token<'a>,
+    pub start: usize,
+    pub end: usize,
+}
+
+pub fn is_word_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
+
+pub fn is_operator(ch: char) -> bool {
+    matches!(ch, '+' | '-' | '*' | '/')
+}
+
+pub fn tokenize(s: &str) -> Vec<Spanned<'_>> {
+    let mut result = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((start, ch)) = chars.next() {
+        if ch.is_whitespace() {
+            continue;
+        }
+
+        let token = if is_word_char(ch) || ch == '%' {
+            let mut end = start + ch.len_utf8();
+            while let Some(&(i, c)) = chars.peek() {
+                if !is_word_char(c) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let text = &s[start..end];
+            let token = match text.strip_prefix('%') {
+                Some("") => Token::Other('%'),
+                Some(name) => Token::Label(name),
+                None => Token::Word(text),
+            };
+            result.push(Spanned { token, start, end });
+            continue;
+        } else if ch == '\'' {
+            let mut literal = chars.clone();
+            match read_char(&mut literal) {
+                Ok(value) if literal.next().map(|(_, c)| c) == Some('\'') => {
+                    let end = literal.peek().map_or(s.len(), |&(i, _)| i);
+                    chars = literal;
+                    result.push(Spanned {
+                        token: Token::Char(value),
+                        start,
+                        end,
+                    });
+                    continue;
+                }
+                _ => Token::Other(ch),
+            }
+        } else if is_operator(ch) {
+            Token::Op(ch)
+        } else if ch == '(' {
+            Token::LParen
+        } else if ch == ')' {
+            Token::RParen
+        } else {
+            Token::Other(ch)
+        };
+
+        result.push(Spanned {
+            token,
+            start,
+            end: start + ch.len_utf8(),
+        });
+    }
+
+    result
+}
+
+// Символ внутри кавычек, с поддержкой \n, \t, \0 и экранирования кавычек
+fn read_char(chars: &mut Peekable<CharIndices>) -> Result<char, String> {
+    match chars.next().map(|(_, c)| c) {
+        Some('\\') => match chars.next().map(|(_, c)| c) {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('0') => Ok('\0'),
+            Some(c @ ('\\' | '\'' | '"')) => Ok(c),
+            Some(c) => Err(format!("Неизвестная escape-последовательность \\{c}")),
+            None => Err("Не закрытая кавычка".to_string()),
+        },
+        Some(c) => Ok(c),
+        None => Err("Не закрытая кавычка".to_string()),
+    }
+}
+
+// Строка в двойных кавычках целиком, например "Привет\n"
+pub fn parse_string(s: &str) -> Result<String, String> {
+    let Some(inner) = s.trim().strip_prefix('"') else {
+        return Err(format!("Ожидалась строка в кавычках, получено {}", s));
+    };
+
+    let mut chars = inner.char_indices().peekable();
+    let mut result = String::new();
+    loop {
+        if let Some(&(i, '"')) = chars.peek() {
+            if !inner[i + 1..].trim().is_empty() {
+                return Err(format!(
+                    "Неожиданные штуки после строки: {}",
+                    &inner[i + 1..]
+                ));
+            }
+            return Ok(result);
+        }
+        result.push(read_char(&mut chars)?);
+    }
+}
+
+// Числа по умолчанию записываются в системе radix. Префикс 0x всегда означает
+// шестнадцатеричное число, а 0b - двоичное, если только по умолчанию не hex
+pub fn parse_number(word: &str, radix: u32) -> Option<i64> {
+    let lower = word.to_lowercase();
+    let (digits, radix) = if let Some(digits) = lower.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = lower.strip_prefix("0b").filter(|_| radix != 16) {
+        (digits, 2)
+    } else {
+        (lower.as_str(), radix)
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    i64::from_str_radix(digits, radix).ok()
+}
@@ -0,0 +1,120 @@
+use crate::parse::file::{Origin, Program};
+use crate::parse::{CommandInfo, Parser};
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Листинг для отчета: адрес, код, двоичный код, мнемоника, метки и строка исходника.
+// В конце таблица меток и констант.
+pub fn listing<I: CommandInfo, P: Parser<I>>(program: &Program, parser: &P) -> String {
+    let mut labels = HashMap::<u16, Vec<&str>>::new();
+    for label in &program.labels {
+        labels.entry(label.pos).or_default().push(&label.name);
+    }
+    let labels = labels
+        .into_iter()
+        .map(|(pos, names)| (pos, names.join(", ")))
+        .collect::<HashMap<u16, String>>();
+    let label_width = labels
+        .values()
+        .map(|names| names.chars().count())
+        .chain(Some("Метка".chars().count()))
+        .max()
+        .unwrap_or(0);
+
+    let mut result = String::new();
+    let _ = writeln!(
+        result,
+        "Адрес  Код   Двоичный             {:<16}{:<label_width$}  Строка  Исходник",
+        "Мнемоника", "Метка"
+    );
+
+    let mut file = None;
+    let mut previous: Option<&Origin> = None;
+    for cell in &program.cells {
+        let origin = &cell.origin;
+        // Ячейки из подключенных файлов идут под заголовком с именем файла
+        if origin.location.file != file {
+            file = origin.location.file.clone();
+            if let Some(file) = &file {
+                let _ = writeln!(result, "; файл {file}");
+            }
+        }
+
+        let mnemonic = if cell.code {
+            parser.parse(cell.value).mnemonic()
+        } else {
+            String::new()
+        };
+        let binary = format!("{:016b}", cell.value)
+            .as_bytes()
+            .chunks(4)
+            .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+            .collect::<Vec<String>>()
+            .join(" ");
+        let label = labels.get(&cell.pos).map_or("", |l| l.as_str());
+        // Строку исходника пишем один раз, даже если из нее несколько ячеек
+        let same_line = previous.is_some_and(|previous| previous.same_line(origin));
+        let (line, source) = if same_line {
+            (String::new(), "")
+        } else {
+            (origin.location.line.to_string(), origin.source.trim_end())
+        };
+        previous = Some(origin);
+
+        let row = format!(
+            "{:03X}    {:04X}  {}  {:<16}{:<label_width$}  {:>6}  {}",
+            cell.pos, cell.value, binary, mnemonic, label, line, source
+        );
+        let _ = writeln!(result, "{}", row.trim_end());
+    }
+
+    if !program.labels.is_empty() || !program.constants.is_empty() {
+        let _ = writeln!(result, "\nТаблица символов");
+    }
+    let mut sorted = program.labels.iter().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| (a.pos, &a.name).cmp(&(b.pos, &b.name)));
+    for label in sorted {
+        let _ = writeln!(result, "{:<label_width$}  {:03X}", label.name, label.pos);
+    }
+    for (name, value) in &program.constants {
+        let _ = writeln!(result, "{:<label_width$}  {:X} ($equ)", name, value);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::file::parse_file;
+    use crate::parse::general::GeneralParser;
+    use crate::parse::listing::listing;
+    use crate::profile::MachineProfile;
+
+    #[test]
+    fn program_listing() {
+        let parser = GeneralParser::new(&MachineProfile::Classic.default_config());
+        let program = "$equ DISPLAY 3
+$pos 10
+CLA $start # начало
+OUT %DISPLAY
+BR %start
+$string \"AB\" $text
+";
+        let program = parse_file(&mut program.as_bytes(), None, &parser, 0x7FF).unwrap();
+        let listing = listing(&program, &parser);
+        let lines = listing.lines().collect::<Vec<&str>>();
+
+        assert_eq!(
+            lines[1],
+            "010    F200  1111 0010 0000 0000  CLA             start       3  CLA $start # начало"
+        );
+        assert_eq!(
+            lines[3],
+            "012    C010  1100 0000 0001 0000  BR 010                      5  BR %start"
+        );
+        assert_eq!(lines[5], "014    0000  0000 0000 0000 0000");
+        assert!(lines.contains(&"text   013"));
+        assert!(lines.contains(&"DISPLAY  3 ($equ)"));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::parse::diagnostic::{span_in, Diagnostic};
+use crate::parse::file::{parse_line, split_arguments, split_label, DataLine};
+use crate::parse::include::Location;
+use crate::parse::lexer::{tokenize, Token};
+
+use std::collections::HashMap;
+
+// Макрос вызывает другие макросы, но не бесконечно
+const MAX_DEPTH: usize = 64;
+
+struct Macro {
+    params: Vec<String>,
+    // Метки, объявленные в теле. В каждой подстановке они получают свое имя
+    locals: Vec<String>,
+    body: Vec<String>,
+}
+
+// Раскрывает $macro NAME a, b ... $endm. Строки подстановки получают номер строки вызова.
+pub(super) fn expand_macros(
+    lines: Vec<(String, Location)>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<(String, Location)> {
+    let mut macros = HashMap::<String, Macro>::new();
+    let mut result = Vec::new();
+    let mut counter = 0;
+    let mut lines = lines.into_iter();
+
+    'lines: while let Some((line, location)) = lines.next() {
+        macro_rules! fail {
+            ($part:expr, $msg:expr) => {{
+                let span = span_in(&line, $part);
+                diagnostics.push(Diagnostic::error(
+                    location,
+                    line.as_str().into(),
+                    span,
+                    $msg,
+                ));
+                continue 'lines;
+            }};
+        }
+
+        match parse_line(&line) {
+            Some(DataLine::Operator("macro", arg)) => {
+                let (name, params) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+                if name.is_empty() {
+                    fail!(line.trim(), "Не указано имя макроса");
+                }
+                let params = split_arguments(params)
+                    .into_iter()
+                    .filter(|param| !param.is_empty())
+                    .map(str::to_string)
+                    .collect::<Vec<String>>();
+
+                let mut body = Vec::new();
+                let mut locals = Vec::new();
+                loop {
+                    let Some((body_line, body_location)) = lines.next() else {
+                        fail!(name, format!("Макрос {name} не закрыт через $endm"));
+                    };
+                    match parse_line(&body_line) {
+                        Some(DataLine::Operator("endm", _)) => break,
+                        Some(DataLine::Operator("macro", _)) => {
+                            let span = span_in(&body_line, body_line.trim());
+                            diagnostics.push(Diagnostic::error(
+                                body_location,
+                                body_line.as_str().into(),
+                                span,
+                                "Макрос нельзя объявить внутри другого макроса",
+                            ));
+                            continue;
+                        }
+                        Some(DataLine::Operator(directive, arg)) => {
+                            let (arg, label) = split_label(arg);
+                            // $label loop объявляет метку так же, как CLA $loop
+                            if directive == "label" {
+                                locals.push(arg.to_string());
+                            }
+                            locals.extend(label.map(str::to_string))
+                        }
+                        Some(DataLine::Command(_, label)) => {
+                            locals.extend(label.map(str::to_string))
+                        }
+                        None => continue,
+                    }
+                    body.push(body_line);
+                }
+
+                macros.insert(
+                    name.to_string(),
+                    Macro {
+                        params,
+                        locals,
+                        body,
+                    },
+                );
+            }
+            Some(DataLine::Operator("endm", _)) => fail!(line.trim(), "$endm без $macro"),
+            _ => {
+                if let Err(e) = expand_line(
+                    &macros,
+                    line.clone(),
+                    &location,
+                    &mut counter,
+                    0,
+                    &mut result,
+                ) {
+                    fail!(line.trim(), e);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn expand_line(
+    macros: &HashMap<String, Macro>,
+    line: String,
+    location: &Location,
+    counter: &mut usize,
+    depth: usize,
+    result: &mut Vec<(String, Location)>,
+) -> Result<(), String> {
+    let Some(DataLine::Command(command, label)) = parse_line(&line) else {
+        result.push((line, location.clone()));
+        return Ok(());
+    };
+    let (name, args) = command
+        .split_once(char::is_whitespace)
+        .unwrap_or((command, ""));
+    let Some(definition) = macros.get(name) else {
+        result.push((line, location.clone()));
+        return Ok(());
+    };
+
+    if depth >= MAX_DEPTH {
+        return Err(format!("Слишком глубокая вложенность макросов в {name}"));
+    }
+
+    let args = if args.trim().is_empty() {
+        vec![]
+    } else {
+        split_arguments(args)
+    };
+    if args.len() != definition.params.len() {
+        return Err(format!(
+            "Макрос {name} ожидает параметров: {}, передано: {}",
+            definition.params.len(),
+            args.len()
+        ));
+    }
+
+    *counter += 1;
+    let mut replacements = HashMap::new();
+    for (param, arg) in definition.params.iter().zip(args) {
+        replacements.insert(param.as_str(), arg.to_string());
+    }
+    let mut renamed = HashMap::new();
+    for local in &definition.locals {
+        let unique = format!("{local}__{counter}");
+        replacements.insert(local.as_str(), format!("%{unique}"));
+        renamed.insert(local.as_str(), unique);
+    }
+    let rename = |label: Option<&str>| {
+        label
+            .map(|label| format!(" ${}", renamed.get(label).map_or(label, |l| l.as_str())))
+            .unwrap_or_default()
+    };
+
+    // Метка на вызове макроса указывает на первую ячейку подстановки
+    if let Some(label) = label {
+        result.push((format!("$label {label}"), location.clone()));
+    }
+
+    for body_line in &definition.body {
+        let expanded = match parse_line(body_line) {
+            Some(DataLine::Operator(directive, arg)) => {
+                let (arg, label) = split_label(arg);
+                let arg = match directive {
+                    "label" => renamed.get(arg).map_or(arg, |l| l.as_str()).to_string(),
+                    _ => substitute(arg, &replacements),
+                };
+                format!("${directive} {arg}{}", rename(label))
+            }
+            Some(DataLine::Command(command, label)) => {
+                format!("{}{}", substitute(command, &replacements), rename(label))
+            }
+            None => continue,
+        };
+        expand_line(macros, expanded, location, counter, depth + 1, result)?;
+    }
+
+    Ok(())
+}
+
+// Заменяет %name на текст параметра или новое имя локальной метки
+fn substitute(text: &str, replacements: &HashMap<&str, String>) -> String {
+    let mut result = String::new();
+    let mut copied = 0;
+    for token in tokenize(text) {
+        let Token::Label(name) = token.token else {
+            continue;
+        };
+        if let Some(replacement) = replacements.get(name) {
+            result.push_str(&text[copied..token.start]);
+            result.push_str(replacement);
+            copied = token.end;
+        }
+    }
+    result.push_str(&text[copied..]);
+    result
+}
@@ -3,12 +3,14 @@ use crate::parse::{CommandInfo, Parser};
 use crate::utils::bit_registers::*;
 use core::ops::*;
 
+#[cfg(feature = "gui")]
 use imgui::Ui;
 
 struct RangeDescriptor {
     range: Range<u16>,
     short_description: &'static str,
     explained: &'static str,
+    variants: Vec<String>,
 }
 
 struct MicroCommandDescriptor {
@@ -27,15 +29,30 @@ impl RangeDescriptor {
         }
     }
 
+    fn get(&self, opcode: u16) -> u16 {
+        let mask = 1u32.shl(self.range.len() as u32) - 1;
+        (opcode as u32).shr(self.range.start as u32).bitand(mask) as u16
+    }
+
+    fn set(&self, opcode: u16, value: u16) -> u16 {
+        let mask = (1u32.shl(self.range.len() as u32) - 1).shl(self.range.start as u32);
+        let value = (value as u32).shl(self.range.start as u32).bitand(mask);
+        (opcode as u32)
+            .bitand(mask.bitxor(0xFFFF_FFFF))
+            .bitor(value) as u16
+    }
+
     fn new(
         range: Range<u16>,
         short_description: &'static str,
         explained: &'static str,
+        variants: Vec<String>,
     ) -> RangeDescriptor {
         RangeDescriptor {
             range,
             short_description,
             explained,
+            variants,
         }
     }
 }
@@ -51,6 +68,16 @@ impl MicroCommandDescriptor {
     fn bit(&mut self, bit: u16, short_description: &'static str, explained: &'static str) {
         self.range(bit, bit, short_description, explained)
     }
+
+    fn flag(&mut self, bit: u16, short_description: &'static str, explained: &'static str) {
+        self.choice(
+            bit,
+            bit,
+            short_description,
+            explained,
+            &["0 - нет", "1 - да"],
+        )
+    }
     fn range(
         &mut self,
         from: u16,
@@ -62,9 +89,70 @@ impl MicroCommandDescriptor {
             to..from + 1,
             short_description,
             explained,
+            vec![],
         ))
     }
 
+    // Поле, которое можно редактировать выбором одного из вариантов.
+    // Варианты идут в порядке значений поля начиная с нуля.
+    fn choice(
+        &mut self,
+        from: u16,
+        to: u16,
+        short_description: &'static str,
+        explained: &'static str,
+        variants: &[&str],
+    ) {
+        self.descriptors.push(RangeDescriptor::new(
+            to..from + 1,
+            short_description,
+            explained,
+            variants.iter().map(|v| v.to_string()).collect(),
+        ))
+    }
+
+    // Числовое поле. Варианты - все числа, которые в него влезают.
+    fn numeric(
+        &mut self,
+        from: u16,
+        to: u16,
+        short_description: &'static str,
+        explained: &'static str,
+    ) {
+        let count = 1u32.shl(from - to + 1);
+        let width = ((from - to) / 4 + 1) as usize;
+        self.descriptors.push(RangeDescriptor::new(
+            to..from + 1,
+            short_description,
+            explained,
+            (0..count)
+                .map(|v| format!("{:0>width$X} ({})", v, v, width = width))
+                .collect(),
+        ))
+    }
+
+    #[cfg(feature = "gui")]
+    fn draw_editor(&self, ui: &Ui, opcode: u16) -> Option<u16> {
+        let mut result = None;
+
+        for descriptor in self.descriptors.iter().filter(|d| !d.variants.is_empty()) {
+            let mut selected = descriptor.get(opcode) as usize;
+            if ui.combo_simple_string(
+                descriptor.short_description,
+                &mut selected,
+                &descriptor.variants,
+            ) {
+                result = Some(descriptor.set(result.unwrap_or(opcode), selected as u16));
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text(descriptor.explained)
+            }
+        }
+
+        result
+    }
+
+    #[cfg(feature = "gui")]
     fn make_description(&self, ui: &Ui, cmd: &dyn MicroCommand) {
         let opcode = cmd.opcode();
 
@@ -122,7 +210,10 @@ pub enum ExecutionResult {
 pub trait MicroCommand {
     fn run(&self, computer: &mut Computer) -> ExecutionResult;
     fn mnemonic(&self) -> String;
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui);
+    #[cfg(feature = "gui")]
+    fn draw_editor(&self, ui: &Ui) -> Option<u16>;
     fn opcode(&self) -> u16;
     fn horizontal(&self) -> u32;
 }
@@ -152,9 +243,30 @@ impl CommandInfo for MicroCommandInfo {
         self.command.mnemonic()
     }
 
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui) {
         self.command.draw_highlight(ui)
     }
+
+    #[cfg(feature = "gui")]
+    fn draw_editor(&self, ui: &Ui) -> Option<u16> {
+        let opcode = self.command.opcode();
+
+        let mut kind = sub_sum(opcode, 15, 14).min(2) as usize;
+        if ui.combo_simple_string(
+            "Тип микрокоманды",
+            &mut kind,
+            &["Операционная 0", "Операционная 1", "Управляющая"],
+        ) {
+            return Some(match kind {
+                0 => opcode.bitand(0x3FFF),
+                1 => opcode.bitand(0x3FFF).bitor(0x4000),
+                _ => opcode.bitor(0x8000),
+            });
+        }
+
+        self.command.draw_editor(ui)
+    }
 }
 
 pub struct McParser;
@@ -229,50 +341,14 @@ impl MicroCommand for ControlCommand {
         )
     }
 
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui) {
-        let description = "Эта микрокоманда нужна для организации условных переходов в мпу.\n\n\
-        Работает все довольно просто:\n\
-        1. Берем регистр который указан в поле \"Проверяемый регистр\"\n\
-        2. Сравниваем его бит, номер которого записан в поле \"Проверяемый бит\" с битом сравнения.\n\
-        3. Если они совпадают, присваиваем значение поля \"Адрес перехода\" регистру СчМК. Иначе делаем \
-        ничего
-        ";
-
-        let mut descriptor = MicroCommandDescriptor::new(description);
-
-        descriptor.bit(
-            15,
-            "Код операции",
-            "Означает, что эта команда является операционной командой",
-        );
-        descriptor.bit(
-            14,
-            "Бит сравнения",
-            "Прыжок будет совершен если сравниваемый бит совпадет с этим",
-        );
-
-        descriptor.range(
-            13,
-            12,
-            "Проверяемый регистр",
-            "Из этого регистра мы возьмем проверяемый бит.\n\
-        00 - РС\n\
-        01 - РД\n\
-        10 - РК\n\
-        11 - А
-        ",
-        );
-
-        descriptor.range(
-            11,
-            8,
-            "Проверяемый бит",
-            "Номер бита, который нам нужно сравнить.",
-        );
-
-        descriptor.range(7, 0, "Адрес перехода", "В случае когда проверяемый бит совпадет с битом сравнения в СчМК будет присвоено это значение");
+        Self::descriptor().make_description(ui, self)
+    }
 
-        descriptor.make_description(ui, self)
+    #[cfg(feature = "gui")]
+    fn draw_editor(&self, ui: &Ui) -> Option<u16> {
+        Self::descriptor().draw_editor(ui, self.0)
     }
 
     fn opcode(&self) -> u16 {
@@ -369,7 +445,13 @@ impl MicroCommand for OperationalCommand0 {
                     .general_memory
                     .borrow_mut()
                     .data
-                    .get_mut(computer.registers.r_address.bitand(0x7FF) as usize)
+                    .get_mut(
+                        computer
+                            .registers
+                            .r_address
+                            .bitand(computer.config.address_mask())
+                            as usize,
+                    )
                     .unwrap()
                     .set(computer.registers.r_data);
                 computer.log(
@@ -385,7 +467,13 @@ impl MicroCommand for OperationalCommand0 {
                     .general_memory
                     .borrow_mut()
                     .data
-                    .get_mut(computer.registers.r_address.bitand(0x7FF) as usize)
+                    .get_mut(
+                        computer
+                            .registers
+                            .r_address
+                            .bitand(computer.config.address_mask())
+                            as usize,
+                    )
                     .unwrap()
                     .get();
                 computer.log(
@@ -470,7 +558,7 @@ impl MicroCommand for OperationalCommand0 {
                     ),
                 );
                 computer.registers.r_buffer =
-                    (right as u32).wrapping_add(left.wrapping_add(1) as u32);
+                    (right as u32).wrapping_add(left as u32).wrapping_add(1);
             }
             Operation::LeftAndRight => {
                 computer.log(
@@ -507,89 +595,14 @@ impl MicroCommand for OperationalCommand0 {
         format!("{}{}", expression, memory)
     }
 
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui) {
-        let desc = "Операционная команда 0\n\n\
-            Ее предназначение - работа с основной памятью, побитовые сдвиги и арифметические действия";
-
-        let mut descriptor = MicroCommandDescriptor::new(desc);
-
-        descriptor.range(
-            15,
-            14,
-            "Код операции",
-            "Означает, что эта команда является операционной командой 0",
-        );
-        descriptor.range(
-            13,
-            12,
-            "Левый вход",
-            "Регистр который будет выполнять роль левого операнда.\n\
-        00 - 0 - это не регистр. Это просто ноль.\n\
-        01 - А\n\
-        10 - РС\n\
-        11 - РК",
-        );
-        descriptor.range(
-            11,
-            10,
-            "Пустое место",
-            "Это просто бесполезные биты. Не важны что тут будет. Они бесполезны.",
-        );
-        descriptor.range(
-            9,
-            8,
-            "Правый вход",
-            "Регистр, который будет выполнять роль правого операнда.\n\
-        00 - 0 - это не регистр. Это просто ноль.\n\
-        01 - РД\n\
-        10 - РК\n\
-        11 - СК",
-        );
-        descriptor.range(
-            7,
-            6,
-            "Обратный код",
-            "От какого операнда мы будем искать обратный код.\n\
-        Oбратный код это когда единицы на нули и нули на единицы\n\
-        00 - ни от какого\n\
-        01 - от левого\n\
-        10 - от правого\n\
-        11 - ни от какого",
-        );
-        descriptor.range(
-            5,
-            4,
-            "Операция",
-            "Вид операции которую мы применим к операндам:\n\
-        00 - Левый + Правый\n\
-        01 - Левый + Правый + 1\n\
-        10 - Левый & Правый(& - побитовое И)\n\
-        11 - Левый + Правый",
-        );
-        descriptor.range(
-            3,
-            2,
-            "Сдвиг",
-            "Это поле - чад. Если мы что-то сдвигаем, то больше ничего не делаем.\n\
-        Результат бинарного сдвига попадает в БР\n\
-        Сдвигаем мы регистр A\n\
-        00 - нет сдвига\n\
-        01 - сдвиг вправо\n\
-        10 - сдвиг влево\n\
-        11 - нет сдвига\n",
-        );
-        descriptor.range(
-            1,
-            0,
-            "Память",
-            "\
-        00 - нет обмена\n\
-        01 - чтение: возьми из ячейки, адрес которой лежит в РА, основной памяти и положи в РД\n\
-        10 - запись: наоборот\n\
-        11 - нет обмена",
-        );
+        Self::descriptor().make_description(ui, self)
+    }
 
-        descriptor.make_description(ui, self);
+    #[cfg(feature = "gui")]
+    fn draw_editor(&self, ui: &Ui) -> Option<u16> {
+        Self::descriptor().draw_editor(ui, self.0)
     }
 
     fn opcode(&self) -> u16 {
@@ -731,6 +744,9 @@ impl MicroCommand for OperationalCommand1 {
                         false,
                         "Установил флаг переноса и убрал лишнюю единицу у БР".to_string(),
                     );
+                } else {
+                    computer.registers.set_overflow(false);
+                    computer.log(false, "Сбросил флаг переноса".to_string());
                 }
             }
             CUpdate::SetOne => {
@@ -826,86 +842,14 @@ impl MicroCommand for OperationalCommand1 {
         format!("{}{}{}{}", io, c, nz, updated)
     }
 
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui) {
-        let desc = "Операционная команда 1\n\n\
-        Эта команда - универсальный боец. В нее пихнули все что не поместилось в другие.\n\
-        Но стоит выделить, что если операционная команда 0 изменяет только регистр БР, то эта команда \
-        умеет пересылать из БР в какой-нибудь другой регистр. Таким образом эти команды часто работают \
-        в паре.
-        ";
-
-        let mut descriptor = MicroCommandDescriptor::new(desc);
-
-        descriptor.range(
-            15,
-            14,
-            "Код операции",
-            "Означает, что эта команда является операционной командой 1",
-        );
-        descriptor.range(
-            13,
-            12,
-            "Пустое место",
-            "Это просто бесполезные биты. Не важны что тут будет. Они бесполезны.",
-        );
-        descriptor.bit(
-            11,
-            "Включить прерывания",
-            "Если 1, прерывания будут разрешены.",
-        );
-        descriptor.bit(10, "Выключить прерывания", "Если 1, прерывания будут запрещены.\nЕсли совместить с предыдущим флагом, не произойдет ничего.");
-        descriptor.bit(
-            9,
-            "Сброс готовности ВУ",
-            "Если 1, у всех ВУ будет сброшен флаг готовности.",
-        );
-        descriptor.bit(
-            8,
-            "Запуск контролера ВУ",
-            "Вот тут начинается черная магия.\n\
-        Если 1:\n\
-        1. Если РК не равно РД не делаем ничего\n\
-        2. Иначе устанавливаем 12 бит регистра РС\n\
-        3. Установка этого бита приводит в действие контролер ВУ\n\
-        4. Далее читайте описание 12 бита РС",
-        );
-        descriptor.range(
-            7,
-            6,
-            "Регистр С",
-            "Этот бит задает вид взаимодействия с 0 битом регистра РС.\n\
-        00 - нет взаимодействия\n\
-        01 - если 16 бит регистра БР равен 1, то устанавливаем С в единицу и убираем 16 бит у БР\n\
-        10 - устанавливаем С в 0\n\
-        11 - устанавливаем С в 1",
-        );
-        descriptor.bit(
-            5,
-            "Регистр N",
-            "Если 1 и если БР меньше 0, то есть 15 бит равен 1, N будет установлен в 1",
-        );
-        descriptor.bit(
-            4,
-            "Регистр Z",
-            "Если 1 и если БР равен 0, Z будет установлен в 1",
-        );
-        descriptor.bit(3, "Остановочка", "Завершает роботу эвм. Чаще всего это говорит о том что команда из основной памяти выполнена.");
-        descriptor.range(
-            2,
-            0,
-            "Выход АЛУ",
-            "Говорит о том куда пересылать содержимое БР\n\
-        000 - никуда\n\
-        001 - в РА\n\
-        010 - в РД\n\
-        011 - в РК\n\
-        100 - в СК\n\
-        101 - в А\n\
-        110 - никуда\n\
-        111 - в РА, РД, РК и А",
-        );
+        Self::descriptor().make_description(ui, self)
+    }
 
-        descriptor.make_description(ui, self);
+    #[cfg(feature = "gui")]
+    fn draw_editor(&self, ui: &Ui) -> Option<u16> {
+        Self::descriptor().draw_editor(ui, self.0)
     }
 
     fn opcode(&self) -> u16 {
@@ -1008,6 +952,117 @@ enum Operation {
 }
 
 impl OperationalCommand0 {
+    fn descriptor() -> MicroCommandDescriptor {
+        let desc = "Операционная команда 0\n\n\
+            Ее предназначение - работа с основной памятью, побитовые сдвиги и арифметические действия";
+
+        let mut descriptor = MicroCommandDescriptor::new(desc);
+
+        descriptor.range(
+            15,
+            14,
+            "Код операции",
+            "Означает, что эта команда является операционной командой 0",
+        );
+        descriptor.choice(
+            13,
+            12,
+            "Левый вход",
+            "Регистр который будет выполнять роль левого операнда.\n\
+        00 - 0 - это не регистр. Это просто ноль.\n\
+        01 - А\n\
+        10 - РС\n\
+        11 - РК",
+            &["00 - 0", "01 - А", "10 - РС", "11 - РК"],
+        );
+        descriptor.range(
+            11,
+            10,
+            "Пустое место",
+            "Это просто бесполезные биты. Не важны что тут будет. Они бесполезны.",
+        );
+        descriptor.choice(
+            9,
+            8,
+            "Правый вход",
+            "Регистр, который будет выполнять роль правого операнда.\n\
+        00 - 0 - это не регистр. Это просто ноль.\n\
+        01 - РД\n\
+        10 - РК\n\
+        11 - СК",
+            &["00 - 0", "01 - РД", "10 - РК", "11 - СК"],
+        );
+        descriptor.choice(
+            7,
+            6,
+            "Обратный код",
+            "От какого операнда мы будем искать обратный код.\n\
+        Oбратный код это когда единицы на нули и нули на единицы\n\
+        00 - ни от какого\n\
+        01 - от левого\n\
+        10 - от правого\n\
+        11 - ни от какого",
+            &[
+                "00 - ни от какого",
+                "01 - от левого",
+                "10 - от правого",
+                "11 - ни от какого",
+            ],
+        );
+        descriptor.choice(
+            5,
+            4,
+            "Операция",
+            "Вид операции которую мы применим к операндам:\n\
+        00 - Левый + Правый\n\
+        01 - Левый + Правый + 1\n\
+        10 - Левый & Правый(& - побитовое И)\n\
+        11 - Левый + Правый",
+            &[
+                "00 - Левый + Правый",
+                "01 - Левый + Правый + 1",
+                "10 - Левый & Правый",
+                "11 - Левый + Правый",
+            ],
+        );
+        descriptor.choice(
+            3,
+            2,
+            "Сдвиг",
+            "Это поле - чад. Если мы что-то сдвигаем, то больше ничего не делаем.\n\
+        Результат бинарного сдвига попадает в БР\n\
+        Сдвигаем мы регистр A\n\
+        00 - нет сдвига\n\
+        01 - сдвиг вправо\n\
+        10 - сдвиг влево\n\
+        11 - нет сдвига\n",
+            &[
+                "00 - нет сдвига",
+                "01 - сдвиг вправо",
+                "10 - сдвиг влево",
+                "11 - нет сдвига",
+            ],
+        );
+        descriptor.choice(
+            1,
+            0,
+            "Память",
+            "\
+        00 - нет обмена\n\
+        01 - чтение: возьми из ячейки, адрес которой лежит в РА, основной памяти и положи в РД\n\
+        10 - запись: наоборот\n\
+        11 - нет обмена",
+            &[
+                "00 - нет обмена",
+                "01 - чтение",
+                "10 - запись",
+                "11 - нет обмена",
+            ],
+        );
+
+        descriptor
+    }
+
     pub fn left_input(&self) -> Option<Register> {
         let b13 = bit_at(self.0, 13);
         let b12 = bit_at(self.0, 12);
@@ -1126,6 +1181,104 @@ pub enum IOControl {
 }
 
 impl OperationalCommand1 {
+    fn descriptor() -> MicroCommandDescriptor {
+        let desc = "Операционная команда 1\n\n\
+        Эта команда - универсальный боец. В нее пихнули все что не поместилось в другие.\n\
+        Но стоит выделить, что если операционная команда 0 изменяет только регистр БР, то эта команда \
+        умеет пересылать из БР в какой-нибудь другой регистр. Таким образом эти команды часто работают \
+        в паре.
+        ";
+
+        let mut descriptor = MicroCommandDescriptor::new(desc);
+
+        descriptor.range(
+            15,
+            14,
+            "Код операции",
+            "Означает, что эта команда является операционной командой 1",
+        );
+        descriptor.range(
+            13,
+            12,
+            "Пустое место",
+            "Это просто бесполезные биты. Не важны что тут будет. Они бесполезны.",
+        );
+        descriptor.flag(
+            11,
+            "Включить прерывания",
+            "Если 1, прерывания будут разрешены.",
+        );
+        descriptor.flag(10, "Выключить прерывания", "Если 1, прерывания будут запрещены.\nЕсли совместить с предыдущим флагом, не произойдет ничего.");
+        descriptor.flag(
+            9,
+            "Сброс готовности ВУ",
+            "Если 1, у всех ВУ будет сброшен флаг готовности.",
+        );
+        descriptor.flag(
+            8,
+            "Запуск контролера ВУ",
+            "Вот тут начинается черная магия.\n\
+        Если 1:\n\
+        1. Если РК не равно РД не делаем ничего\n\
+        2. Иначе устанавливаем 12 бит регистра РС\n\
+        3. Установка этого бита приводит в действие контролер ВУ\n\
+        4. Далее читайте описание 12 бита РС",
+        );
+        descriptor.choice(
+            7,
+            6,
+            "Регистр С",
+            "Этот бит задает вид взаимодействия с 0 битом регистра РС.\n\
+        00 - нет взаимодействия\n\
+        01 - если 16 бит регистра БР равен 1, то устанавливаем С в единицу и убираем 16 бит у БР\n\
+        10 - устанавливаем С в 0\n\
+        11 - устанавливаем С в 1",
+            &[
+                "00 - нет взаимодействия",
+                "01 - C = БР[16]",
+                "10 - C = 0",
+                "11 - C = 1",
+            ],
+        );
+        descriptor.flag(
+            5,
+            "Регистр N",
+            "Если 1 и если БР меньше 0, то есть 15 бит равен 1, N будет установлен в 1",
+        );
+        descriptor.flag(
+            4,
+            "Регистр Z",
+            "Если 1 и если БР равен 0, Z будет установлен в 1",
+        );
+        descriptor.flag(3, "Остановочка", "Завершает роботу эвм. Чаще всего это говорит о том что команда из основной памяти выполнена.");
+        descriptor.choice(
+            2,
+            0,
+            "Выход АЛУ",
+            "Говорит о том куда пересылать содержимое БР\n\
+        000 - никуда\n\
+        001 - в РА\n\
+        010 - в РД\n\
+        011 - в РК\n\
+        100 - в СК\n\
+        101 - в А\n\
+        110 - никуда\n\
+        111 - в РА, РД, РК и А",
+            &[
+                "000 - никуда",
+                "001 - в РА",
+                "010 - в РД",
+                "011 - в РК",
+                "100 - в СК",
+                "101 - в А",
+                "110 - никуда",
+                "111 - в РА, РД, РК и А",
+            ],
+        );
+
+        descriptor
+    }
+
     pub fn hlt(&self) -> bool {
         bit_at(self.0, 3)
     }
@@ -1209,6 +1362,60 @@ impl OperationalCommand1 {
 }
 
 impl ControlCommand {
+    fn descriptor() -> MicroCommandDescriptor {
+        let description = "Эта микрокоманда нужна для организации условных переходов в мпу.\n\n\
+        Работает все довольно просто:\n\
+        1. Берем регистр который указан в поле \"Проверяемый регистр\"\n\
+        2. Сравниваем его бит, номер которого записан в поле \"Проверяемый бит\" с битом сравнения.\n\
+        3. Если они совпадают, присваиваем значение поля \"Адрес перехода\" регистру СчМК. Иначе делаем \
+        ничего
+        ";
+
+        let mut descriptor = MicroCommandDescriptor::new(description);
+
+        descriptor.bit(
+            15,
+            "Код операции",
+            "Означает, что эта команда является операционной командой",
+        );
+        descriptor.choice(
+            14,
+            14,
+            "Бит сравнения",
+            "Прыжок будет совершен если сравниваемый бит совпадет с этим",
+            &["0", "1"],
+        );
+
+        descriptor.choice(
+            13,
+            12,
+            "Проверяемый регистр",
+            "Из этого регистра мы возьмем проверяемый бит.\n\
+        00 - РС\n\
+        01 - РД\n\
+        10 - РК\n\
+        11 - А
+        ",
+            &["00 - РС", "01 - РД", "10 - РК", "11 - А"],
+        );
+
+        descriptor.numeric(
+            11,
+            8,
+            "Проверяемый бит",
+            "Номер бита, который нам нужно сравнить.",
+        );
+
+        descriptor.numeric(7, 0, "Адрес перехода", "В случае когда проверяемый бит совпадет с битом сравнения в СчМК будет присвоено это значение");
+
+        descriptor
+    }
+
+    // Управляющая микрокоманда, если opcode - она
+    pub fn decode(opcode: u16) -> Option<ControlCommand> {
+        (sub_sum(opcode, 15, 14) >= 2).then_some(ControlCommand(opcode))
+    }
+
     pub fn needed_bit(&self) -> bool {
         bit_at(self.0, 14)
     }
@@ -1273,3 +1480,58 @@ impl OperationalCommand0 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::model::Computer;
+    use crate::parse::mc::ExecutionResult;
+
+    // Выполняет по микрокомандам одну команду с ячейкой 020 в качестве операнда
+    fn execute(command: u16, counter: u16, operand: u16, carry: bool) -> (u16, bool) {
+        let mut computer = Computer::new();
+        {
+            let mut memory = computer.general_memory.borrow_mut();
+            memory.data[0x10].set(command);
+            memory.data[0x20].set(operand);
+        }
+        computer.registers.r_command_counter = 0x10;
+        computer.registers.r_counter = counter;
+        computer.registers.set_overflow(carry);
+        computer.find(|res| res == &ExecutionResult::Halted);
+        (
+            computer.registers.r_counter,
+            computer.registers.get_overflow(),
+        )
+    }
+
+    // С ставится только при переносе из 16-го разряда, иначе сбрасывается,
+    // в том числе когда БР=А+1 начинается с FFFF
+    #[test]
+    fn alu_carry_flag() {
+        let cases = [
+            // ADD 020
+            (0x4020, 0xFFFF, 0x0001, false, 0x0000, true),
+            (0x4020, 0x7FFF, 0x0001, true, 0x8000, false),
+            (0x4020, 0x0000, 0x0000, true, 0x0000, false),
+            // ADC 020
+            (0x5020, 0xFFFF, 0x0000, true, 0x0000, true),
+            (0x5020, 0xFFFF, 0xFFFF, true, 0xFFFF, true),
+            (0x5020, 0x7FFF, 0x0000, true, 0x8000, false),
+            (0x5020, 0x7FFF, 0x0001, false, 0x8000, false),
+            // INC
+            (0xF800, 0xFFFF, 0x0000, false, 0x0000, true),
+            (0xF800, 0x7FFF, 0x0000, true, 0x8000, false),
+        ];
+        for (command, counter, operand, carry, result, expected_carry) in cases {
+            assert_eq!(
+                execute(command, counter, operand, carry),
+                (result, expected_carry),
+                "{:0>4X}: А={:0>4X}, операнд {:0>4X}, С={}",
+                command,
+                counter,
+                operand,
+                carry
+            );
+        }
+    }
+}
@@ -1,13 +1,60 @@
+#[cfg(feature = "gui")]
 use imgui::Ui;
+use std::ops::Range;
 
+pub mod bpc;
+pub mod cfg;
+mod check;
+pub mod diagnostic;
+pub mod disasm;
+pub mod expr;
 pub mod file;
+pub mod format;
 pub mod general;
+pub mod image;
+pub mod include;
+pub mod lexer;
+pub mod listing;
+mod macros;
 pub mod mc;
+pub mod ng;
+pub mod syntax;
+
+// Что команда делает с памятью и потоком управления. Нужно ассемблеру для предупреждений.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Effect {
+    // Ячейка, в которую команда пишет
+    pub writes: Option<u16>,
+    // Ячейка, на которую команда может перейти
+    pub jumps: Option<u16>,
+    // Может перепрыгнуть через следующую команду
+    pub skips: bool,
+    // Следующая по порядку команда после этой не выполняется (BR, HLT)
+    pub stops: bool,
+    // Переход в подпрограмму, после возврата выполнение продолжится со следующей (JSR)
+    pub calls: bool,
+    // Поведение команды не задано
+    pub undefined: bool,
+    // Ячейка из операнда: сам адрес или, при косвенной адресации, ячейка с адресом
+    pub address: Option<u16>,
+}
 
 pub trait CommandInfo {
     fn file_string(&self) -> String;
     fn mnemonic(&self) -> String;
+    #[cfg(feature = "gui")]
     fn draw_highlight(&self, ui: &Ui);
+
+    // Редактор команды по полям. Возвращает новый опкод, если что-то поменяли.
+    #[cfg(feature = "gui")]
+    fn draw_editor(&self, _ui: &Ui) -> Option<u16> {
+        None
+    }
+
+    // Косвенную адресацию не разбираем: адрес станет известен только при выполнении
+    fn effect(&self) -> Effect {
+        Effect::default()
+    }
 }
 
 pub trait Parser<T: CommandInfo> {
@@ -16,4 +63,14 @@ pub trait Parser<T: CommandInfo> {
     fn supports_rev_parse(&self) -> bool;
 
     fn rev_parse(&self, str: &str) -> Result<u16, String>;
+
+    // То же самое, но известен адрес ячейки, в которую попадет команда
+    fn rev_parse_at(&self, str: &str, _position: u16) -> Result<u16, String> {
+        self.rev_parse(str)
+    }
+
+    // Ячейки, которые ЭВМ использует при прерывании
+    fn interrupt_vectors(&self) -> Range<u16> {
+        0..0
+    }
 }
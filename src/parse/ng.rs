@@ -0,0 +1,769 @@
+use crate::parse::general::{GeneralCommand, GeneralParser};
+use crate::profile::{MachineConfig, ADDRESS_FIELD_MASK};
+
+use core::ops::{BitAnd, BitOr, Shr};
+#[cfg(feature = "gui")]
+use imgui::Ui;
+
+// Команды БЭВМ-NG. Опкод определяется старшими битами команды целиком,
+// поэтому, в отличие от классической БЭВМ, лишние биты не игнорируются.
+
+// Режимы адресации в битах 8-11 адресной команды
+const MODE_INDIRECT: u16 = 0x8;
+const MODE_POST_INCREMENT: u16 = 0xA;
+const MODE_PRE_DECREMENT: u16 = 0xB;
+const MODE_STACK: u16 = 0xC;
+const MODE_RELATIVE: u16 = 0xE;
+const MODE_IMMEDIATE: u16 = 0xF;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Addressing {
+    // Прямая абсолютная: M = *X
+    Absolute(u16),
+    // Косвенная относительная: M = **(IP+d)
+    Indirect(u8),
+    // Косвенная автоинкрементная: M = **(IP+d), затем *(IP+d) += 1
+    PostIncrement(u8),
+    // Косвенная автодекрементная: *(IP+d) -= 1, затем M = **(IP+d)
+    PreDecrement(u8),
+    // Относительно указателя стека: M = *(SP+d)
+    Stack(u8),
+    // Прямая относительная: M = *(IP+d)
+    Relative(u8),
+    // Прямая загрузка: M = d
+    Immediate(u8),
+    Unknown(u16),
+}
+
+impl Addressing {
+    pub fn decode(command: u16) -> Addressing {
+        if command.bitand(0x0800) == 0 {
+            return Addressing::Absolute(command.bitand(ADDRESS_FIELD_MASK));
+        }
+
+        let offset = command.bitand(0xFF) as u8;
+        match command.shr(8u16).bitand(0xF) {
+            MODE_INDIRECT => Addressing::Indirect(offset),
+            MODE_POST_INCREMENT => Addressing::PostIncrement(offset),
+            MODE_PRE_DECREMENT => Addressing::PreDecrement(offset),
+            MODE_STACK => Addressing::Stack(offset),
+            MODE_RELATIVE => Addressing::Relative(offset),
+            MODE_IMMEDIATE => Addressing::Immediate(offset),
+            mode => Addressing::Unknown(mode),
+        }
+    }
+
+    fn encode(&self) -> u16 {
+        let with_mode = |mode: u16, offset: u8| mode << 8 | offset as u16;
+        match *self {
+            Addressing::Absolute(address) => address.bitand(ADDRESS_FIELD_MASK),
+            Addressing::Indirect(d) => with_mode(MODE_INDIRECT, d),
+            Addressing::PostIncrement(d) => with_mode(MODE_POST_INCREMENT, d),
+            Addressing::PreDecrement(d) => with_mode(MODE_PRE_DECREMENT, d),
+            Addressing::Stack(d) => with_mode(MODE_STACK, d),
+            Addressing::Relative(d) => with_mode(MODE_RELATIVE, d),
+            Addressing::Immediate(d) => with_mode(MODE_IMMEDIATE, d),
+            Addressing::Unknown(mode) => mode << 8,
+        }
+    }
+
+    fn format(&self) -> String {
+        match *self {
+            Addressing::Absolute(address) => format!("{:0>3X}", address),
+            Addressing::Indirect(d) => format!("({})", format_ip_offset(d)),
+            Addressing::PostIncrement(d) => format!("({})+", format_ip_offset(d)),
+            Addressing::PreDecrement(d) => format!("-({})", format_ip_offset(d)),
+            Addressing::Stack(d) => format!("&{}", format_signed(d)),
+            Addressing::Relative(d) => format_ip_offset(d),
+            Addressing::Immediate(d) => format!("#{:0>2X}", d),
+            Addressing::Unknown(mode) => format!("?{:X}", mode),
+        }
+    }
+
+    fn parse(s: &str, position: Option<u16>, max_address: u16) -> Result<Addressing, String> {
+        if let Some(value) = s.strip_prefix('#') {
+            return parse_byte(value).map(Addressing::Immediate);
+        }
+        if let Some(value) = s.strip_prefix('&') {
+            return parse_signed(value).map(Addressing::Stack);
+        }
+        if let Some(inner) = s.strip_prefix("-(").and_then(|s| s.strip_suffix(')')) {
+            return parse_ip_offset(inner, position).map(Addressing::PreDecrement);
+        }
+        if let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(")+")) {
+            return parse_ip_offset(inner, position).map(Addressing::PostIncrement);
+        }
+        if let Some(inner) = s.strip_prefix('(') {
+            let Some(inner) = inner.strip_suffix(')') else {
+                return Err("Не закрытая скобка".to_string());
+            };
+            return parse_ip_offset(inner, position).map(Addressing::Indirect);
+        }
+        if s.to_uppercase().starts_with("IP") {
+            return parse_ip_offset(s, position).map(Addressing::Relative);
+        }
+
+        let Ok(address) = u16::from_str_radix(s, 16) else {
+            return Err(format!("Ошибка во время парсинга числа {}", s));
+        };
+        if address > max_address {
+            return Err(format!("Максимально адресуема память 0x{:X}", max_address));
+        }
+        Ok(Addressing::Absolute(address))
+    }
+
+    fn explain(&self) -> String {
+        match *self {
+            Addressing::Absolute(address) => {
+                format!("прямая абсолютная, M - значение в ячейке {:0>3X}", address)
+            }
+            Addressing::Indirect(d) => format!(
+                "косвенная относительная, M - значение в ячейке, адрес которой лежит в {}",
+                format_ip_offset(d)
+            ),
+            Addressing::PostIncrement(d) => format!(
+                "косвенная автоинкрементная, как косвенная, но после обращения ячейка {} увеличивается на 1",
+                format_ip_offset(d)
+            ),
+            Addressing::PreDecrement(d) => format!(
+                "косвенная автодекрементная, как косвенная, но перед обращением ячейка {} уменьшается на 1",
+                format_ip_offset(d)
+            ),
+            Addressing::Stack(d) => format!(
+                "относительно SP, M - значение в ячейке SP{}",
+                format_signed_with_sign(d)
+            ),
+            Addressing::Relative(d) => format!(
+                "прямая относительная, M - значение в ячейке {}",
+                format_ip_offset(d)
+            ),
+            Addressing::Immediate(d) => {
+                format!("прямая загрузка, M - число {:0>2X} с расширенным знаком", d)
+            }
+            Addressing::Unknown(mode) => format!("режим {:X} не определен", mode),
+        }
+    }
+}
+
+fn format_signed(d: u8) -> String {
+    if (d as i8) < 0 {
+        format!("-{:0>2X}", (d as i8).unsigned_abs())
+    } else {
+        format!("{:0>2X}", d)
+    }
+}
+
+fn format_signed_with_sign(d: u8) -> String {
+    if (d as i8) < 0 {
+        format_signed(d)
+    } else {
+        format!("+{}", format_signed(d))
+    }
+}
+
+fn format_ip_offset(d: u8) -> String {
+    format!("IP{}", format_signed_with_sign(d))
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    u8::from_str_radix(s, 16).map_err(|_| format!("Ошибка во время парсинга байта {}", s))
+}
+
+fn parse_signed(s: &str) -> Result<u8, String> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let Ok(value) = i16::from_str_radix(digits, 16) else {
+        return Err(format!("Ошибка во время парсинга смещения {}", s));
+    };
+    let value = if negative { -value } else { value };
+    if !(-0x80..=0x7F).contains(&value) {
+        return Err(format!(
+            "Смещение {} не помещается в байт. Допустимо от -80 до +7F",
+            s
+        ));
+    }
+    Ok(value as i8 as u8)
+}
+
+// IP+d, IP-d или абсолютный адрес, если известно, где лежит команда
+fn parse_ip_offset(s: &str, position: Option<u16>) -> Result<u8, String> {
+    let upper = s.to_uppercase();
+    if let Some(offset) = upper.strip_prefix("IP") {
+        return parse_signed(offset);
+    }
+
+    let Some(position) = position else {
+        return Err(format!(
+            "Ожидалось смещение вида IP+XX или IP-XX, получено {}",
+            s
+        ));
+    };
+    let Ok(target) = u16::from_str_radix(s, 16) else {
+        return Err(format!("Ошибка во время парсинга числа {}", s));
+    };
+
+    // IP к моменту исполнения уже указывает на следующую команду
+    let offset = target as i32 - (position as i32 + 1);
+    if !(-0x80..=0x7F).contains(&offset) {
+        return Err(format!(
+            "Адрес {:0>3X} слишком далеко от команды в ячейке {:0>3X}",
+            target, position
+        ));
+    }
+    Ok(offset as i8 as u8)
+}
+
+pub fn sign_extend(d: u8) -> u16 {
+    d as i8 as i16 as u16
+}
+
+// Команда целиком задается опкодом, операнда нет
+struct FixedCommand {
+    name: &'static str,
+    description: &'static str,
+    opcode: u16,
+    significant: u16,
+}
+
+impl FixedCommand {
+    fn new(opcode: u16, significant: u16, name: &'static str, description: &'static str) -> Self {
+        FixedCommand {
+            name,
+            description,
+            opcode,
+            significant,
+        }
+    }
+}
+
+impl GeneralCommand for FixedCommand {
+    fn matching(&self, cmd: u16) -> bool {
+        cmd.bitand(self.significant) == self.opcode
+    }
+
+    fn specificity(&self) -> u32 {
+        self.significant.count_ones()
+    }
+
+    fn file_string(&self, cmd: u16) -> String {
+        if cmd == self.opcode {
+            self.name.to_owned()
+        } else {
+            format!("{cmd:0>4X}")
+        }
+    }
+
+    fn mnemonic(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn mask(&self) -> u16 {
+        self.opcode
+    }
+
+    fn parse(&self, _data: u16) -> String {
+        self.name.to_string()
+    }
+
+    fn rev_parse(&self, s: &str) -> Result<u16, String> {
+        if s.trim().to_uppercase() != self.name {
+            return Err(format!(
+                "{} является безадресной командой и не принимает аргументов",
+                self.name
+            ));
+        }
+        Ok(self.opcode)
+    }
+
+    #[cfg(feature = "gui")]
+    fn draw_highlight(&self, ui: &Ui, opcode: u16) {
+        ui.text("Тип: Безадресная команда");
+        ui.text(format!("Мнемоника: {}", self.name));
+        ui.text(format!("Опкод: {:0>4X}", self.opcode));
+
+        ui.text_wrapped(format!("Описание: {}", self.description));
+
+        if opcode != self.opcode {
+            ui.text_wrapped(format!(
+                "Примечание: младшие биты опкода {:0>4X} не используются командой {}",
+                opcode, self.name
+            ))
+        }
+    }
+}
+
+// Адресная команда с режимом адресации в битах 8-11
+struct AddressCommand {
+    name: &'static str,
+    description: &'static str,
+    opcode: u16,
+    immediate: bool,
+    max_address: u16,
+}
+
+impl AddressCommand {
+    fn new(
+        config: &MachineConfig,
+        opcode: u16,
+        name: &'static str,
+        description: &'static str,
+    ) -> Self {
+        AddressCommand {
+            name,
+            description,
+            opcode,
+            immediate: true,
+            max_address: config.max_direct_address(),
+        }
+    }
+
+    // Для команд, которым нужен адрес, а не значение (ST, JUMP, CALL...)
+    fn without_immediate(mut self) -> Self {
+        self.immediate = false;
+        self
+    }
+
+    fn operand(&self, s: &str) -> Result<String, String> {
+        let splited = s.split_whitespace().collect::<Vec<&str>>();
+        match splited.len() {
+            2 => Ok(splited[1].to_string()),
+            0 | 1 => Err("Ожидалось два параметра".to_string()),
+            _ => Err(format!("Неожиданные штуки: {}", splited[2..].join(" "))),
+        }
+    }
+
+    fn encode(&self, addressing: Addressing) -> Result<u16, String> {
+        if let Addressing::Immediate(_) = addressing {
+            if !self.immediate {
+                return Err(format!("{} не поддерживает прямую загрузку (#)", self.name));
+            }
+        }
+        Ok(self.opcode.bitor(addressing.encode()))
+    }
+}
+
+impl GeneralCommand for AddressCommand {
+    fn matching(&self, cmd: u16) -> bool {
+        cmd.bitand(0xF000) == self.opcode
+    }
+
+    fn specificity(&self) -> u32 {
+        4
+    }
+
+    // В файле # начинает комментарий, поэтому прямая загрузка сохраняется числом
+    fn file_string(&self, cmd: u16) -> String {
+        match Addressing::decode(cmd) {
+            Addressing::Unknown(_) | Addressing::Immediate(_) => format!("{cmd:0>4X}"),
+            _ => self.parse(cmd),
+        }
+    }
+
+    fn mnemonic(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn mask(&self) -> u16 {
+        self.opcode
+    }
+
+    fn parse(&self, data: u16) -> String {
+        format!("{} {}", self.name, Addressing::decode(data).format())
+    }
+
+    fn rev_parse(&self, s: &str) -> Result<u16, String> {
+        self.encode(Addressing::parse(
+            &self.operand(s)?,
+            None,
+            self.max_address,
+        )?)
+    }
+
+    fn rev_parse_at(&self, s: &str, position: u16) -> Result<u16, String> {
+        self.encode(Addressing::parse(
+            &self.operand(s)?,
+            Some(position),
+            self.max_address,
+        )?)
+    }
+
+    #[cfg(feature = "gui")]
+    fn draw_highlight(&self, ui: &Ui, opcode: u16) {
+        ui.text("Тип: Адресная команда");
+        ui.text(format!("Мнемоника: {}", self.parse(opcode)));
+        ui.text(format!("Маска: {:0>4X}", self.opcode));
+        ui.text_wrapped(format!(
+            "Адресация: {}",
+            Addressing::decode(opcode).explain()
+        ));
+        ui.text_wrapped(format!("Описание: {}", self.description))
+    }
+}
+
+// Переход со смещением относительно IP в младшем байте
+struct BranchCommand {
+    name: &'static str,
+    description: &'static str,
+    opcode: u16,
+}
+
+impl BranchCommand {
+    fn new(opcode: u16, name: &'static str, description: &'static str) -> Self {
+        BranchCommand {
+            name,
+            description,
+            opcode,
+        }
+    }
+
+    fn rev_parse_with(&self, s: &str, position: Option<u16>) -> Result<u16, String> {
+        let splited = s.split_whitespace().collect::<Vec<&str>>();
+        if splited.len() != 2 {
+            return Err("Ожидалось два параметра".to_string());
+        }
+        let offset = parse_ip_offset(splited[1], position)?;
+        Ok(self.opcode.bitor(offset as u16))
+    }
+}
+
+impl GeneralCommand for BranchCommand {
+    fn matching(&self, cmd: u16) -> bool {
+        cmd.bitand(0xFF00) == self.opcode
+    }
+
+    fn specificity(&self) -> u32 {
+        8
+    }
+
+    fn file_string(&self, cmd: u16) -> String {
+        self.parse(cmd)
+    }
+
+    fn mnemonic(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn mask(&self) -> u16 {
+        self.opcode
+    }
+
+    fn parse(&self, data: u16) -> String {
+        format!(
+            "{} {}",
+            self.name,
+            format_ip_offset(data.bitand(0xFF) as u8)
+        )
+    }
+
+    fn rev_parse(&self, s: &str) -> Result<u16, String> {
+        self.rev_parse_with(s, None)
+    }
+
+    fn rev_parse_at(&self, s: &str, position: u16) -> Result<u16, String> {
+        self.rev_parse_with(s, Some(position))
+    }
+
+    #[cfg(feature = "gui")]
+    fn draw_highlight(&self, ui: &Ui, opcode: u16) {
+        ui.text("Тип: Команда ветвления");
+        ui.text(format!("Мнемоника: {}", self.parse(opcode)));
+        ui.text(format!("Маска: {:0>4X}", self.opcode));
+        ui.text(format!(
+            "Смещение: {}",
+            format_signed(opcode.bitand(0xFF) as u8)
+        ));
+        ui.text_wrapped(format!("Описание: {}", self.description))
+    }
+}
+
+// Команда ввода-вывода с номером регистра ВУ или вектора в младшем байте
+struct IoCommand {
+    name: &'static str,
+    description: &'static str,
+    opcode: u16,
+    max: u16,
+}
+
+impl IoCommand {
+    fn new(opcode: u16, max: u16, name: &'static str, description: &'static str) -> Self {
+        IoCommand {
+            name,
+            description,
+            opcode,
+            max,
+        }
+    }
+}
+
+impl GeneralCommand for IoCommand {
+    fn matching(&self, cmd: u16) -> bool {
+        cmd.bitand(0xFF00) == self.opcode
+    }
+
+    fn specificity(&self) -> u32 {
+        8
+    }
+
+    fn file_string(&self, cmd: u16) -> String {
+        if cmd.bitand(0xFF) > self.max {
+            format!("{cmd:0>4X}")
+        } else {
+            self.parse(cmd)
+        }
+    }
+
+    fn mnemonic(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn mask(&self) -> u16 {
+        self.opcode
+    }
+
+    fn parse(&self, data: u16) -> String {
+        format!("{} {:0>2X}", self.name, data.bitand(0xFF))
+    }
+
+    fn rev_parse(&self, s: &str) -> Result<u16, String> {
+        let splited = s.split_whitespace().collect::<Vec<&str>>();
+        if splited.len() != 2 {
+            return Err("Ожидалось два параметра".to_string());
+        }
+        let Ok(value) = u16::from_str_radix(splited[1], 16) else {
+            return Err(format!("Ошибка во время парсинга числа {}", splited[1]));
+        };
+        if value > self.max {
+            return Err(format!(
+                "Максимальное значение для {} - {:X}",
+                self.name, self.max
+            ));
+        }
+        Ok(self.opcode.bitor(value))
+    }
+
+    #[cfg(feature = "gui")]
+    fn draw_highlight(&self, ui: &Ui, opcode: u16) {
+        ui.text("Тип: Команда ввода-вывода");
+        ui.text(format!("Мнемоника: {}", self.parse(opcode)));
+        ui.text(format!("Маска: {:0>4X}", self.opcode));
+        ui.text_wrapped(format!("Описание: {}", self.description))
+    }
+}
+
+impl GeneralParser {
+    pub fn new_ng(config: &MachineConfig) -> GeneralParser {
+        // Четные номера - регистры данных, нечетные - регистры состояния
+        let max_io_register = (config.io_devices * 2 - 1) as u16;
+
+        let mut parser = GeneralParser::empty();
+
+        let fixed = [
+            (0x0000, 0xFF00, "NOP", "Команда, которая не делает ничего."),
+            (0x0100, 0xFF00, "HLT", "Останавливает ЭВМ."),
+            (0x0200, 0xFF80, "CLA", "AC = 0"),
+            (0x0280, 0xFF80, "NOT", "Инвертирует все биты AC"),
+            (0x0300, 0xFF80, "CLC", "C = 0"),
+            (0x0380, 0xFF80, "CMC", "Инвертирует C"),
+            (0x0400, 0xFF80, "ROL", "Циклический сдвиг AC влево через C"),
+            (0x0480, 0xFF80, "ROR", "Циклический сдвиг AC вправо через C"),
+            (
+                0x0500,
+                0xFF80,
+                "ASL",
+                "Арифметический сдвиг AC влево. Старший бит попадает в C",
+            ),
+            (
+                0x0580,
+                0xFF80,
+                "ASR",
+                "Арифметический сдвиг AC вправо. Знак сохраняется, младший бит попадает в C",
+            ),
+            (
+                0x0600,
+                0xFF80,
+                "SXTB",
+                "Расширяет знак младшего байта AC на старший байт",
+            ),
+            (
+                0x0680,
+                0xFF80,
+                "SWAB",
+                "Меняет местами старший и младший байты AC",
+            ),
+            (0x0700, 0xFFC0, "INC", "AC = AC + 1"),
+            (0x0740, 0xFFC0, "DEC", "AC = AC - 1"),
+            (0x0780, 0xFF80, "NEG", "AC = -AC"),
+            (0x0800, 0xFF00, "POP", "Снимает значение со стека в AC"),
+            (0x0900, 0xFF00, "POPF", "Снимает значение со стека в PS"),
+            (
+                0x0A00,
+                0xFF00,
+                "RET",
+                "Возврат из подпрограммы. Снимает IP со стека",
+            ),
+            (
+                0x0B00,
+                0xFF00,
+                "IRET",
+                "Возврат из прерывания. Снимает со стека PS, затем IP",
+            ),
+            (0x0C00, 0xFF00, "PUSH", "Кладет AC на стек"),
+            (0x0D00, 0xFF00, "PUSHF", "Кладет PS на стек"),
+            (
+                0x0E00,
+                0xFF00,
+                "SWAP",
+                "Меняет местами AC и значение на вершине стека",
+            ),
+            (0x1000, 0xFF00, "DI", "Запрещает прерывания"),
+            (0x1100, 0xFF00, "EI", "Разрешает прерывания"),
+        ];
+        for (opcode, significant, name, description) in fixed {
+            parser.register(FixedCommand::new(opcode, significant, name, description));
+        }
+
+        // Все остальные опкоды ничего не делают
+        parser.register(FixedCommand::new(
+            0x0000,
+            0x0000,
+            "HZ",
+            "Команда для которой не задано поведение. То есть она ничего не делает.",
+        ));
+
+        parser.register(IoCommand::new(
+            0x1200,
+            max_io_register,
+            "IN",
+            "Читает регистр ВУ в младший байт AC. Четный номер - регистр данных ВУ номер N/2, нечетный - регистр состояния (6 бит - готовность)",
+        ));
+        parser.register(IoCommand::new(
+            0x1300,
+            max_io_register,
+            "OUT",
+            "Пишет младший байт AC в регистр ВУ. Запись в регистр состояния (нечетный номер) сбрасывает готовность ВУ",
+        ));
+        parser.register(IoCommand::new(
+            0x1800,
+            0x7,
+            "INT",
+            "Программное прерывание. Кладет на стек IP и PS, затем загружает их из ячеек 2N и 2N+1",
+        ));
+
+        parser.register(AddressCommand::new(config, 0x2000, "AND", "AC = AC & M"));
+        parser.register(AddressCommand::new(config, 0x3000, "OR", "AC = AC | M"));
+        parser.register(AddressCommand::new(config, 0x4000, "ADD", "AC = AC + M"));
+        parser.register(AddressCommand::new(
+            config,
+            0x5000,
+            "ADC",
+            "AC = AC + M + C",
+        ));
+        parser.register(AddressCommand::new(config, 0x6000, "SUB", "AC = AC - M"));
+        parser.register(AddressCommand::new(
+            config,
+            0x7000,
+            "CMP",
+            "Вычисляет AC - M и выставляет флаги, не изменяя AC",
+        ));
+        parser.register(
+            AddressCommand::new(
+                config,
+                0x8000,
+                "LOOP",
+                "M = M - 1. Если M <= 0, пропускает следующую команду",
+            )
+            .without_immediate(),
+        );
+        parser.register(AddressCommand::new(config, 0xA000, "LD", "AC = M"));
+        parser.register(
+            AddressCommand::new(config, 0xB000, "SWAM", "Меняет местами AC и M")
+                .without_immediate(),
+        );
+        parser.register(
+            AddressCommand::new(config, 0xC000, "JUMP", "IP = адрес M").without_immediate(),
+        );
+        parser.register(
+            AddressCommand::new(
+                config,
+                0xD000,
+                "CALL",
+                "Кладет IP на стек и переходит по адресу M",
+            )
+            .without_immediate(),
+        );
+        parser.register(AddressCommand::new(config, 0xE000, "ST", "M = AC").without_immediate());
+
+        let branches = [
+            (0xCE00, "BR", "Безусловный переход"),
+            (0xF000, "BEQ", "Переход, если Z = 1"),
+            (0xF100, "BNE", "Переход, если Z = 0"),
+            (0xF200, "BMI", "Переход, если N = 1"),
+            (0xF300, "BPL", "Переход, если N = 0"),
+            (0xF400, "BCS", "Переход, если C = 1"),
+            (0xF500, "BCC", "Переход, если C = 0"),
+            (0xF600, "BVS", "Переход, если V = 1"),
+            (0xF700, "BVC", "Переход, если V = 0"),
+            (0xF800, "BLT", "Переход, если N xor V = 1"),
+            (0xF900, "BGE", "Переход, если N xor V = 0"),
+        ];
+        for (opcode, name, description) in branches {
+            parser.register(BranchCommand::new(opcode, name, description));
+        }
+
+        // Векторы INT 0..7: пары ячеек 2N и 2N+1
+        parser.interrupt_vectors = 0..0x10;
+        parser.sort();
+
+        parser
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::general::GeneralParser;
+    use crate::parse::{CommandInfo, Parser};
+    use crate::profile::MachineProfile;
+
+    #[test]
+    fn parse_ng() {
+        let parser = GeneralParser::new_ng(&MachineProfile::Ng.default_config());
+
+        assert_eq!(parser.parse(0x0280).mnemonic(), "NOT");
+        assert_eq!(parser.parse(0x1280).mnemonic(), "IN 80");
+        assert_eq!(parser.parse(0x4123).mnemonic(), "ADD 123");
+        assert_eq!(parser.parse(0x4A05).mnemonic(), "ADD (IP+05)+");
+        assert_eq!(parser.parse(0xACFE).mnemonic(), "LD &-02");
+        assert_eq!(parser.parse(0xCEFD).mnemonic(), "BR IP-03");
+        assert_eq!(parser.parse(0x0F00).mnemonic(), "HZ");
+    }
+
+    #[test]
+    fn rev_parse_ng() {
+        let parser = GeneralParser::new_ng(&MachineProfile::Ng.default_config());
+
+        assert_eq!(parser.rev_parse("ADD #7F"), Ok(0x4F7F));
+        assert_eq!(parser.rev_parse("ST -(IP-01)"), Ok(0xEBFF));
+        assert_eq!(parser.rev_parse("BEQ IP+10"), Ok(0xF010));
+        assert_eq!(parser.rev_parse_at("BEQ 10", 0x20), Ok(0xF0EF));
+        assert!(parser.rev_parse("ST #01").is_err());
+        assert!(parser.rev_parse("BEQ 10").is_err());
+    }
+}
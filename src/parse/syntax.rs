@@ -0,0 +1,122 @@
+use crate::parse::lexer::{tokenize, Token};
+
+use std::ops::Range;
+
+// Раскраска строки .mm для редактора
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Highlight {
+    Mnemonic,
+    // $pos, $word, $equ и прочие в начале строки
+    Directive,
+    // Объявление метки: $name после команды
+    Label,
+    // Ссылка на метку: %name
+    Reference,
+    // Строки и символы в кавычках
+    Text,
+    Comment,
+}
+
+// Куски строки, которые надо раскрасить, по порядку. Остальное пишется обычным цветом.
+pub fn highlight<F: Fn(&str) -> bool>(
+    line: &str,
+    is_mnemonic: F,
+) -> Vec<(Range<usize>, Highlight)> {
+    let mut quoted = Vec::new();
+    let mut comment = None;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match quote {
+            _ if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some((q, start)) if c == q => {
+                quoted.push(start..i + 1);
+                quote = None;
+            }
+            Some(_) => {}
+            None if c == '#' => {
+                comment = Some(i);
+                break;
+            }
+            None if c == '"' || c == '\'' => quote = Some((c, i)),
+            None => {}
+        }
+    }
+    // Незакрытая кавычка тянется до конца строки
+    let code_end = comment.unwrap_or(line.len());
+    if let Some((_, start)) = quote {
+        quoted.push(start..code_end);
+    }
+
+    let mut result = quoted
+        .iter()
+        .map(|range| (range.clone(), Highlight::Text))
+        .collect::<Vec<_>>();
+    let tokens = tokenize(&line[..code_end])
+        .into_iter()
+        .filter(|token| !quoted.iter().any(|range| range.contains(&token.start)))
+        .collect::<Vec<_>>();
+    for (i, token) in tokens.iter().enumerate() {
+        match token.token {
+            Token::Word(word) if i == 0 && is_mnemonic(word) => {
+                result.push((token.start..token.end, Highlight::Mnemonic));
+            }
+            Token::Label(_) => result.push((token.start..token.end, Highlight::Reference)),
+            Token::Other('$') => {
+                let Some(name) = tokens.get(i + 1).filter(|name| name.start == token.end) else {
+                    continue;
+                };
+                let kind = if i == 0 {
+                    Highlight::Directive
+                } else {
+                    Highlight::Label
+                };
+                result.push((token.start..name.end, kind));
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = comment {
+        result.push((start..line.len(), Highlight::Comment));
+    }
+
+    result.sort_by_key(|(range, _)| range.start);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::syntax::{highlight, Highlight};
+
+    fn parts(line: &str) -> Vec<(&str, Highlight)> {
+        let is_mnemonic = |word: &str| ["CLA", "ADD", "BR"].contains(&word.to_uppercase().as_str());
+        highlight(line, is_mnemonic)
+            .into_iter()
+            .map(|(range, kind)| (&line[range], kind))
+            .collect()
+    }
+
+    #[test]
+    fn highlights_line() {
+        assert_eq!(
+            parts("add %x $start # ADD %y"),
+            vec![
+                ("add", Highlight::Mnemonic),
+                ("%x", Highlight::Reference),
+                ("$start", Highlight::Label),
+                ("# ADD %y", Highlight::Comment),
+            ]
+        );
+        assert_eq!(
+            parts("$string \"# %a\" $text"),
+            vec![
+                ("$string", Highlight::Directive),
+                ("\"# %a\"", Highlight::Text),
+                ("$text", Highlight::Label),
+            ]
+        );
+        // ADD в операнде - это число, а не команда
+        assert_eq!(parts("$word ADD"), vec![("$word", Highlight::Directive)]);
+    }
+}
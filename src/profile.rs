@@ -0,0 +1,106 @@
+use crate::parse::general::GeneralParser;
+
+// Модель ЭВМ, которую эмулирует программа
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum MachineProfile {
+    // Классическая БЭВМ из методички
+    Classic,
+    // БЭВМ-NG со стеком и режимами адресации
+    Ng,
+}
+
+impl MachineProfile {
+    pub const ALL: [MachineProfile; 2] = [MachineProfile::Classic, MachineProfile::Ng];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            MachineProfile::Classic => "БЭВМ (классическая)",
+            MachineProfile::Ng => "БЭВМ-NG",
+        }
+    }
+
+    // Имя для командной строки: bevm --profile ng
+    pub fn name(&self) -> &'static str {
+        match self {
+            MachineProfile::Classic => "classic",
+            MachineProfile::Ng => "ng",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MachineProfile> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|profile| profile.name() == name.to_lowercase())
+    }
+
+    // У БЭВМ-NG нет микропрограммы, ее команды исполняются только целиком
+    pub fn has_microcode(&self) -> bool {
+        *self == MachineProfile::Classic
+    }
+
+    pub fn parser(&self, config: &MachineConfig) -> GeneralParser {
+        match self {
+            MachineProfile::Classic => GeneralParser::new(config),
+            MachineProfile::Ng => GeneralParser::new_ng(config),
+        }
+    }
+
+    pub fn default_config(&self) -> MachineConfig {
+        MachineConfig {
+            address_bits: 11,
+            micro_address_bits: 8,
+            io_devices: 16,
+        }
+    }
+
+    // Микропрограмма классической БЭВМ кладет в РА команду целиком, поэтому адрес не шире 11 бит.
+    // БЭВМ-NG может адресовать больше через косвенную адресацию и стек.
+    pub fn max_address_bits(&self) -> u8 {
+        match self {
+            MachineProfile::Classic => 11,
+            MachineProfile::Ng => 16,
+        }
+    }
+}
+
+// Размеры памяти и число ВУ
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct MachineConfig {
+    pub address_bits: u8,
+    pub micro_address_bits: u8,
+    pub io_devices: usize,
+}
+
+// Адресное поле команды всегда 11 бит
+pub const ADDRESS_FIELD_MASK: u16 = 0x7FF;
+
+pub const MIN_ADDRESS_BITS: u8 = 4;
+pub const MAX_IO_DEVICES: usize = 16;
+
+impl MachineConfig {
+    pub fn general_size(&self) -> usize {
+        1 << self.address_bits
+    }
+
+    pub fn address_mask(&self) -> u16 {
+        (self.general_size() - 1) as u16
+    }
+
+    pub fn micro_size(&self) -> usize {
+        1 << self.micro_address_bits
+    }
+
+    pub fn micro_address_mask(&self) -> u8 {
+        (self.micro_size() - 1) as u8
+    }
+
+    // Наибольший адрес, который можно записать прямо в команде
+    pub fn max_direct_address(&self) -> u16 {
+        self.address_mask().min(ADDRESS_FIELD_MASK)
+    }
+
+    pub fn max_io_device(&self) -> u16 {
+        (self.io_devices - 1) as u16
+    }
+}
@@ -1,20 +1,28 @@
 use crate::model::{Computer, Memory, MemoryCell};
+use crate::parse::bpc::{read_bpc, write_bpc};
+use crate::parse::disasm::disassemble;
+use crate::parse::file::{label_address, resolve_labels, write_file};
+use crate::parse::image::{read_image, write_image, ImageFormat};
+use crate::parse::listing::listing;
 use crate::parse::{CommandInfo, Parser};
 use crate::ui::gui::GuiState;
-use crate::ui::popup::{PopupMessage, PopupParseError};
+use crate::ui::popup::{PopupDiagnostics, PopupMessage, PopupParseError};
 use crate::ui::window::Tool;
 use imgui::__core::cell::RefMut;
 use imgui::{InputTextFlags, Io, StyleColor, StyleVar, Ui};
 use rfd::FileDialog;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
 use std::rc::Rc;
 
 #[derive(PartialEq, Eq)]
 enum CellRepresentation {
     Hex,
     Binary,
+    Fields,
 }
 
 impl CellRepresentation {
@@ -22,6 +30,7 @@ impl CellRepresentation {
         match self {
             CellRepresentation::Hex => "Шестнадцетеричное",
             CellRepresentation::Binary => "Бинарное",
+            CellRepresentation::Fields => "По полям",
         }
     }
 
@@ -56,14 +65,43 @@ impl CellRepresentation {
         }
         width_t.end()
     }
-    fn draw(&self, cell: &mut MemoryCell, ui: &Ui) {
+    fn draw_fields(&self, cell: &mut MemoryCell, command: &dyn CommandInfo, ui: &Ui) {
+        let label = format!("{:0>4X} {}###fields", cell.get(), command.mnemonic());
+        if let Some(token) = ui.tree_node_config(label).push() {
+            let width_t = ui.push_item_width(250.0);
+            if let Some(opcode) = command.draw_editor(ui) {
+                cell.set(opcode)
+            }
+            width_t.end();
+            token.pop();
+        }
+    }
+    fn draw(&self, cell: &mut MemoryCell, command: &dyn CommandInfo, ui: &Ui) {
         match self {
             CellRepresentation::Hex => self.draw_hex(cell, ui),
             CellRepresentation::Binary => self.draw_binary(cell, ui),
+            CellRepresentation::Fields => self.draw_fields(cell, command, ui),
+        }
+    }
+
+    fn highlight_color(&self) -> StyleColor {
+        match self {
+            CellRepresentation::Fields => StyleColor::Text,
+            _ => StyleColor::FrameBg,
         }
     }
 }
 
+// BR 010 -> BR start, если у ячейки 010 есть имя
+fn labeled_mnemonic<I: CommandInfo>(command: &I, names: &BTreeMap<u16, String>) -> String {
+    let mnemonic = command.mnemonic();
+    let address = command.effect().address;
+    match address.and_then(|address| Some((address, names.get(&address)?))) {
+        Some((address, name)) => label_address(&mnemonic, address, name),
+        None => mnemonic,
+    }
+}
+
 pub struct CellsTool<I: CommandInfo, P: Parser<I>, F>
 where
     F: Fn(&Computer) -> u16,
@@ -71,6 +109,13 @@ where
     page: Rc<RefCell<Memory<I, P>>>,
     counter_register: F,
     representation: CellRepresentation,
+    fields_editor: bool,
+    // Писать листинг .lst рядом с загруженной программой
+    write_listing: bool,
+    // Адрес начала программы для .bpc
+    bpc_start: String,
+    // Основная память: загруженная программа показывается в окне исходника, а имена ячеек - в таблице
+    source: bool,
 }
 
 impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> Tool for CellsTool<I, P, F>
@@ -86,25 +131,50 @@ where
 
         let mut next_rev_focused = false;
 
+        let jump_to = state.jump_to.filter(|_| self.source);
+
         let (parser, mut data) =
             RefMut::map_split(self.page.borrow_mut(), |r| (&mut r.parser, &mut r.data));
 
         let mut focused: Option<I> = None;
 
+        // Имена ячеек показываются рядом с адресом и вместо адресов в командах.
+        // Они бывают только у основной памяти
+        let labels = if self.source {
+            state.labels.clone()
+        } else {
+            Rc::default()
+        };
+        let label_width = labels.width;
+
         for (idx, cell) in data.iter_mut().enumerate() {
             let token = ui.push_id(idx.to_string());
-            ui.text(format!("{:0>3X}", idx));
+            if jump_to == Some(idx as u16) {
+                ui.set_scroll_here_y();
+                state.jump_to = None;
+            }
+            match label_width {
+                Some(width) => {
+                    let name = cell.name.as_deref().unwrap_or("");
+                    ui.text(format!("{:0>3X} {:<width$}", idx, name, width = width));
+                }
+                None => ui.text(format!("{:0>3X}", idx)),
+            }
             ui.same_line();
             let t = if current_executed == idx as u16 {
                 if state.jump_requested {
                     ui.set_scroll_here_y();
                     state.jump_requested = false;
                 }
-                Some(ui.push_style_color(StyleColor::FrameBg, [1.0, 0.0, 0.0, 1.0]))
+                Some(ui.push_style_color(
+                    self.representation.highlight_color(),
+                    [1.0, 0.0, 0.0, 1.0],
+                ))
             } else {
                 None
             };
-            self.representation.draw(cell, ui);
+            let command = parser.parse(cell.get());
+            self.representation.draw(cell, &command, ui);
             if let Some(t) = t {
                 t.pop();
             }
@@ -112,6 +182,11 @@ where
                 focused = Some(parser.parse(cell.get()))
             }
 
+            if self.representation == CellRepresentation::Fields {
+                token.pop();
+                continue;
+            }
+
             ui.same_line();
             let command = parser.parse(cell.get());
 
@@ -121,17 +196,19 @@ where
                     ui.set_keyboard_focus_here();
                     next_rev_focused = false
                 }
-                content.push_str(command.mnemonic().as_str());
+                content.push_str(&labeled_mnemonic(&command, &labels.names));
                 if ui
                     .input_text("###mnemonic", &mut content)
                     .flags(InputTextFlags::empty())
                     .enter_returns_true(true)
                     .build()
                 {
-                    match parser.rev_parse(&content) {
+                    match parser.rev_parse_at(&resolve_labels(&content, &labels.addresses), idx as u16) {
                         Ok(opcode) => {
                             next_rev_focused = true;
                             cell.set(opcode);
+                            // Команду ввели мнемоникой - теперь это код
+                            cell.is_data = false;
                         }
                         Err(msg) => state
                             .popup_manager
@@ -143,7 +220,7 @@ where
                     focused = Some(command)
                 }
             } else {
-                ui.text(command.mnemonic().as_str());
+                ui.text(labeled_mnemonic(&command, &labels.names));
             }
 
             token.pop();
@@ -167,9 +244,24 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             counter_register,
             page,
             representation: CellRepresentation::Hex,
+            fields_editor: false,
+            write_listing: false,
+            bpc_start: String::new(),
+            source: false,
         }
     }
 
+    pub fn with_fields_editor(mut self) -> Self {
+        self.fields_editor = true;
+        self.representation = CellRepresentation::Fields;
+        self
+    }
+
+    pub fn with_source(mut self) -> Self {
+        self.source = true;
+        self
+    }
+
     fn draw_menu_bar(&mut self, state: &mut GuiState, ui: &Ui) {
         ui.menu_bar(|| {
             ui.menu("Опции", || {
@@ -206,6 +298,30 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
         }
     }
 
+    // Исходник с метками L_ и DATA_ вместо адресов, например для дампа из .bpc
+    fn on_disassemble(&mut self, state: &mut GuiState) {
+        let Some(path) = FileDialog::new().add_filter("", &["mm"]).save_file() else {
+            state.popup_manager.open(PopupMessage::new(
+                "Ошибка выбора файла",
+                "Не удалось выбрать файл",
+            ));
+            return;
+        };
+
+        let page = self.page.borrow();
+        let source = disassemble(&page.data, &page.parser);
+        match std::fs::write(&path, source) {
+            Ok(_) => state.popup_manager.open(PopupMessage::new(
+                "Успех",
+                format!("Программа записана в {}", path.display()),
+            )),
+            Err(e) => state.popup_manager.open(PopupMessage::new(
+                "Провал",
+                format!("Не могу записать {}: {}", path.display(), e),
+            )),
+        }
+    }
+
     fn save_to_file(&mut self, file: &str) -> Result<(), String> {
         let mut f = OpenOptions::new()
             .create(true)
@@ -215,23 +331,8 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             .open(file)
             .map_err(|e| e.to_string())?;
 
-        let mut s = String::new();
-        let mut prev_zero = true;
-
-        for (pos, cell) in self.page.borrow().data.iter().enumerate() {
-            let v = cell.get();
-            if v == 0 {
-                prev_zero = true
-            } else {
-                if prev_zero {
-                    s.push_str(format!("$pos {:X}\n", pos).as_str())
-                }
-                let str = self.page.borrow().parser.parse(v).file_string();
-                s.push_str(str.as_str());
-                s.push('\n');
-                prev_zero = false;
-            }
-        }
+        let page = self.page.borrow();
+        let s = write_file(&page.data, &page.parser);
 
         f.write(s.as_bytes()).map_err(|_| "Can't write file")?;
         f.flush().map_err(|_| "Can't write file")?;
@@ -239,7 +340,7 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
         Ok(())
     }
 
-    fn choose_file(state: &mut GuiState, filter: Option<&str>) -> Option<File> {
+    fn choose_path(state: &mut GuiState, filter: Option<&str>) -> Option<PathBuf> {
         let filter = filter.map(|f| [f]);
         let dialog = if filter.is_none() {
             FileDialog::new()
@@ -254,10 +355,11 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             ));
             return None;
         };
-        let file_name = file_name
-            .into_os_string()
-            .into_string()
-            .unwrap_or("".to_owned());
+        Some(file_name)
+    }
+
+    fn choose_file(state: &mut GuiState, filter: Option<&str>) -> Option<File> {
+        let file_name = Self::choose_path(state, filter)?;
 
         File::open(file_name).map(Some).unwrap_or_else(|e| {
             state
@@ -267,27 +369,60 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
         })
     }
 
-    fn on_load_from_file(&mut self, state: &mut GuiState) {
-        let Some(mut f) = Self::choose_file(state, Some("mm")) else { return };
-
-        let parse_result =
-            match crate::parse::file::parse_file(&mut f, &self.page.borrow().parser, 0xFF) {
-                Ok(result) => result,
-                Err(msg) => {
-                    state
-                        .popup_manager
-                        .open(PopupMessage::new("Ошибка во время парсинга", msg));
-                    return;
-                }
-            };
+    // merge - положить программу поверх памяти, не обнуляя остальные ячейки
+    fn on_load_from_file(&mut self, state: &mut GuiState, merge: bool) {
+        let Some(path) = Self::choose_path(state, Some("mm")) else { return };
+        let mut f = match File::open(&path) {
+            Ok(f) => f,
+            Err(e) => {
+                state
+                    .popup_manager
+                    .open(PopupMessage::new("Ошибка открытия файла", e.to_string()));
+                return;
+            }
+        };
+
+        // Программа может занимать всю страницу памяти
+        let max_size = (self.page.borrow().data.len() - 1) as u16;
+        // Путь нужен, чтобы $include искал файлы рядом с программой
+        let mut parse_result = match crate::parse::file::parse_file(
+            &mut f,
+            Some(&path),
+            &self.page.borrow().parser,
+            max_size,
+        ) {
+            Ok(result) => result,
+            Err(diagnostics) => {
+                state.popup_manager.open(PopupDiagnostics::new(diagnostics));
+                return;
+            }
+        };
+
+        if merge {
+            parse_result.overlay(&mut self.page.borrow_mut().data);
+        } else {
+            parse_result.load_into(&mut self.page.borrow_mut().data);
+        }
+
+        if self.write_listing {
+            let listing_path = path.with_extension("lst");
+            let listing = listing(&parse_result, &self.page.borrow().parser);
+            if let Err(e) = std::fs::write(&listing_path, listing) {
+                state.popup_manager.open(PopupMessage::new(
+                    "Ошибка записи листинга",
+                    format!("Не могу записать {}: {}", listing_path.display(), e),
+                ));
+            }
+        }
 
-        let mem = &mut self.page.borrow_mut().data;
-        for x in mem.iter_mut() {
-            x.set(0)
+        // Программа загружена, но на подозрительные места стоит посмотреть
+        let warnings = std::mem::take(&mut parse_result.warnings);
+        if !warnings.is_empty() {
+            state.popup_manager.open(PopupDiagnostics::new(warnings));
         }
 
-        for (pos, v) in parse_result {
-            mem.get_mut(pos as usize).unwrap().set(v);
+        if self.source {
+            state.set_program(parse_result);
         }
     }
 
@@ -296,79 +431,102 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             return;
         };
 
-        let mut start_pos: Option<u16> = None;
+        let size = self.page.borrow().data.len();
+        let bpc = match read_bpc(BufReader::new(f), size) {
+            Ok(bpc) => bpc,
+            Err(e) => {
+                state.popup_manager.open(PopupMessage::new("Ошибочка", e));
+                return;
+            }
+        };
 
-        let mut parse_result = Vec::<(u16, u16)>::new();
+        let mut page = self.page.borrow_mut();
+        // Имена и комментарии прошлой программы к этой памяти не относятся
+        page.data.iter_mut().for_each(|x| *x = MemoryCell::new());
+        for (pos, v) in bpc.cells {
+            page.data[pos as usize].set(v);
+        }
 
-        for (line, line_num) in BufReader::new(f).lines().zip(1..) {
-            match line {
-                Ok(line) => {
-                    let split: Vec<&str> = line.split(' ').collect();
+        if let Some(pos) = bpc.start {
+            state.computer.registers.r_command_counter = pos;
+        }
+        if self.source {
+            state.forget_program();
+        }
+    }
 
-                    if split.len() < 2 {
-                        state.popup_manager.open(PopupMessage::new(
-                            "Ошибочка",
-                            format!("Неверный формат({}) на строчке {}", line, line_num),
-                        ));
-                        return;
-                    }
+    // BasePC начнет программу с ячейки start
+    fn save_bpc(&mut self, state: &mut GuiState, start: u16) {
+        let Some(path) = FileDialog::new().add_filter("", &["bpc"]).save_file() else {
+            state.popup_manager.open(PopupMessage::new(
+                "Ошибка выбора файла",
+                "Не удалось выбрать файл",
+            ));
+            return;
+        };
 
-                    let pos = u16::from_str_radix(split[0], 16);
-                    if let Err(_e) = pos {
-                        state.popup_manager.open(PopupMessage::new(
-                            "Ошибочка",
-                            format!(
-                                "Не могу распарсить позицию {} на строчке {}",
-                                split[0], line_num
-                            ),
-                        ));
-                        return;
-                    }
-                    let pos = pos.unwrap();
-
-                    let cmd_str = if split[1].starts_with('+') {
-                        start_pos = Some(pos);
-                        split[1]
-                    } else {
-                        &split[1][1..]
-                    };
-
-                    let Ok(cmd) = u16::from_str_radix(cmd_str, 16) else {
-                        state.popup_manager.open(PopupMessage::new(
-                            "Ошибочка",
-                            format!(
-                                "Не могу распарсить команду {} на строчке {}",
-                                cmd_str, line_num
-                            ),
-                        ));
-                        return;
-                    };
-
-                    parse_result.push((pos, cmd))
-                }
-                Err(e) => state
-                    .popup_manager
-                    .open(PopupMessage::new("Ошибочка", e.to_string())),
-            }
+        let text = write_bpc(&self.page.borrow().data, start);
+        match std::fs::write(&path, text) {
+            Ok(_) => state.popup_manager.open(PopupMessage::new(
+                "Успех",
+                format!("Успешно сохранил в файл {}", path.display()),
+            )),
+            Err(e) => state.popup_manager.open(PopupMessage::new(
+                "Провал",
+                format!("Не могу сохранить в файл \"{}\": {}", path.display(), e),
+            )),
         }
+    }
+
+    // Образ всей страницы для Verilog и других программ
+    fn save_image(&mut self, state: &mut GuiState, format: ImageFormat) {
+        let Some(path) = FileDialog::new()
+            .add_filter("", &[format.extension()])
+            .save_file()
+        else {
+            state.popup_manager.open(PopupMessage::new(
+                "Ошибка выбора файла",
+                "Не удалось выбрать файл",
+            ));
+            return;
+        };
 
-        self.page
-            .borrow_mut()
-            .data
-            .iter_mut()
-            .for_each(|x| x.set(0));
-
-        for (pos, v) in parse_result {
-            self.page
-                .borrow_mut()
-                .data
-                .get_mut(pos as usize)
-                .unwrap()
-                .set(v);
+        let image = write_image(&self.page.borrow().data, format);
+        match std::fs::write(&path, image) {
+            Ok(_) => state.popup_manager.open(PopupMessage::new(
+                "Успех",
+                format!("Успешно сохранил в файл {}", path.display()),
+            )),
+            Err(e) => state.popup_manager.open(PopupMessage::new(
+                "Провал",
+                format!("Не могу сохранить в файл \"{}\": {}", path.display(), e),
+            )),
         }
+    }
 
-        if let Some(pos) = start_pos {
-            state.computer.registers.r_command_counter = pos;
+    fn load_image(&mut self, state: &mut GuiState, format: ImageFormat) {
+        let Some(path) = Self::choose_path(state, Some(format.extension())) else {
+            return;
+        };
+        let size = self.page.borrow().data.len();
+        let words = match std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| read_image(&data, size, format))
+        {
+            Ok(words) => words,
+            Err(e) => {
+                state.popup_manager.open(PopupMessage::new("Ошибочка", e));
+                return;
+            }
+        };
+
+        let mut page = self.page.borrow_mut();
+        for (cell, word) in page.data.iter_mut().zip(words) {
+            *cell = MemoryCell::new();
+            cell.set(word);
+        }
+        if self.source {
+            state.forget_program();
         }
     }
 
@@ -378,11 +536,55 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
                 self.on_save_to_file(state);
             }
             if ui.menu_item("Загрузить") {
-                self.on_load_from_file(state);
+                self.on_load_from_file(state, false);
+            }
+            if ui.menu_item("Загрузить поверх памяти") {
+                self.on_load_from_file(state, true);
             }
             if ui.menu_item("Загрузить .bpc") {
                 self.load_bpc(state);
             }
+            if let Some(token) = ui.begin_menu("Сохранить .bpc") {
+                let counter = (self.counter_register)(&state.computer);
+                if ui.menu_item(format!("Старт с СК ({:03X})", counter)) {
+                    self.save_bpc(state, counter);
+                }
+                ui.input_text("Стартовый адрес", &mut self.bpc_start)
+                    .chars_hexadecimal(true)
+                    .build();
+                let start = u16::from_str_radix(&self.bpc_start, 16)
+                    .ok()
+                    .filter(|start| (*start as usize) < self.page.borrow().data.len());
+                if ui
+                    .menu_item_config("Старт с указанного адреса")
+                    .enabled(start.is_some())
+                    .build()
+                {
+                    self.save_bpc(state, start.unwrap());
+                }
+                token.end()
+            }
+            if let Some(token) = ui.begin_menu("Сохранить образ") {
+                for format in ImageFormat::ALL {
+                    if ui.menu_item(format.title()) {
+                        self.save_image(state, format);
+                    }
+                }
+                token.end()
+            }
+            if let Some(token) = ui.begin_menu("Загрузить образ") {
+                for format in ImageFormat::ALL {
+                    if ui.menu_item(format.title()) {
+                        self.load_image(state, format);
+                    }
+                }
+                token.end()
+            }
+            if ui.menu_item("Дизассемблировать в .mm") {
+                self.on_disassemble(state);
+            }
+            ui.menu_item_config("Писать листинг при загрузке")
+                .build_with_ref(&mut self.write_listing);
 
             token.end()
         }
@@ -404,6 +606,14 @@ impl<I: CommandInfo, P: Parser<I>, F: Fn(&Computer) -> u16> CellsTool<I, P, F> {
             {
                 self.representation = CellRepresentation::Binary
             }
+            if self.fields_editor
+                && ui
+                    .menu_item_config(CellRepresentation::Fields.title())
+                    .selected(self.representation == CellRepresentation::Fields)
+                    .build()
+            {
+                self.representation = CellRepresentation::Fields
+            }
             token.end()
         }
     }
@@ -0,0 +1,313 @@
+use crate::interpreter::{FETCH_ADDRESS, HALT_ADDRESS, INTERRUPT_ADDRESS};
+use crate::parse::cfg::{micro_graph, program_graph, write_dot, Block, EdgeKind, Graph};
+use crate::ui::gui::GuiState;
+use crate::ui::popup::PopupMessage;
+use crate::ui::window::Tool;
+use imgui::{DrawListMut, Io, Ui};
+use rfd::FileDialog;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+const GAP: [f32; 2] = [30.0, 40.0];
+const PADDING: f32 = 6.0;
+
+// Блок на холсте: левый верхний угол и размер относительно начала холста
+struct Placement {
+    pos: [f32; 2],
+    size: [f32; 2],
+    layer: usize,
+}
+
+// Граф переходов основной памяти или микропрограммы.
+// Блоки разложены по слоям: чем дальше от входа, тем ниже.
+pub struct CfgTool {
+    micro: bool,
+    // Вход в программу. Пустая строка - текущий СК
+    start: String,
+}
+
+impl CfgTool {
+    pub fn new() -> CfgTool {
+        CfgTool {
+            micro: false,
+            start: String::new(),
+        }
+    }
+
+    fn entries(&self, state: &GuiState) -> Vec<u16> {
+        if self.micro {
+            // Выборка команды, прерывание и останов - с них начинаются все ветки МПУ
+            return vec![
+                FETCH_ADDRESS as u16,
+                INTERRUPT_ADDRESS as u16,
+                HALT_ADDRESS as u16,
+            ];
+        }
+        let start = u16::from_str_radix(&self.start, 16)
+            .unwrap_or(state.computer.registers.r_command_counter);
+        vec![start & state.computer.config.address_mask()]
+    }
+
+    fn graph(&self, state: &GuiState) -> Graph {
+        let entries = self.entries(state);
+        if self.micro {
+            let memory = state.computer.mc_memory.borrow();
+            micro_graph(
+                &memory.data,
+                &memory.parser,
+                &state.computer.config,
+                &entries,
+            )
+        } else {
+            let memory = state.computer.general_memory.borrow();
+            program_graph(
+                &memory.data,
+                &memory.parser,
+                &state.computer.config,
+                &entries,
+            )
+        }
+    }
+
+    fn export(&self, state: &mut GuiState, graph: &Graph) {
+        let Some(path) = FileDialog::new().add_filter("", &["dot"]).save_file() else {
+            return;
+        };
+        let name = if self.micro {
+            "microprogram"
+        } else {
+            "program"
+        };
+        if let Err(e) = std::fs::write(&path, write_dot(graph, name)) {
+            state.popup_manager.open(PopupMessage::new(
+                "Провал",
+                format!("Не могу сохранить в файл \"{}\": {}", path.display(), e),
+            ));
+        }
+    }
+}
+
+fn block_text(block: &Block) -> String {
+    block
+        .commands
+        .iter()
+        .map(|(pos, text)| format!("{:03X}: {}", pos, text))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn edge_color(kind: EdgeKind) -> [f32; 4] {
+    match kind {
+        EdgeKind::Next => [0.6, 0.6, 0.6, 1.0],
+        EdgeKind::Jump => [0.4, 0.8, 0.4, 1.0],
+        EdgeKind::Skip => [1.0, 0.6, 0.2, 1.0],
+        EdgeKind::Call => [0.8, 0.5, 1.0, 1.0],
+    }
+}
+
+// Слой блока - длина кратчайшего пути до него от входа
+fn layout(ui: &Ui, graph: &Graph, entries: &[u16]) -> HashMap<u16, Placement> {
+    let mut layers = HashMap::<u16, usize>::new();
+    let mut queue = VecDeque::new();
+    for block in graph.blocks.iter().filter(|b| entries.contains(&b.start)) {
+        layers.insert(block.start, 0);
+        queue.push_back(block.start);
+    }
+    while let Some(from) = queue.pop_front() {
+        let layer = layers[&from] + 1;
+        for edge in graph.edges.iter().filter(|edge| edge.from == from) {
+            if let Entry::Vacant(entry) = layers.entry(edge.to) {
+                entry.insert(layer);
+                queue.push_back(edge.to);
+            }
+        }
+    }
+
+    let mut result = HashMap::new();
+    let mut x = Vec::<f32>::new();
+    let mut heights = Vec::<f32>::new();
+    // Блоки идут по адресам, поэтому внутри слоя они тоже по порядку
+    for block in &graph.blocks {
+        let layer = layers.get(&block.start).copied().unwrap_or(0);
+        if x.len() <= layer {
+            x.resize(layer + 1, 0.0);
+            heights.resize(layer + 1, 0.0);
+        }
+        let text = ui.calc_text_size(block_text(block));
+        let size = [text[0] + PADDING * 2.0, text[1] + PADDING * 2.0];
+        result.insert(
+            block.start,
+            Placement {
+                pos: [x[layer], 0.0],
+                size,
+                layer,
+            },
+        );
+        x[layer] += size[0] + GAP[0];
+        heights[layer] = heights[layer].max(size[1]);
+    }
+    let mut y = vec![0.0; heights.len()];
+    for layer in 1..heights.len() {
+        y[layer] = y[layer - 1] + heights[layer - 1] + GAP[1];
+    }
+    for placement in result.values_mut() {
+        placement.pos[1] = y[placement.layer];
+    }
+    result
+}
+
+fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn arrow(draw_list: &DrawListMut, points: &[[f32; 2]], color: [f32; 4]) {
+    for pair in points.windows(2) {
+        draw_list
+            .add_line(pair[0], pair[1], color)
+            .thickness(1.5)
+            .build();
+    }
+    let [.., from, to] = points else {
+        return;
+    };
+    let length = ((to[0] - from[0]).powi(2) + (to[1] - from[1]).powi(2)).sqrt();
+    if length == 0.0 {
+        return;
+    }
+    let direction = [(to[0] - from[0]) / length, (to[1] - from[1]) / length];
+    let back = [to[0] - direction[0] * 8.0, to[1] - direction[1] * 8.0];
+    let side = [-direction[1] * 4.0, direction[0] * 4.0];
+    draw_list
+        .add_triangle(
+            *to,
+            [back[0] + side[0], back[1] + side[1]],
+            [back[0] - side[0], back[1] - side[1]],
+            color,
+        )
+        .filled(true)
+        .build();
+}
+
+fn draw_graph(ui: &Ui, graph: &Graph, entries: &[u16], current: u16) {
+    let placements = layout(ui, graph, entries);
+    let origin = ui.cursor_screen_pos();
+    let draw_list = ui.get_window_draw_list();
+
+    let mut extent = [0.0f32, 0.0f32];
+    for edge in &graph.edges {
+        let (Some(from), Some(to)) = (placements.get(&edge.from), placements.get(&edge.to)) else {
+            continue;
+        };
+        let color = edge_color(edge.kind);
+        let points = if to.layer > from.layer {
+            vec![
+                add(
+                    origin,
+                    [from.pos[0] + from.size[0] / 2.0, from.pos[1] + from.size[1]],
+                ),
+                add(origin, [to.pos[0] + to.size[0] / 2.0, to.pos[1]]),
+            ]
+        } else {
+            // Переход назад обходит блоки справа
+            let right = (from.pos[0] + from.size[0]).max(to.pos[0] + to.size[0]) + GAP[0] / 2.0;
+            let start = [from.pos[0] + from.size[0], from.pos[1] + from.size[1] / 2.0];
+            let end = [to.pos[0] + to.size[0], to.pos[1] + to.size[1] / 2.0];
+            extent[0] = extent[0].max(right);
+            vec![
+                add(origin, start),
+                add(origin, [right, start[1]]),
+                add(origin, [right, end[1]]),
+                add(origin, end),
+            ]
+        };
+        arrow(&draw_list, &points, color);
+    }
+
+    for block in &graph.blocks {
+        let placement = &placements[&block.start];
+        let min = add(origin, placement.pos);
+        let max = add(min, placement.size);
+        let border = if block.contains(current) {
+            [1.0, 0.8, 0.0, 1.0]
+        } else {
+            [0.5, 0.5, 0.5, 1.0]
+        };
+        draw_list
+            .add_rect(min, max, [0.1, 0.1, 0.12, 1.0])
+            .filled(true)
+            .build();
+        draw_list.add_rect(min, max, border).build();
+        draw_list.add_text(
+            add(min, [PADDING, PADDING]),
+            [1.0, 1.0, 1.0, 1.0],
+            block_text(block),
+        );
+        extent[0] = extent[0].max(placement.pos[0] + placement.size[0]);
+        extent[1] = extent[1].max(placement.pos[1] + placement.size[1]);
+    }
+    // Холст нарисован поверх окна, а прокрутке нужен элемент такого же размера
+    ui.dummy(add(extent, [PADDING, PADDING]));
+}
+
+impl Tool for CfgTool {
+    fn draw(&mut self, ui: &Ui, _io: &Io, state: &mut GuiState) {
+        let has_microcode = state.computer.profile.has_microcode();
+        let graph = self.graph(state);
+
+        ui.menu_bar(|| {
+            if ui
+                .menu_item_config("Основная память")
+                .selected(!self.micro)
+                .build()
+            {
+                self.micro = false;
+            }
+            if ui
+                .menu_item_config("Микропрограмма")
+                .selected(self.micro)
+                .enabled(has_microcode)
+                .build()
+            {
+                self.micro = true;
+            }
+            if ui.menu_item("Экспорт DOT") {
+                self.export(state, &graph);
+            }
+        });
+        if self.micro && !has_microcode {
+            self.micro = false;
+        }
+
+        if !self.micro {
+            ui.set_next_item_width(60.0);
+            ui.input_text("Вход (пусто - СК)", &mut self.start)
+                .chars_hexadecimal(true)
+                .build();
+            ui.same_line();
+        }
+        for (kind, name) in [
+            (EdgeKind::Next, "по порядку"),
+            (EdgeKind::Jump, "переход"),
+            (EdgeKind::Skip, "пропуск"),
+            (EdgeKind::Call, "вызов"),
+        ] {
+            ui.text_colored(edge_color(kind), name);
+            ui.same_line();
+        }
+        ui.new_line();
+
+        if graph.blocks.is_empty() {
+            ui.text_wrapped("По адресу входа пустая ячейка, граф не из чего строить.");
+            return;
+        }
+        let current = if self.micro {
+            state.computer.registers.r_micro_command_counter as u16
+        } else {
+            state.computer.registers.r_command_counter
+        };
+        let entries = self.entries(state);
+        ui.child_window("cfg")
+            .horizontal_scrollbar(true)
+            .build(|| draw_graph(ui, &graph, &entries, current));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::compiler::{compile, Compiled, Mapped};
+use crate::parse::diagnostic::Diagnostic;
+use crate::ui::gui::GuiState;
+use crate::ui::popup::{severity_color, PopupMessage};
+use crate::ui::window::Tool;
+use imgui::{Io, StyleColor, Ui};
+use rfd::FileDialog;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+const EXAMPLE: &str = "// Сумма элементов массива
+var a[4];
+var i;
+var sum;
+
+proc fill {
+    i = 0;
+    while i < 4 {
+        a[i] = i << 2;
+        i = i + 1;
+    }
+}
+
+call fill;
+i = 0;
+while i != 4 {
+    sum = sum + a[i];
+    i = i + 1;
+}
+";
+
+// Маленький язык высокого уровня: пишем программу, смотрим, во что она скомпилировалась,
+// и выполняем ее по шагам, видя текущую строку исходника
+pub struct CompilerTool {
+    text: String,
+    path: Option<PathBuf>,
+    compiled: Option<Compiled>,
+    // Ячейки загруженной программы и строки, из которых они получились
+    map: Vec<Mapped>,
+    diagnostics: Vec<Diagnostic>,
+    status: String,
+}
+
+impl CompilerTool {
+    pub fn new() -> CompilerTool {
+        CompilerTool {
+            text: EXAMPLE.to_string(),
+            path: None,
+            compiled: None,
+            map: Vec::new(),
+            diagnostics: Vec::new(),
+            status: String::new(),
+        }
+    }
+
+    fn on_compile(&mut self, state: &mut GuiState) {
+        let name = self
+            .path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map(|name| Rc::from(name.to_string_lossy().as_ref()));
+        self.map.clear();
+        let compiled = match compile(&self.text, name) {
+            Ok(compiled) => compiled,
+            Err(diagnostics) => {
+                self.status = "Не скомпилировано".to_string();
+                self.diagnostics = diagnostics;
+                self.compiled = None;
+                return;
+            }
+        };
+
+        let result = {
+            let memory = state.computer.general_memory.borrow();
+            let max_size = (memory.data.len() - 1) as u16;
+            compiled.assemble(&memory.parser, max_size)
+        };
+        match result {
+            Ok(program) => {
+                self.diagnostics.clear();
+                program.load_into(&mut state.computer.general_memory.borrow_mut().data);
+                self.map = compiled.map(&program);
+                self.status = format!("Загружено ячеек: {}", program.cells.len());
+                state.set_program(program);
+                state.jump_requested = true;
+            }
+            // Код рассчитан на команды классической БЭВМ
+            Err(diagnostics) => {
+                self.status = "Сгенерированный код не собрался".to_string();
+                self.diagnostics = diagnostics;
+            }
+        }
+        self.compiled = Some(compiled);
+    }
+
+    fn on_open(&mut self, state: &mut GuiState) {
+        let Some(path) = FileDialog::new().add_filter("", &["bl"]).pick_file() else {
+            return;
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                self.text = text;
+                self.path = Some(path);
+                self.compiled = None;
+                self.map.clear();
+                self.diagnostics.clear();
+                self.status.clear();
+            }
+            Err(e) => state.popup_manager.open(PopupMessage::new(
+                "Ошибка открытия файла",
+                format!("Не могу прочитать {}: {}", path.display(), e),
+            )),
+        }
+    }
+
+    fn save(state: &mut GuiState, extension: &str, text: &str) -> Option<PathBuf> {
+        let path = FileDialog::new().add_filter("", &[extension]).save_file()?;
+        match std::fs::write(&path, text) {
+            Ok(_) => Some(path),
+            Err(e) => {
+                state.popup_manager.open(PopupMessage::new(
+                    "Провал",
+                    format!("Не могу сохранить в файл \"{}\": {}", path.display(), e),
+                ));
+                None
+            }
+        }
+    }
+
+    // Строка исходника, которую сейчас выполняет ЭВМ
+    fn current(&self, state: &GuiState) -> Option<&Mapped> {
+        let counter = state.computer.registers.r_command_counter;
+        self.map
+            .iter()
+            .find(|mapped| mapped.pos == counter && mapped.line != 0)
+    }
+
+    fn draw_assembly(&self, ui: &Ui, state: &GuiState, compiled: &Compiled) {
+        let current = self.current(state).map(|mapped| mapped.assembly_line);
+        for ((text, line), assembly_line) in compiled.assembly.lines().zip(&compiled.lines).zip(1..)
+        {
+            let address = self
+                .map
+                .iter()
+                .find(|mapped| mapped.assembly_line == assembly_line)
+                .map_or_else(|| "   ".to_string(), |mapped| format!("{:03X}", mapped.pos));
+            let source = if *line == 0 {
+                "    ".to_string()
+            } else {
+                format!("{:>4}", line)
+            };
+            ui.text_disabled(format!("{} {}", source, address));
+            ui.same_line();
+            if current == Some(assembly_line) {
+                let token = ui.push_style_color(StyleColor::Text, [1.0, 0.8, 0.0, 1.0]);
+                ui.text(text);
+                token.pop();
+            } else if text.starts_with('#') {
+                ui.text_disabled(text);
+            } else {
+                ui.text(text);
+            }
+            if *line != 0 && ui.is_item_hovered() {
+                let source = self.text.lines().nth(*line as usize - 1).unwrap_or("");
+                ui.tooltip_text(format!("Строка {}: {}", line, source.trim()));
+            }
+        }
+    }
+}
+
+impl Tool for CompilerTool {
+    fn draw(&mut self, ui: &Ui, _io: &Io, state: &mut GuiState) {
+        ui.menu_bar(|| {
+            if let Some(t) = ui.begin_menu("Файл") {
+                if ui.menu_item("Открыть") {
+                    self.on_open(state);
+                }
+                if ui.menu_item("Сохранить") {
+                    if let Some(path) = CompilerTool::save(state, "bl", &self.text) {
+                        self.path = Some(path);
+                    }
+                }
+                if let Some(compiled) = &self.compiled {
+                    if ui.menu_item("Сохранить .mm") {
+                        CompilerTool::save(state, "mm", &compiled.assembly);
+                    }
+                }
+                t.end();
+            }
+            if ui.menu_item("Компилировать и загрузить") {
+                self.on_compile(state);
+            }
+            if !self.status.is_empty() {
+                ui.text_disabled(&self.status);
+            }
+        });
+
+        let Some(tab_bar) = ui.tab_bar("compiler") else {
+            return;
+        };
+        if let Some(tab) = ui.tab_item("Код") {
+            let lines = self.diagnostics.len() + 1;
+            let height = -ui.text_line_height_with_spacing() * lines as f32;
+            if ui
+                .input_text_multiline("##program", &mut self.text, [-1.0, height])
+                .build()
+            {
+                // Карта относится к старому тексту
+                self.map.clear();
+            }
+            match self.current(state) {
+                Some(mapped) => ui.text(format!("Выполняется строка {}", mapped.line)),
+                None => ui.text_disabled("Скомпилируйте программу и запустите ее с адреса 010"),
+            }
+            for diagnostic in &self.diagnostics {
+                ui.text_colored(
+                    severity_color(diagnostic.severity),
+                    format!(
+                        "Строка {}: {}: {}",
+                        diagnostic.location,
+                        diagnostic.severity.title(),
+                        diagnostic.message
+                    ),
+                );
+            }
+            tab.end();
+        }
+        if let Some(tab) = ui.tab_item("Ассемблер") {
+            match &self.compiled {
+                Some(compiled) => self.draw_assembly(ui, state, compiled),
+                None => {
+                    ui.text_wrapped("Здесь появится код, в который скомпилировалась программа.")
+                }
+            }
+            tab.end();
+        }
+        tab_bar.end();
+    }
+}
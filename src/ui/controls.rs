@@ -1,6 +1,8 @@
-use std::{collections::VecDeque, borrow::BorrowMut};
+use std::collections::VecDeque;
 
+use crate::interpreter::ExecutionMode;
 use crate::model::Registers;
+use crate::profile::{MachineConfig, MachineProfile, MAX_IO_DEVICES, MIN_ADDRESS_BITS};
 
 use crate::parse::mc::ExecutionResult;
 use crate::ui::gui::GuiState;
@@ -11,6 +13,7 @@ use imgui::{Io, Ui};
 pub struct SmartControlsTool {
     auto_run: bool,
     history: VecDeque<HistoryEntry>,
+    mode: ExecutionMode,
 }
 
 struct HistoryEntry {
@@ -24,12 +27,14 @@ impl Tool for SmartControlsTool {
 }
 
 const HISTORY_MAX_SIZE: usize = 15;
+const FAST_COMMANDS_PER_FRAME: usize = 10000;
 
 impl SmartControlsTool {
     pub fn new() -> Self {
         Self {
             auto_run: false,
             history: VecDeque::with_capacity(HISTORY_MAX_SIZE),
+            mode: ExecutionMode::Microcode,
         }
     }
 
@@ -48,7 +53,86 @@ impl SmartControlsTool {
         if let Some(tok) = ui.begin_menu_bar() {
             if ui.menu_item("Сброс ЭВМ!") {
                 state.computer.reset_memory();
-                state.computer.registers = Registers::new()
+                state.computer.registers = Registers::new();
+                state.forget_program();
+            }
+            if let Some(t) = ui.begin_menu("Модель ЭВМ") {
+                for profile in MachineProfile::ALL {
+                    if ui
+                        .menu_item_config(profile.title())
+                        .selected(state.computer.profile == profile)
+                        .build()
+                        && state.computer.profile != profile
+                    {
+                        state.computer.set_profile(profile);
+                        state.forget_program();
+                        self.history.clear();
+                        self.auto_run = false;
+                    }
+                }
+                ui.separator();
+                let config = state.computer.config;
+                if let Some(t) = ui.begin_menu("Размер памяти") {
+                    for bits in MIN_ADDRESS_BITS..=state.computer.profile.max_address_bits() {
+                        let size = 1usize << bits;
+                        if ui
+                            .menu_item_config(format!("{} ячеек ({} бит адреса)", size, bits))
+                            .selected(config.address_bits == bits)
+                            .build()
+                            && config.address_bits != bits
+                        {
+                            state.computer.set_config(MachineConfig {
+                                address_bits: bits,
+                                ..config
+                            });
+                            state.forget_program();
+                            self.history.clear();
+                            self.auto_run = false;
+                        }
+                    }
+                    t.end();
+                }
+                if let Some(t) = ui.begin_menu("Число ВУ") {
+                    for devices in 1..=MAX_IO_DEVICES {
+                        if ui
+                            .menu_item_config(format!("{}", devices))
+                            .selected(config.io_devices == devices)
+                            .build()
+                            && config.io_devices != devices
+                        {
+                            state.computer.set_config(MachineConfig {
+                                io_devices: devices,
+                                ..config
+                            });
+                            state.forget_program();
+                            self.history.clear();
+                            self.auto_run = false;
+                        }
+                    }
+                    t.end();
+                }
+                t.end();
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Классическая БЭВМ из методички или БЭВМ-NG со стеком и режимами адресации.\nПри смене модели или размеров памяти память и регистры сбрасываются.")
+            }
+            if let Some(t) = ui.begin_menu_with_enabled(
+                "Режим исполнения",
+                state.computer.profile.has_microcode(),
+            ) {
+                for mode in [ExecutionMode::Microcode, ExecutionMode::Fast] {
+                    if ui
+                        .menu_item_config(mode.title())
+                        .selected(self.mode == mode)
+                        .build()
+                    {
+                        self.mode = mode;
+                    }
+                }
+                t.end();
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Точный режим исполняет микрокоманды одну за другой.\nБыстрый режим исполняет команды основной памяти целиком, повторяя стандартную микропрограмму.\nВ быстром режиме за кадр успевает выполниться гораздо больше команд.")
             }
             tok.end();
         }
@@ -56,6 +140,15 @@ impl SmartControlsTool {
         let w = ui.content_region_avail().first().unwrap() / 3.0 - 6.0;
         let h = ui.content_region_avail().get(1).unwrap() / 2.0 - 3.0;
 
+        // Без микропрограммы команды исполняются только целиком
+        let has_microcode = state.computer.profile.has_microcode();
+        let mode = if has_microcode {
+            self.mode
+        } else {
+            ExecutionMode::Fast
+        };
+
+        let disabled = ui.begin_disabled(!has_microcode);
         if ui.button_with_size("Микро шаг", [w, h]) {
             self.make_history_entry(state);
             state.computer.registers.set_execute_by_tick(true);
@@ -63,6 +156,7 @@ impl SmartControlsTool {
             state.computer.registers.set_program_mode(false);
             state.computer.micro_step();
         }
+        disabled.end();
 
         if ui.is_item_hovered() {
             ui.tooltip_text("Устанавливает флаг \"Исполнение\" в 1\nУстанавливает флаг \"Состояние тумблера\" в 0.\nУстанавливается флаг \"Программа\" в 0.\nВыполняется текущая микрокоманда и происходит переход к следующнй.")
@@ -75,7 +169,14 @@ impl SmartControlsTool {
             state.computer.registers.set_execute_by_tick(false);
             state.computer.registers.set_lever(false);
             state.computer.registers.set_program_mode(false);
-            state.computer.find(|res| res == &ExecutionResult::Halted);
+            match mode {
+                ExecutionMode::Microcode => {
+                    state.computer.find(|res| res == &ExecutionResult::Halted);
+                }
+                ExecutionMode::Fast => {
+                    state.computer.fast_run(FAST_COMMANDS_PER_FRAME);
+                }
+            }
         }
         if ui.is_item_hovered() {
             ui.tooltip_text("Устанавливает флаг \"Исполнение\" в 0\nУстанавливает флаг \"Состояние тумблера\" в 0.\nУстанавливается флаг \"Программа\" в 0.\nВыполняется полный цикл микрокоманд.\nГрубо говоря выполняется одна команда.")
@@ -95,13 +196,18 @@ impl SmartControlsTool {
 
         if ui.button_with_size("Пуск", [w, h]) {
             self.make_history_entry(state);
-            state.computer.registers.r_micro_command_counter = 0xA8;
+            if has_microcode {
+                state.computer.registers.r_micro_command_counter = 0xA8;
+            } else {
+                state.computer.registers.r_counter = 0;
+                state.computer.registers.r_status = 0;
+            }
             state.computer.registers.set_execute_by_tick(false);
             state.computer.registers.set_lever(true);
             state.computer.registers.set_program_mode(true);
         }
         if ui.is_item_hovered() {
-            ui.tooltip_text("Устанавливает флаг \"Исполнение\" в 0\nУстанавливает флаг \"Состояние тумблера\" в 1.\nУстанавливается флаг \"Программа\" в 1.\nУстанавливает СчМК в 0A8 то есть сбрасывает состояние регистров ЭВМ\nЭВМ начинает самостоятельно выполнять команду за командой.")
+            ui.tooltip_text("Устанавливает флаг \"Исполнение\" в 0\nУстанавливает флаг \"Состояние тумблера\" в 1.\nУстанавливается флаг \"Программа\" в 1.\nУстанавливает СчМК в 0A8 то есть сбрасывает состояние регистров ЭВМ\nВ БЭВМ-NG сбрасывает аккумулятор и регистр состояния\nЭВМ начинает самостоятельно выполнять команду за командой.")
         }
         ui.same_line();
         if ui.button_with_size("Продолжить", [w, h]) {
@@ -125,11 +231,39 @@ impl SmartControlsTool {
             self.auto_run = true;
         }
         if self.auto_run {
-            if state
-                .computer.borrow_mut()
-                .take(100)
-                .find(|res| res == &ExecutionResult::Halted).is_some()
-            {
+            // Точки останова проверяются после каждого шага, поэтому с точки,
+            // на которой остановились, можно продолжить
+            let mut halted = false;
+            let mut breakpoint = false;
+            let steps = match mode {
+                ExecutionMode::Microcode => 100,
+                ExecutionMode::Fast => FAST_COMMANDS_PER_FRAME,
+            };
+            for _ in 0..steps {
+                let result = match mode {
+                    ExecutionMode::Microcode => state.computer.micro_step(),
+                    ExecutionMode::Fast => state.computer.fast_step(),
+                };
+                if result == ExecutionResult::Halted {
+                    halted = true;
+                    break;
+                }
+                let counter = state.computer.registers.r_command_counter;
+                if state.computer.at_command_start() && state.breakpoints.contains(&counter) {
+                    breakpoint = true;
+                    break;
+                }
+            }
+            if breakpoint {
+                let counter = state.computer.registers.r_command_counter;
+                state
+                    .computer
+                    .log(false, format!("Точка останова в ячейке {:0>3X}", counter));
+                state.computer.registers.set_lever(false);
+                self.auto_run = false;
+                state.jump_requested = true;
+            }
+            if halted {
                 if state.computer.registers.get_lever() {
                     state.popup_manager.open(PopupMessage::new(
                         "Остановочка",
@@ -0,0 +1,369 @@
+use crate::parse::diagnostic::{Diagnostic, Severity};
+use crate::parse::file::parse_file;
+use crate::parse::format::format_file;
+use crate::parse::syntax::{highlight, Highlight};
+use crate::ui::gui::GuiState;
+use crate::ui::popup::{severity_color, PopupMessage};
+use crate::ui::window::Tool;
+use imgui::{
+    InputTextCallbackHandler, InputTextMultilineCallback, Io, StyleColor, TextCallbackData, Ui,
+};
+use rfd::FileDialog;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+// Редактор .mm: пишем программу, сразу видим ошибки и загружаем ее в основную память
+pub struct EditorTool {
+    text: String,
+    path: Option<PathBuf>,
+    // Сообщения ассемблера для текущего текста
+    diagnostics: Vec<Diagnostic>,
+    checked: bool,
+    // Есть несохраненные изменения
+    modified: bool,
+    status: String,
+    // Куда поставить курсор после клика по сообщению
+    goto: Option<Range<usize>>,
+}
+
+// Выделяет в поле ввода место, на которое указывает сообщение
+struct MoveCursor<'a>(&'a mut Option<Range<usize>>);
+
+impl InputTextCallbackHandler for MoveCursor<'_> {
+    fn on_always(&mut self, mut data: TextCallbackData) {
+        if let Some(span) = self.0.take() {
+            data.set_cursor_pos(span.end);
+            *data.selection_start_mut() = span.start as i32;
+            *data.selection_end_mut() = span.end as i32;
+        }
+    }
+}
+
+impl EditorTool {
+    pub fn new() -> EditorTool {
+        EditorTool {
+            text: String::new(),
+            path: None,
+            diagnostics: Vec::new(),
+            checked: false,
+            modified: false,
+            status: String::new(),
+            goto: None,
+        }
+    }
+
+    // Имя файла, под которым ассемблер называет строки основного файла
+    fn file_name(&self) -> Option<Rc<str>> {
+        let name = self.path.as_ref()?.file_name()?.to_string_lossy();
+        Some(name.as_ref().into())
+    }
+
+    fn title(&self) -> String {
+        let name = self
+            .file_name()
+            .map_or_else(|| "без имени".to_string(), |name| name.to_string());
+        if self.modified {
+            format!("{name}*")
+        } else {
+            name
+        }
+    }
+
+    fn check(&mut self, state: &mut GuiState) {
+        let memory = state.computer.general_memory.borrow();
+        let max_size = (memory.data.len() - 1) as u16;
+        self.diagnostics = match parse_file(
+            &mut self.text.as_bytes(),
+            self.path.as_deref(),
+            &memory.parser,
+            max_size,
+        ) {
+            Ok(program) => program.warnings,
+            Err(diagnostics) => diagnostics,
+        };
+        self.checked = true;
+    }
+
+    fn on_assemble(&mut self, state: &mut GuiState) {
+        let result = {
+            let memory = state.computer.general_memory.borrow();
+            let max_size = (memory.data.len() - 1) as u16;
+            parse_file(
+                &mut self.text.as_bytes(),
+                self.path.as_deref(),
+                &memory.parser,
+                max_size,
+            )
+        };
+        self.checked = true;
+
+        let mut program = match result {
+            Ok(program) => program,
+            Err(diagnostics) => {
+                let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+                self.status = format!("Не собрано, ошибок: {errors}");
+                self.diagnostics = diagnostics;
+                return;
+            }
+        };
+        self.diagnostics = std::mem::take(&mut program.warnings);
+        program.load_into(&mut state.computer.general_memory.borrow_mut().data);
+        self.status = format!("Загружено ячеек: {}", program.cells.len());
+
+        state.set_program(program);
+        state.jump_requested = true;
+    }
+
+    fn on_format(&mut self, state: &mut GuiState) {
+        let result = {
+            let memory = state.computer.general_memory.borrow();
+            let max_size = (memory.data.len() - 1) as u16;
+            format_file(&self.text, self.path.as_deref(), &memory.parser, max_size)
+        };
+        match result {
+            Ok(text) if text == self.text => self.status = "Уже отформатировано".to_string(),
+            Ok(text) => {
+                self.text = text;
+                self.modified = true;
+                self.checked = false;
+                self.status = "Отформатировано".to_string();
+            }
+            Err(diagnostics) => {
+                self.status = "Не отформатировано: в программе ошибки".to_string();
+                self.diagnostics = diagnostics;
+                self.checked = true;
+            }
+        }
+    }
+
+    fn on_open(&mut self, state: &mut GuiState) {
+        let Some(path) = FileDialog::new().add_filter("", &["mm"]).pick_file() else {
+            return;
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                self.text = text;
+                self.path = Some(path);
+                self.modified = false;
+                self.checked = false;
+                self.status.clear();
+            }
+            Err(e) => state.popup_manager.open(PopupMessage::new(
+                "Ошибка открытия файла",
+                format!("Не могу прочитать {}: {}", path.display(), e),
+            )),
+        }
+    }
+
+    fn on_save(&mut self, state: &mut GuiState, choose: bool) {
+        let path = match &self.path {
+            Some(path) if !choose => path.clone(),
+            _ => {
+                let Some(path) = FileDialog::new().add_filter("", &["mm"]).save_file() else {
+                    return;
+                };
+                path
+            }
+        };
+        match std::fs::write(&path, &self.text) {
+            Ok(_) => {
+                self.status = format!("Сохранено в {}", path.display());
+                self.path = Some(path);
+                self.modified = false;
+                // $include теперь ищется рядом с новым файлом
+                self.checked = false;
+            }
+            Err(e) => state.popup_manager.open(PopupMessage::new(
+                "Провал",
+                format!("Не могу сохранить в файл \"{}\": {}", path.display(), e),
+            )),
+        }
+    }
+
+    fn draw_menu_bar(&mut self, ui: &Ui, state: &mut GuiState) {
+        ui.menu_bar(|| {
+            if let Some(t) = ui.begin_menu("Файл") {
+                if ui.menu_item("Новый") {
+                    *self = EditorTool::new();
+                }
+                if ui.menu_item("Открыть") {
+                    self.on_open(state);
+                }
+                if ui.menu_item("Сохранить") {
+                    self.on_save(state, false);
+                }
+                if ui.menu_item("Сохранить как") {
+                    self.on_save(state, true);
+                }
+                t.end();
+            }
+            if ui.menu_item("Собрать и загрузить") {
+                self.on_assemble(state);
+            }
+            if ui.menu_item("Форматировать") {
+                self.on_format(state);
+            }
+            ui.text_disabled(self.title());
+            if !self.status.is_empty() {
+                ui.text_disabled(&self.status);
+            }
+        });
+    }
+
+    // Байты текста, на которые указывает сообщение об основном файле
+    fn span_of(&self, diagnostic: &Diagnostic) -> Option<Range<usize>> {
+        let index = diagnostic.location.line.checked_sub(1)? as usize;
+        let start = self
+            .text
+            .split_inclusive('\n')
+            .take(index)
+            .map(str::len)
+            .sum::<usize>();
+        let line = self.text.lines().nth(index)?;
+        // Строка из подстановки макроса - выделяется вся строка вызова
+        if diagnostic.source.as_ref() != line {
+            return Some(start..start + line.len());
+        }
+        let span = &diagnostic.span;
+        let end = span.end.min(line.len());
+        Some(start + span.start.min(end)..start + end)
+    }
+
+    // Сообщения под полем ввода. Клик переносит курсор к месту ошибки
+    fn draw_diagnostics(&mut self, ui: &Ui) {
+        let file = self.file_name();
+        let mut goto = None;
+        for (i, diagnostic) in self.diagnostics.iter().enumerate() {
+            let color = ui.push_style_color(StyleColor::Text, severity_color(diagnostic.severity));
+            let text = format!(
+                "Строка {}, символ {}: {}: {}",
+                diagnostic.location,
+                diagnostic.columns().start + 1,
+                diagnostic.severity.title(),
+                diagnostic.message
+            );
+            if diagnostic.location.file != file {
+                // В подключенный файл отсюда не перейти
+                ui.text(text);
+            } else if ui.selectable(format!("{text}##{i}")) {
+                goto = self.span_of(diagnostic);
+            }
+            color.pop();
+        }
+        if goto.is_some() {
+            self.goto = goto;
+        }
+    }
+
+    fn draw_highlighted(&self, ui: &Ui, is_mnemonic: &dyn Fn(&str) -> bool) {
+        let file = self.file_name();
+        // Сообщения из подключенных файлов сюда не привязать, они идут списком сверху
+        for diagnostic in self.diagnostics.iter().filter(|d| d.location.file != file) {
+            ui.text_colored(severity_color(diagnostic.severity), diagnostic.to_string());
+        }
+
+        for (i, line) in self.text.lines().enumerate() {
+            ui.text_disabled(format!("{:>4}", i + 1));
+            ui.same_line();
+
+            let mut pos = 0;
+            for (range, kind) in highlight(line, is_mnemonic) {
+                if range.start < pos {
+                    continue;
+                }
+                if range.start > pos {
+                    ui.text(&line[pos..range.start]);
+                    ui.same_line_with_spacing(0.0, 0.0);
+                }
+                ui.text_colored(highlight_color(kind), &line[range.clone()]);
+                ui.same_line_with_spacing(0.0, 0.0);
+                pos = range.end;
+            }
+            ui.text(&line[pos..]);
+
+            let line_num = i as u32 + 1;
+            for diagnostic in self
+                .diagnostics
+                .iter()
+                .filter(|d| d.location.file == file && d.location.line == line_num)
+            {
+                ui.text_colored(
+                    severity_color(diagnostic.severity),
+                    format!(
+                        "     {} {}: {}",
+                        diagnostic.caret(),
+                        diagnostic.severity.title(),
+                        diagnostic.message
+                    ),
+                );
+            }
+        }
+    }
+}
+
+impl Tool for EditorTool {
+    fn draw(&mut self, ui: &Ui, _io: &Io, state: &mut GuiState) {
+        self.draw_menu_bar(ui, state);
+        if !self.checked {
+            self.check(state);
+        }
+
+        let Some(tab_bar) = ui.tab_bar("editor") else {
+            return;
+        };
+        if let Some(tab) = ui.tab_item("Правка") {
+            let count = |severity: Severity| {
+                self.diagnostics
+                    .iter()
+                    .filter(|d| d.severity == severity)
+                    .count()
+            };
+            let summary = format!(
+                "Ошибок: {}, предупреждений: {}",
+                count(Severity::Error),
+                count(Severity::Warning)
+            );
+            let line = ui.text_line_height_with_spacing();
+            let list = line * self.diagnostics.len().min(6) as f32;
+            if self.goto.is_some() {
+                ui.set_keyboard_focus_here();
+            }
+            if ui
+                .input_text_multiline("##source", &mut self.text, [-1.0, -line - list])
+                .callback(
+                    InputTextMultilineCallback::ALWAYS,
+                    MoveCursor(&mut self.goto),
+                )
+                .build()
+            {
+                self.modified = true;
+                self.checked = false;
+            }
+            ui.text(summary);
+            if list > 0.0 {
+                ui.child_window("diagnostics")
+                    .size([0.0, list])
+                    .build(|| self.draw_diagnostics(ui));
+            }
+            tab.end();
+        }
+        if let Some(tab) = ui.tab_item("Подсветка") {
+            let memory = state.computer.general_memory.borrow();
+            self.draw_highlighted(ui, &|word| memory.parser.is_mnemonic(word));
+            tab.end();
+        }
+        tab_bar.end();
+    }
+}
+
+fn highlight_color(kind: Highlight) -> [f32; 4] {
+    match kind {
+        Highlight::Mnemonic => [0.4, 0.7, 1.0, 1.0],
+        Highlight::Directive => [0.8, 0.5, 1.0, 1.0],
+        Highlight::Label => [0.4, 0.9, 0.4, 1.0],
+        Highlight::Reference => [0.9, 0.9, 0.4, 1.0],
+        Highlight::Text => [0.9, 0.6, 0.4, 1.0],
+        Highlight::Comment => [0.5, 0.5, 0.5, 1.0],
+    }
+}
@@ -4,15 +4,21 @@ extern crate imgui_opengl_renderer;
 extern crate imgui_sdl2;
 extern crate sdl2;
 
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::rc::Rc;
 use std::time::Instant;
 
 use imgui::{Condition, Ui};
 use sdl2::video::Window as SDLWindow;
 
-use crate::model::Computer;
+use crate::model::{Computer, MemoryCell};
+use crate::parse::file::Program;
 use crate::parse::CommandInfo;
 use crate::ui::cells::CellsTool;
+use crate::ui::cfg::CfgTool;
 use crate::ui::controls::SmartControlsTool;
+use crate::ui::compiler::CompilerTool;
+use crate::ui::editor::EditorTool;
 use crate::ui::help::HelpTool;
 use crate::ui::highlight::CommandHighlightTool;
 use crate::ui::io::IOTool;
@@ -20,7 +26,9 @@ use crate::ui::layout::LayoutTool;
 use crate::ui::log::LogTool;
 use crate::ui::popup::Popup;
 use crate::ui::registers::RegistersTool;
+use crate::ui::source::SourceTool;
 use crate::ui::status::StatusTool;
+use crate::ui::symbols::SymbolsTool;
 use crate::ui::window::{Tool, WindowTool};
 
 use self::imgui::sys::ImGuiKey_Backspace;
@@ -52,6 +60,36 @@ impl PopupManager {
     }
 }
 
+// Имена ячеек основной памяти в обе стороны. Собираются при загрузке программы, а не каждый кадр
+#[derive(Default)]
+pub struct Labels {
+    pub addresses: HashMap<String, u16>,
+    // По порядку адресов, как в таблице имен
+    pub names: BTreeMap<u16, String>,
+    // Ширина колонки имен в таблице ячеек
+    pub width: Option<usize>,
+}
+
+impl Labels {
+    fn collect(memory: &[MemoryCell]) -> Labels {
+        let names = memory
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, cell)| Some((pos as u16, cell.name.clone()?)))
+            .collect::<BTreeMap<u16, String>>();
+        let addresses = names
+            .iter()
+            .map(|(pos, name)| (name.clone(), *pos))
+            .collect::<HashMap<String, u16>>();
+        let width = names.values().map(|name| name.chars().count()).max();
+        Labels {
+            addresses,
+            names,
+            width,
+        }
+    }
+}
+
 pub struct GuiState {
     pub last_file_general: Option<String>,
     pub last_file_mc: Option<String>,
@@ -61,6 +99,12 @@ pub struct GuiState {
     pub popup_manager: PopupManager,
     pub current_command: Option<Box<dyn CommandInfo>>,
     pub jump_requested: bool,
+    // Ячейка основной памяти, к которой надо прокрутить таблицу
+    pub jump_to: Option<u16>,
+    // Программа, загруженная из .mm в основную память, и точки останова в ней
+    pub program: Option<Program>,
+    pub breakpoints: BTreeSet<u16>,
+    pub labels: Rc<Labels>,
 }
 
 impl GuiState {
@@ -74,8 +118,27 @@ impl GuiState {
             popup_manager: PopupManager::new(),
             current_command: None,
             jump_requested: false,
+            jump_to: None,
+            program: None,
+            breakpoints: BTreeSet::new(),
+            labels: Rc::default(),
         }
     }
+
+    // Программу уже записали в основную память: имена ячеек берутся оттуда
+    pub fn set_program(&mut self, program: Program) {
+        let labels = Labels::collect(&self.computer.general_memory.borrow().data);
+        self.labels = Rc::new(labels);
+        self.program = Some(program);
+        self.breakpoints.clear();
+    }
+
+    // Память перезаписали не из .mm: старая программа и ее точки останова к ней не относятся
+    pub fn forget_program(&mut self) {
+        self.program = None;
+        self.breakpoints.clear();
+        self.labels = Rc::default();
+    }
 }
 
 pub struct Gui {
@@ -105,13 +168,15 @@ impl Gui {
                                     "Основная память",
                                     CellsTool::new(computer.general_memory.clone(), |c| {
                                         c.registers.r_command_counter
-                                    }),
+                                    })
+                                    .with_source(),
                                 )
                                 .append(
                                     "Память МПУ",
                                     CellsTool::new(computer.mc_memory.clone(), |c| {
                                         c.registers.r_micro_command_counter as u16
-                                    }),
+                                    })
+                                    .with_fields_editor(),
                                 ),
                         )
                         .append(
@@ -208,6 +273,10 @@ impl Gui {
                                                         "../help/cheatsheet.txt"
                                                     )),
                                                 )
+                                                .append(
+                                                    "БЭВМ-NG",
+                                                    HelpTool::new(include_str!("../help/ng.txt")),
+                                                )
                                                 .append(
                                                     "Нотация",
                                                     HelpTool::new(include_str!(
@@ -219,7 +288,18 @@ impl Gui {
                                                     HelpTool::new(include_str!(
                                                         "../help/run_and_stop.txt"
                                                     )),
-                                                ),
+                                                )
+                                                .append(
+                                                    "Язык",
+                                                    HelpTool::new(include_str!(
+                                                        "../help/compiler.txt"
+                                                    )),
+                                                )
+                                                .append("Исходник", SourceTool::new())
+                                                .append("Редактор", EditorTool::new())
+                                                .append("Компилятор", CompilerTool::new())
+                                                .append("Граф переходов", CfgTool::new())
+                                                .append("Метки", SymbolsTool::new()),
                                         ),
                                 ),
                         ),
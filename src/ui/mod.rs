@@ -5,7 +5,10 @@ use std::process::Command;
 pub mod gui;
 
 mod cells;
+mod cfg;
+mod compiler;
 mod controls;
+mod editor;
 mod help;
 mod highlight;
 mod io;
@@ -13,7 +16,9 @@ mod layout;
 mod log;
 mod popup;
 mod registers;
+mod source;
 mod status;
+mod symbols;
 mod tracing;
 mod window;
 
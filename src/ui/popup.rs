@@ -1,5 +1,6 @@
+use crate::parse::diagnostic::{Diagnostic, Severity};
 use crate::ui::gui::GuiState;
-use imgui::Ui;
+use imgui::{StyleColor, Ui};
 
 pub trait Popup {
     fn name(&self) -> String;
@@ -74,3 +75,63 @@ impl Popup for PopupParseError {
         open
     }
 }
+
+pub fn severity_color(severity: Severity) -> [f32; 4] {
+    match severity {
+        Severity::Error => [1.0, 0.3, 0.3, 1.0],
+        Severity::Warning => [1.0, 0.8, 0.2, 1.0],
+    }
+}
+
+// Все сообщения ассемблера разом: строка исходника и ^^^ под ошибочным местом
+pub struct PopupDiagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+impl PopupDiagnostics {
+    pub fn new(diagnostics: Vec<Diagnostic>) -> PopupDiagnostics {
+        PopupDiagnostics { diagnostics }
+    }
+}
+impl Popup for PopupDiagnostics {
+    fn name(&self) -> String {
+        "Сообщения ассемблера".to_string()
+    }
+
+    fn draw(&mut self, ui: &Ui, _state: &mut GuiState) -> bool {
+        let mut open = true;
+        let name = self.name();
+        let popup = ui
+            .modal_popup_config(name)
+            .opened(&mut open)
+            .always_auto_resize(true);
+
+        popup.build(|| {
+            ui.text(format!("Найдено сообщений: {}", self.diagnostics.len()));
+            // Длинный список прокручивается, иначе кнопка закрытия уедет за край экрана
+            let max_height = ui.io().display_size[1] * 0.6;
+            ui.child_window("diagnostics")
+                .size([700.0, max_height])
+                .build(|| {
+                    for diagnostic in &self.diagnostics {
+                        ui.separator();
+                        let color = severity_color(diagnostic.severity);
+                        let token = ui.push_style_color(StyleColor::Text, color);
+                        ui.text(format!(
+                            "Строка {}, символ {}: {}",
+                            diagnostic.location,
+                            diagnostic.columns().start + 1,
+                            diagnostic.severity.title()
+                        ));
+                        token.pop();
+                        ui.text(&diagnostic.message);
+                        ui.text(diagnostic.source.as_ref());
+                        let token = ui.push_style_color(StyleColor::Text, color);
+                        ui.text(diagnostic.caret());
+                        token.pop();
+                    }
+                });
+        });
+
+        open
+    }
+}
@@ -63,6 +63,16 @@ impl Tool for RegistersTool {
                 Register::CommandCounter,
                 "Счетчик команд. Указывает на текущую выполняемую команду.",
             );
+            if !computer.profile.has_microcode() {
+                igTableNextRow(ImGuiTableRowFlags_None as c_int, 0.0);
+                igTableNextColumn();
+                reg_field(
+                    ui,
+                    computer,
+                    Register::StackPointer,
+                    "Указатель стека. Только в БЭВМ-NG. Стек растет в сторону уменьшения адресов.",
+                );
+            }
             igEndTable();
         }
 
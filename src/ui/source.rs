@@ -0,0 +1,147 @@
+use crate::parse::file::Program;
+use crate::parse::lexer::{tokenize, Token};
+use crate::ui::gui::GuiState;
+use crate::ui::window::Tool;
+use imgui::{Io, StyleColor, Ui};
+
+// Загруженный .mm файл: строка с текущей командой подсвечена,
+// слева точки останова, при наведении на %метку видно ее значение
+pub struct SourceTool {
+    file: usize,
+    // Строка, к которой последний раз прокручивали окно
+    followed: Option<(usize, u32)>,
+}
+
+impl SourceTool {
+    pub fn new() -> SourceTool {
+        SourceTool {
+            file: 0,
+            followed: None,
+        }
+    }
+}
+
+impl Tool for SourceTool {
+    fn draw(&mut self, ui: &Ui, _io: &Io, state: &mut GuiState) {
+        let Some(program) = state.program.as_ref() else {
+            ui.text_wrapped(
+                "Загрузите программу из .mm файла в основную память, и она появится здесь.",
+            );
+            return;
+        };
+
+        let counter = state.computer.registers.r_command_counter;
+        // Файл и строка команды, на которую указывает СК
+        let current = program.line_of(counter);
+
+        ui.menu_bar(|| {
+            if program.sources.len() > 1 {
+                if let Some(t) = ui.begin_menu("Файл") {
+                    for (i, source) in program.sources.iter().enumerate() {
+                        let name = source.name.as_deref().unwrap_or("без имени");
+                        if ui.menu_item_config(name).selected(i == self.file).build() {
+                            self.file = i;
+                        }
+                    }
+                    t.end();
+                }
+            }
+        });
+
+        // За текущей командой окно переключается само
+        let follow = current.is_some() && current != self.followed;
+        if follow {
+            self.file = current.unwrap().0;
+        }
+        let Some(source) = program.sources.get(self.file) else {
+            self.file = 0;
+            return;
+        };
+
+        let char_width = ui.calc_text_size("0")[0];
+        for (i, line) in source.lines.iter().enumerate() {
+            let line_num = i as u32 + 1;
+            let address = source.addresses.get(&line_num).copied();
+            let id = ui.push_id_usize(i);
+
+            match address {
+                Some(address) => {
+                    let enabled = state.breakpoints.contains(&address);
+                    let marker = if enabled { "●" } else { " " };
+                    let token = ui.push_style_color(StyleColor::Text, [1.0, 0.2, 0.2, 1.0]);
+                    if ui
+                        .selectable_config(marker)
+                        .size([char_width * 2.0, 0.0])
+                        .build()
+                    {
+                        if enabled {
+                            state.breakpoints.remove(&address);
+                        } else {
+                            state.breakpoints.insert(address);
+                        }
+                    }
+                    token.pop();
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Точка останова: ЭВМ остановится перед этой командой");
+                    }
+                    ui.same_line();
+                    ui.text_disabled(format!("{:0>3X}", address));
+                }
+                None => {
+                    ui.dummy([char_width * 2.0, 0.0]);
+                    ui.same_line();
+                    ui.text_disabled("   ");
+                }
+            }
+            ui.same_line();
+
+            let is_current = current == Some((self.file, line_num));
+            let token =
+                is_current.then(|| ui.push_style_color(StyleColor::Text, [1.0, 0.8, 0.0, 1.0]));
+            ui.text(line);
+            if let Some(token) = token {
+                token.pop();
+            }
+            if ui.is_item_hovered() {
+                let column = ((ui.io().mouse_pos[0] - ui.item_rect_min()[0]) / char_width) as usize;
+                if let Some(hint) = label_hint(program, state, line, column) {
+                    ui.tooltip_text(hint);
+                }
+            }
+            if is_current && follow {
+                ui.set_scroll_here_y();
+                self.followed = current;
+            }
+
+            id.pop();
+        }
+    }
+}
+
+// Значение метки под курсором мыши
+fn label_hint(program: &Program, state: &GuiState, line: &str, column: usize) -> Option<String> {
+    let byte = line
+        .char_indices()
+        .nth(column)
+        .map_or(line.len(), |(i, _)| i);
+    let name = tokenize(line)
+        .into_iter()
+        .find_map(|token| match token.token {
+            Token::Label(name) if (token.start..token.end).contains(&byte) => Some(name),
+            _ => None,
+        })?;
+
+    if let Some(label) = program.labels.iter().find(|label| label.name == name) {
+        let memory = state.computer.general_memory.borrow();
+        let value = memory.data.get(label.pos as usize)?.get();
+        return Some(format!(
+            "%{} = {:0>3X}\nВ ячейке {:0>3X}: {:0>4X}",
+            name, label.pos, label.pos, value
+        ));
+    }
+    program
+        .constants
+        .iter()
+        .find(|(constant, _)| constant == name)
+        .map(|(_, value)| format!("%{} = {:X} ($equ)", name, value))
+}
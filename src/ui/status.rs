@@ -27,6 +27,7 @@ impl Tool for StatusTool {
             );
         }
         let mut cnt = 0;
+        let has_microcode = state.computer.profile.has_microcode();
 
         let mut status_flag = |name: &str, tooltip: &str, location: u8| {
             unsafe {
@@ -62,7 +63,11 @@ impl Tool for StatusTool {
         status_flag("Состояние тумблера", "Работа - 1\nОстанов - 0\nЕсли \"Работа\", ЭВМ продолжает выполнение как не в себя.\nЕсли \"Останов\", ЭВМ остановится когда закончит выполнять команду.", 7);
         status_flag("Исполнение", "0 - По циклам\n1 - По тактам\nТактом здесь считается выполнение одной команды МПУ\nЦиклом здесь считается выполнение одной команды не из МКУ", 11);
 
-        status_flag("Постоянный 0", "Для безусловного перехода МПУ использует сравнение этого бита с 0.\nУстановите здесь единицу чтобы все сломать!", 3);
+        if has_microcode {
+            status_flag("Постоянный 0", "Для безусловного перехода МПУ использует сравнение этого бита с 0.\nУстановите здесь единицу чтобы все сломать!", 3);
+        } else {
+            status_flag("Переполнение (V)", "Только в БЭВМ-NG.\nСообщает, что результат знаковой операции не поместился в 16 бит", 3);
+        }
         status_flag("Программа", "Не работает!\n\nВ методичке написали что-то типа:\nУстанавливается когда ЭВМ выполняет команды не по тактам, а самостоятельно. В этом режиме работают прерывания.\n\nТак как при таком раскладе становится сложно отлаживать прерывания, в этом эмуляторе ЭВМ этот флаг не учитывается.\nЕще в листинге команд МПУ в методичке нет ни одной команды, которая бы обращалась к этому флагу. Потому смысл совсем теряется.", 8);
         status_flag("Ввод-вывод", "Этот бит устанавливается в 1, когда выполняется микрокоманда \"Организация связей с ВУ\"(4100)\nПри этом РД должен быть равен РА\nКогда этот бит установлен начинает работать модуль связи с ВУ.\nВ этот момент считается, что регистр РД хранит в себе команду ВУ\nТип команды будет вычислен исходя из содержимого битов 8-11 регистра РД\nСразу после этого флаг будет сброшен.", 12);
 
@@ -0,0 +1,63 @@
+use crate::ui::gui::GuiState;
+use crate::ui::window::Tool;
+use imgui::{Io, Ui};
+
+// Таблица имен ячеек основной памяти. Клик по строке прокручивает память к ячейке.
+pub struct SymbolsTool {
+    filter: String,
+}
+
+impl SymbolsTool {
+    pub fn new() -> SymbolsTool {
+        SymbolsTool {
+            filter: String::new(),
+        }
+    }
+}
+
+impl Tool for SymbolsTool {
+    fn draw(&mut self, ui: &Ui, _io: &Io, state: &mut GuiState) {
+        ui.input_text("Поиск", &mut self.filter).build();
+
+        // Имена собраны при загрузке программы, память читается только ради значений
+        let memory = state.computer.general_memory.borrow();
+        let filter = self.filter.to_lowercase();
+        let labels = state
+            .labels
+            .names
+            .iter()
+            .filter(|(_, name)| filter.is_empty() || name.to_lowercase().contains(&filter))
+            .map(|(pos, name)| (*pos, name.as_str(), memory.data[*pos as usize].get()))
+            .collect::<Vec<_>>();
+        if labels.is_empty() {
+            ui.text_wrapped("Меток нет. Они появятся после загрузки программы из .mm");
+            return;
+        }
+
+        let width = labels
+            .iter()
+            .map(|(_, name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        ui.text_disabled(format!("Адр {:<width$} Значение", "Метка", width = width));
+        let mut jump = None;
+        for (pos, name, value) in labels {
+            let row = format!(
+                "{:0>3X} {:<width$} {:0>4X} ({})",
+                pos,
+                name,
+                value,
+                value as i16,
+                width = width
+            );
+            if ui.selectable(row) {
+                jump = Some(pos);
+            }
+        }
+        drop(memory);
+
+        if jump.is_some() {
+            state.jump_to = jump;
+        }
+    }
+}
@@ -0,0 +1,34 @@
+// Кодировка КОИ-8Р, в которой БЭВМ традиционно выводит русский текст
+
+const LOWER: &str = "юабцдефгхийклмнопярстужвьызшэщчъ";
+const UPPER: &str = "ЮАБЦДЕФГХИЙКЛМНОПЯРСТУЖВЬЫЗШЭЩЧЪ";
+
+pub fn encode(ch: char) -> Option<u8> {
+    if ch.is_ascii() {
+        return Some(ch as u8);
+    }
+    match ch {
+        'ё' => return Some(0xA3),
+        'Ё' => return Some(0xB3),
+        _ => {}
+    }
+    if let Some(i) = LOWER.chars().position(|c| c == ch) {
+        return Some(0xC0 + i as u8);
+    }
+    UPPER.chars().position(|c| c == ch).map(|i| 0xE0 + i as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::koi8r::encode;
+
+    #[test]
+    fn encode_russian() {
+        assert_eq!(encode('A'), Some(0x41));
+        assert_eq!(encode('а'), Some(0xC1));
+        assert_eq!(encode('П'), Some(0xF0));
+        assert_eq!(encode('ъ'), Some(0xDF));
+        assert_eq!(encode('Ё'), Some(0xB3));
+        assert_eq!(encode('€'), None);
+    }
+}